@@ -5,6 +5,7 @@ fn main()->Result<(),Box<dyn Error>>{
     tonic_build::compile_protos("proto/user/user.proto")?;
     tonic_build::compile_protos("proto/app/app.proto")?;
     tonic_build::compile_protos("proto/client/client.proto")?;
+    tonic_build::compile_protos("proto/google/rpc/rpc.proto")?;
 
     Ok(())
 }
\ No newline at end of file
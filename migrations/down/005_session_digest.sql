@@ -0,0 +1,6 @@
+-- the sessions dropped on the way up are not restored, and those opened since then are dropped as well, since their
+-- digests are no tokens at all
+DELETE FROM Sessions;
+DELETE FROM Tombstones;
+DELETE FROM AuthCodes;
+DELETE FROM RefreshTokens;
@@ -0,0 +1,8 @@
+ALTER TABLE Tickets
+    DROP COLUMN app_id,
+    DROP COLUMN scope;
+
+DROP TABLE Consents;
+
+ALTER TABLE Apps
+    DROP COLUMN scope;
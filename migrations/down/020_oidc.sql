@@ -0,0 +1,6 @@
+ALTER TABLE AuthCodes
+    DROP COLUMN scope,
+    DROP COLUMN nonce;
+
+ALTER TABLE RefreshTokens
+    DROP COLUMN scope;
@@ -0,0 +1,2 @@
+DROP TABLE AuthCodes;
+DROP TABLE Redirects;
@@ -0,0 +1,14 @@
+-- clients may hold a single secret only, so just the oldest one of each client survives
+DELETE FROM Secrets AS newer
+    USING Secrets AS older
+    WHERE newer.client_id = older.client_id
+    AND newer.id > older.id;
+
+ALTER TABLE Secrets
+    ADD CONSTRAINT secrets_client_id_key UNIQUE (client_id);
+
+ALTER TABLE Secrets
+    DROP CONSTRAINT secrets_client_id_fkey,
+    ADD CONSTRAINT secrets_client_id_fkey
+        FOREIGN KEY (client_id)
+        REFERENCES Clients(id);
@@ -0,0 +1,3 @@
+-- fails as long as any label does not fit the former length; the directories dropped on the way up are not restored
+ALTER TABLE Apps
+    ALTER COLUMN label TYPE VARCHAR(16);
@@ -0,0 +1,2 @@
+ALTER TABLE Apps
+    DROP COLUMN force_reauth;
@@ -0,0 +1,9 @@
+ALTER TABLE Sessions
+    DROP COLUMN peer_addr,
+    DROP COLUMN user_agent,
+    DROP COLUMN forwarded_for;
+
+ALTER TABLE Tombstones
+    DROP COLUMN peer_addr,
+    DROP COLUMN user_agent,
+    DROP COLUMN forwarded_for;
@@ -0,0 +1,17 @@
+ALTER TABLE Tombstones
+    DROP COLUMN device;
+
+-- users may keep a single session only, so just one of each user's sessions survives
+DELETE FROM Sessions AS older
+    USING Sessions AS newer
+    WHERE older.user_id = newer.user_id
+    AND older.cookie < newer.cookie;
+
+ALTER TABLE Sessions
+    DROP CONSTRAINT sessions_user_id_device_key;
+
+ALTER TABLE Sessions
+    ADD UNIQUE (user_id);
+
+ALTER TABLE Sessions
+    DROP COLUMN device;
@@ -0,0 +1,2 @@
+DROP TABLE Directories;
+DROP TABLE Sessions;
@@ -0,0 +1,3 @@
+-- fails as long as any password hash does not fit the former length
+ALTER TABLE Users
+    ALTER COLUMN pwd TYPE VARCHAR(64);
@@ -0,0 +1,2 @@
+ALTER TABLE Users
+    ALTER COLUMN pwd TYPE VARCHAR(256);
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE Tickets (
+    id VARCHAR(64) PRIMARY KEY,
+    client_id INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    secret VARCHAR(256) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    deadline TIMESTAMP NOT NULL,
+    consumed_at TIMESTAMP,
+
+    FOREIGN KEY (client_id)
+        REFERENCES Clients(id)
+        ON DELETE CASCADE
+)
\ No newline at end of file
@@ -0,0 +1,10 @@
+CREATE TABLE Outbox (
+    id SERIAL PRIMARY KEY,
+    recipient VARCHAR(64) NOT NULL,
+    subject VARCHAR(256) NOT NULL,
+    body TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_error TEXT
+)
\ No newline at end of file
@@ -0,0 +1,29 @@
+CREATE TABLE Sessions (
+    cookie VARCHAR(64) PRIMARY KEY,
+    user_id INTEGER NOT NULL UNIQUE,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    touch_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (user_id)
+        REFERENCES Users(id)
+        ON DELETE CASCADE
+);
+
+CREATE TABLE Directories (
+    cookie VARCHAR(64) NOT NULL,
+    token VARCHAR(64) NOT NULL,
+    app_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (cookie, token),
+    UNIQUE (cookie, app_id),
+
+    FOREIGN KEY (cookie)
+        REFERENCES Sessions(cookie)
+        ON DELETE CASCADE,
+
+    FOREIGN KEY (app_id)
+        REFERENCES Apps(id)
+        ON DELETE CASCADE
+)
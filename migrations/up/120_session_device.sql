@@ -0,0 +1,11 @@
+ALTER TABLE Sessions
+    ADD COLUMN device VARCHAR(64) NOT NULL DEFAULT '';
+
+ALTER TABLE Sessions
+    DROP CONSTRAINT sessions_user_id_key;
+
+ALTER TABLE Sessions
+    ADD UNIQUE (user_id, device);
+
+ALTER TABLE Tombstones
+    ADD COLUMN device VARCHAR(64) NOT NULL DEFAULT '';
@@ -0,0 +1,9 @@
+ALTER TABLE Sessions
+    ADD COLUMN peer_addr VARCHAR(64) NOT NULL DEFAULT '',
+    ADD COLUMN user_agent VARCHAR(256) NOT NULL DEFAULT '',
+    ADD COLUMN forwarded_for VARCHAR(256) NOT NULL DEFAULT '';
+
+ALTER TABLE Tombstones
+    ADD COLUMN peer_addr VARCHAR(64) NOT NULL DEFAULT '',
+    ADD COLUMN user_agent VARCHAR(256) NOT NULL DEFAULT '',
+    ADD COLUMN forwarded_for VARCHAR(256) NOT NULL DEFAULT '';
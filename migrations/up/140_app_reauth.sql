@@ -0,0 +1,2 @@
+ALTER TABLE Apps
+    ADD COLUMN force_reauth BOOLEAN NOT NULL DEFAULT FALSE;
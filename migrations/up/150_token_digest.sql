@@ -0,0 +1,6 @@
+ALTER TABLE Apps
+    ALTER COLUMN label TYPE VARCHAR(64);
+
+-- directories used to keep their tokens as plain text, while now only their digests are; dropping them all logs
+-- every session out of its apps, so users are asked to sign in to each app again
+DELETE FROM Directories;
@@ -0,0 +1,10 @@
+-- clients may hold as many secrets as names, such as users enrolling a second factor
+ALTER TABLE Secrets
+    DROP CONSTRAINT secrets_client_id_key;
+
+ALTER TABLE Secrets
+    DROP CONSTRAINT secrets_client_id_fkey,
+    ADD CONSTRAINT secrets_client_id_fkey
+        FOREIGN KEY (client_id)
+        REFERENCES Clients(id)
+        ON DELETE CASCADE;
@@ -0,0 +1,26 @@
+CREATE TABLE Redirects (
+    app_id INTEGER NOT NULL,
+    uri VARCHAR(256) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (app_id, uri),
+    FOREIGN KEY (app_id)
+        REFERENCES Apps(id)
+        ON DELETE CASCADE
+);
+
+-- authorization codes are kept as digests, along with the session they have been issued for
+CREATE TABLE AuthCodes (
+    code VARCHAR(64) PRIMARY KEY,
+    app_id INTEGER NOT NULL,
+    cookie VARCHAR(64) NOT NULL,
+    redirect_uri VARCHAR(256) NOT NULL,
+    code_challenge VARCHAR(128) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    deadline TIMESTAMP NOT NULL,
+    consumed_at TIMESTAMP,
+
+    FOREIGN KEY (app_id)
+        REFERENCES Apps(id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,23 @@
+-- refresh tokens are kept as digests; the rotated ones are kept as well until the family expires, so any replay can be told
+CREATE TABLE RefreshTokens (
+    token VARCHAR(64) PRIMARY KEY,
+    family VARCHAR(64) NOT NULL,
+    client_id INTEGER NOT NULL,
+    app_id INTEGER NOT NULL,
+    cookie VARCHAR(64) NOT NULL,
+    device VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    deadline TIMESTAMP NOT NULL,
+    rotated_at TIMESTAMP,
+
+    FOREIGN KEY (client_id)
+        REFERENCES Clients(id)
+        ON DELETE CASCADE,
+
+    FOREIGN KEY (app_id)
+        REFERENCES Apps(id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX refresh_tokens_family_idx ON RefreshTokens (family);
+CREATE INDEX refresh_tokens_cookie_idx ON RefreshTokens (cookie);
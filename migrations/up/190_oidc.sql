@@ -0,0 +1,7 @@
+-- the scopes are kept as a space separated list, as told by the authorization request
+ALTER TABLE AuthCodes
+    ADD COLUMN scope VARCHAR(256) NOT NULL DEFAULT '',
+    ADD COLUMN nonce VARCHAR(256) NOT NULL DEFAULT '';
+
+ALTER TABLE RefreshTokens
+    ADD COLUMN scope VARCHAR(256) NOT NULL DEFAULT '';
@@ -0,0 +1,26 @@
+-- the scopes each app may ask its users for, kept as a space separated list
+ALTER TABLE Apps
+    ADD COLUMN scope VARCHAR(256) NOT NULL DEFAULT '';
+
+-- the scopes each user has granted to each app, so the user is not asked for them again
+CREATE TABLE Consents (
+    client_id INTEGER NOT NULL,
+    app_id INTEGER NOT NULL,
+    scope VARCHAR(256) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (client_id, app_id),
+    FOREIGN KEY (client_id)
+        REFERENCES Clients(id)
+        ON DELETE CASCADE,
+
+    FOREIGN KEY (app_id)
+        REFERENCES Apps(id)
+        ON DELETE CASCADE
+);
+
+-- consent tickets are bound to the app and the scopes the user is asked to grant
+ALTER TABLE Tickets
+    ADD COLUMN app_id INTEGER REFERENCES Apps(id) ON DELETE CASCADE,
+    ADD COLUMN scope VARCHAR(256) NOT NULL DEFAULT '';
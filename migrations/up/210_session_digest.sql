@@ -0,0 +1,6 @@
+-- sessions used to be kept by their tokens as plain text, while now only their digests are; dropping them all logs every
+-- user out, and neither the authorization codes nor the refresh tokens issued for them may outlive them
+DELETE FROM Sessions;
+DELETE FROM Tombstones;
+DELETE FROM AuthCodes;
+DELETE FROM RefreshTokens;
@@ -0,0 +1,232 @@
+use std::env;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use rand::RngCore;
+use crate::token::Token;
+use crate::default;
+use crate::error;
+
+// a cookie looks like v1.<session>.<directory>.<key id>.<signature>, all of its parts being url-safe
+const VERSION: &str = "v1";
+const SEPARATOR: char = '.';
+const PARTS: usize = 5;
+const MAX_COOKIE_LEN: usize = 512;
+const EPHEMERAL_KEY_ID: &str = "ephemeral";
+const EPHEMERAL_KEY_LEN: usize = 32; // in bytes
+
+const ERR_COOKIE_FORMAT: &str = "The provided cookie does not match with a real one";
+const ERR_COOKIE_VERSION: &str = "The provided cookie version is not supported";
+const ERR_COOKIE_SIGNATURE: &str = "The provided cookie is not valid";
+const ERR_COOKIE_KEYS: &str = "Cookie keys must be formatted as a comma separated list of id:base64url";
+
+// Keyring holds the keys cookies get signed with: the first one signs all the new cookies, while the others
+// are kept so the cookies signed before a rotation remain valid until their sessions expire
+struct Keyring {
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+lazy_static! {
+    static ref KEYRING: Keyring = {
+        match env::var(default::ENV_COOKIE_KEYS) {
+            Ok(value) => Keyring::parse(&value).expect(ERR_COOKIE_KEYS),
+            Err(_) => {
+                // no cookie will survive a restart, nor be accepted by any other replica
+                println!("No cookie keys have been set, an ephemeral one is used instead");
+                Keyring::ephemeral()
+            },
+        }
+    };
+}
+
+impl Keyring {
+    fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        for item in value.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()) {
+            let (kid, key) = match item.split_once(':') {
+                Some((kid, key)) if !kid.is_empty() && !kid.contains(SEPARATOR) => (kid, key),
+                _ => return Err(ERR_COOKIE_KEYS.into()),
+            };
+
+            let key = base64::decode_config(key, base64::URL_SAFE_NO_PAD)?;
+            if key.is_empty() {
+                return Err(ERR_COOKIE_KEYS.into());
+            }
+
+            keys.push((kid.to_string(), key));
+        }
+
+        if keys.is_empty() {
+            return Err(ERR_COOKIE_KEYS.into());
+        }
+
+        Ok(Keyring{
+            keys,
+        })
+    }
+
+    fn ephemeral() -> Self {
+        let mut key = vec![0; EPHEMERAL_KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        Keyring{
+            keys: vec![(EPHEMERAL_KEY_ID.to_string(), key)],
+        }
+    }
+
+    fn current(&self) -> &(String, Vec<u8>) {
+        &self.keys[0]
+    }
+
+    fn get(&self, kid: &str) -> Option<&[u8]> {
+        self.keys.iter()
+            .find(|(id, _)| id == kid)
+            .map(|(_, key)| key.as_slice())
+    }
+}
+
+fn sign(key: &[u8], payload: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(payload.as_bytes())?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn encode(data: &str) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(data: &str) -> Result<String, Box<dyn Error>> {
+    let data = base64::decode_config(data, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| error::Error::invalid("cookie", ERR_COOKIE_FORMAT))?;
+    String::from_utf8(data)
+        .map_err(|_| error::Error::invalid("cookie", ERR_COOKIE_FORMAT).into())
+}
+
+// build_cookie returns the cookie for the session's directory, signed by the current key
+pub fn build_cookie(sess: &Token, dir: &Token) -> Result<String, Box<dyn Error>> {
+    let (kid, key) = KEYRING.current();
+    let payload = format!("{}{sep}{}{sep}{}{sep}{}", VERSION, encode(sess.as_str()), encode(dir.as_str()), kid, sep = SEPARATOR);
+    let signature = sign(key, &payload)?;
+    Ok(format!("{}{}{}", payload, SEPARATOR, base64::encode_config(signature, base64::URL_SAFE_NO_PAD)))
+}
+
+// session_token returns the token of the user's session on the device, as derived from the moment it was opened by the
+// current key; so the very same token can be told again while the session remains alive, although only its digest is kept
+pub fn session_token(user: i32, device: &str, created_at: SystemTime) -> Result<Token, Box<dyn Error>> {
+    let (_, key) = KEYRING.current();
+    let micros = created_at.duration_since(UNIX_EPOCH)?.as_micros();
+    let payload = format!("sess{sep}{}{sep}{}{sep}{}", user, micros, device, sep = SEPARATOR);
+    let mac = sign(key, &payload)?;
+    Ok(Token::restore(&base64::encode_config(&mac[..default::TOKEN_LEN], base64::URL_SAFE_NO_PAD), created_at))
+}
+
+// directory_token returns the token of the session's directory in the app, as derived from the moment it was opened by the
+// current key; so the very same token can be told again while the directory remains open, although only its digest is kept
+pub fn directory_token(sess: &Token, app: i32, opened_at: SystemTime) -> Result<Token, Box<dyn Error>> {
+    let (_, key) = KEYRING.current();
+    let opened_at = opened_at.duration_since(UNIX_EPOCH)?.as_micros();
+    let payload = format!("dir{sep}{}{sep}{}{sep}{}", sess.as_str(), app, opened_at, sep = SEPARATOR);
+    let mac = sign(key, &payload)?;
+    Ok(Token::from_string(&base64::encode_config(&mac[..default::TOKEN_LEN], base64::URL_SAFE_NO_PAD)))
+}
+
+// fingerprint identifies the cookie in logs, with no way to get the cookie back from it
+pub fn fingerprint(cookie: &str) -> String {
+    Token::from_string(cookie).fingerprint()
+}
+
+// split_cookie returns the session and directory tokens of the cookie, once its signature has been verified
+pub fn split_cookie(cookie: &str) -> Result<(Token, Token), Box<dyn Error>> {
+    if cookie.len() > MAX_COOKIE_LEN {
+        return Err(error::Error::invalid("cookie", ERR_COOKIE_FORMAT).into());
+    }
+
+    let parts: Vec<&str> = cookie.split(SEPARATOR).collect();
+    if parts.len() != PARTS {
+        return Err(error::Error::invalid("cookie", ERR_COOKIE_FORMAT).into());
+    }
+
+    if parts[0] != VERSION {
+        return Err(error::Error::invalid("cookie", ERR_COOKIE_VERSION).into());
+    }
+
+    // no lookup is performed before making sure the cookie has not been tampered with
+    let key = KEYRING.get(parts[3])
+        .ok_or_else(|| error::Error::Unauthenticated(ERR_COOKIE_SIGNATURE.into()))?;
+    let signature = base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)
+        .map_err(|_| error::Error::invalid("cookie", ERR_COOKIE_FORMAT))?;
+    let payload = &cookie[..cookie.len() - parts[4].len() - 1];
+    let expected = sign(key, payload)?;
+    if signature.len() != expected.len() || !memcmp::eq(&signature, &expected) {
+        return Err(error::Error::Unauthenticated(ERR_COOKIE_SIGNATURE.into()).into());
+    }
+
+    let sess_token = Token::from_string(&decode(parts[1])?);
+    let dir_token = Token::from_string(&decode(parts[2])?);
+    Ok((sess_token, dir_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookie_roundtrip() {
+        let sess = Token::new(default::TOKEN_LEN);
+        let dir = Token::new(default::TOKEN_LEN);
+        let cookie = build_cookie(&sess, &dir).unwrap();
+        assert!(cookie.starts_with(VERSION));
+        assert!(cookie.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == SEPARATOR));
+
+        let (got_sess, got_dir) = split_cookie(&cookie).unwrap();
+        assert_eq!(got_sess.as_str(), sess.as_str());
+        assert_eq!(got_dir.as_str(), dir.as_str());
+    }
+
+    #[test]
+    fn cookie_tampered() {
+        let sess = Token::new(default::TOKEN_LEN);
+        let dir = Token::new(default::TOKEN_LEN);
+        let cookie = build_cookie(&sess, &dir).unwrap();
+
+        // swapping the session for another one breaks the signature
+        let other = encode(Token::new(default::TOKEN_LEN).as_str());
+        let parts: Vec<&str> = cookie.split(SEPARATOR).collect();
+        let tampered = [parts[0], &other, parts[2], parts[3], parts[4]].join(".");
+        assert!(split_cookie(&tampered).is_err());
+
+        // unknown keys, versions and formats are all rejected
+        let tampered = [parts[0], parts[1], parts[2], "unknown", parts[4]].join(".");
+        assert!(split_cookie(&tampered).is_err());
+        let tampered = ["v0", parts[1], parts[2], parts[3], parts[4]].join(".");
+        assert!(split_cookie(&tampered).is_err());
+        assert!(split_cookie(&format!("{}{}", sess, dir)).is_err());
+    }
+
+    #[test]
+    fn cookie_directory_token() {
+        let sess = Token::new(default::TOKEN_LEN);
+        let now = SystemTime::now();
+        let token = directory_token(&sess, 1, now).unwrap();
+        assert_eq!(token.as_str().len(), Token::new(default::TOKEN_LEN).as_str().len());
+        assert!(token == directory_token(&sess, 1, now).unwrap());
+
+        // no other session, app nor opening gets the same token
+        assert!(token != directory_token(&Token::new(default::TOKEN_LEN), 1, now).unwrap());
+        assert!(token != directory_token(&sess, 2, now).unwrap());
+        assert!(token != directory_token(&sess, 1, now + std::time::Duration::from_secs(1)).unwrap());
+    }
+
+    #[test]
+    fn keyring_rotation() {
+        let old = Keyring::parse("old:c2VjcmV0").unwrap();
+        let new = Keyring::parse("new:YW5vdGhlcg,old:c2VjcmV0").unwrap();
+        assert_eq!(new.current().0, "new");
+        assert_eq!(new.get("old"), old.get("old"));
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("no_key").is_err());
+    }
+}
@@ -1,24 +1,100 @@
 pub const SERVER_IP: &str = "127.0.0.1";
 
-pub const TOKEN_LEN: usize = 8;
-pub const TOKEN_TIMEOUT: u64 = 86400; // 3600s * 24h
+pub const TOKEN_LEN: usize = 16; // in bytes, 128 bits of entropy
+pub const SESSION_LIFETIME: u64 = 86400; // 3600s * 24h
+pub const SESSION_IDLE_TIMEOUT: u64 = 7200; // 3600s * 2h
+pub const SESSION_MAX_PER_USER: usize = 5; // oldest ones get evicted beyond it
+pub const SESSION_TOMBSTONE: u64 = 600; // in seconds, how long a dead session is remembered
+pub const TICKET_TIMEOUT: u64 = 3600; // in seconds
+pub const PENDING_POLICY: &str = "allow"; // allow, grace or reject
+pub const PENDING_GRACE: u64 = 259200; // 3600s * 72h
+pub const SESSION_STORE: &str = "memory"; // memory or postgres
+pub const SWEEP_INTERVAL: u64 = 60; // in seconds
+pub const LOGIN_MAX_ATTEMPTS: u32 = 5; // failed attempts before locking out
+pub const LOGIN_BACKOFF: u64 = 1; // in seconds, doubled on each failed attempt
+pub const LOGIN_LOCKOUT: u64 = 900; // in seconds
+pub const NONCE_MAX_REQUESTS: u32 = 10; // nonces each identity or address may ask for within a window
+pub const NONCE_WINDOW: u64 = 900; // in seconds, with no nonce requested, before the count starts over
+pub const CHALLENGE_TIMEOUT: u64 = 300; // in seconds, to provide the second factor
+pub const TOTP_ENROLL_TIMEOUT: u64 = 600; // in seconds, to confirm the enrollment
+pub const TOTP_ISSUER: &str = "alvidir";
+pub const RECOVERY_CODES: usize = 10;
+pub const RECOVERY_CODE_LEN: usize = 8; // in bytes
+pub const AUTH_CODE_TIMEOUT: u64 = 60; // in seconds, to exchange the authorization code
+pub const ACCESS_TOKEN_LIFETIME: u64 = 900; // in seconds, never beyond the session's deadline
+pub const ISSUER: &str = "https://oauth.alvidir.com"; // whom the access tokens are told to be issued by
+pub const REFRESH_TOKEN_LIFETIME: u64 = 2592000; // 3600s * 24h * 30d, since the family was started
+pub const OAUTH_COOKIE: &str = "session"; // the cookie the authorization endpoint expects the user's one in
+pub const MAX_FORM_LEN: u64 = 4096; // in bytes, the longest body the token endpoint reads
 
 pub const CONNECTION_TIMEOUT: u64 = 100; // in seconds
 pub const CONNECTION_SLEEP: u64 = 1; // in seconds
 
 pub const RSA_NAME: &str = "default_rsa.pem";
+pub const TOTP_NAME: &str = "totp";
+pub const TOTP_PENDING_NAME: &str = "totp_pending";
+pub const RECOVERY_NAME: &str = "recovery_codes";
+pub const KEY_PREFIX: &str = "key."; // the name of users' public keys starts with it
+
+pub const MAILER_DIR: &str = "/tmp/oauth/mail";
+pub const MAIL_FROM: &str = "oauth@alvidir.com";
+pub const MAIL_MAX_ATTEMPTS: i32 = 10;
+pub const MAIL_RETRY_DELAY: u64 = 30; // in seconds, doubled on each failed attempt
+pub const MAIL_LEASE: u64 = 60; // in seconds
+
+pub const PWD_MEMORY_COST: u32 = 19456; // in KiB
+pub const PWD_TIME_COST: u32 = 2; // number of iterations
+pub const PWD_PARALLELISM: u32 = 1; // number of lanes
 
 pub const ENV_SERVICE_PORT: &str = "SERVICE_PORT";
+pub const ENV_HTTP_PORT: &str = "HTTP_PORT";
 pub const ENV_POSTGRES_DSN: &str = "DATABASE_URL";
 pub const ENV_MONGO_DSN: &str = "MONGO_DSN";
 pub const ENV_MONGO_DB: &str = "MONGO_DB";
 pub const ENV_MONGO_COLL: &str = "MONGO_COLLECTION";
+pub const ENV_PENDING_POLICY: &str = "PENDING_POLICY";
+pub const ENV_PENDING_GRACE: &str = "PENDING_GRACE";
+pub const ENV_SESSION_STORE: &str = "SESSION_STORE";
+pub const ENV_SESSION_LIFETIME: &str = "SESSION_LIFETIME";
+pub const ENV_SESSION_IDLE_TIMEOUT: &str = "SESSION_IDLE_TIMEOUT";
+pub const ENV_SESSION_MAX_PER_USER: &str = "SESSION_MAX_PER_USER";
+pub const ENV_MAILER_BACKEND: &str = "MAILER_BACKEND";
+pub const ENV_MAILER_DIR: &str = "MAILER_DIR";
+pub const ENV_MAIL_FROM: &str = "MAIL_FROM";
+pub const ENV_SMTP_HOST: &str = "SMTP_HOST";
+pub const ENV_SMTP_USERNAME: &str = "SMTP_USERNAME";
+pub const ENV_SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+pub const ENV_PWD_MEMORY_COST: &str = "PWD_MEMORY_COST";
+pub const ENV_PWD_TIME_COST: &str = "PWD_TIME_COST";
+pub const ENV_PWD_PARALLELISM: &str = "PWD_PARALLELISM";
+pub const ENV_COOKIE_KEYS: &str = "COOKIE_KEYS";
+pub const ENV_LOGIN_MAX_ATTEMPTS: &str = "LOGIN_MAX_ATTEMPTS";
+pub const ENV_LOGIN_BACKOFF: &str = "LOGIN_BACKOFF";
+pub const ENV_LOGIN_LOCKOUT: &str = "LOGIN_LOCKOUT";
+pub const ENV_NONCE_MAX_REQUESTS: &str = "NONCE_MAX_REQUESTS";
+pub const ENV_NONCE_WINDOW: &str = "NONCE_WINDOW";
+pub const ENV_TOTP_KEY: &str = "TOTP_KEY";
+pub const ENV_TOTP_ISSUER: &str = "TOTP_ISSUER";
+pub const ENV_REFRESH_TOKEN_LIFETIME: &str = "REFRESH_TOKEN_LIFETIME";
+pub const ENV_ACCESS_TOKEN_LIFETIME: &str = "ACCESS_TOKEN_LIFETIME";
+pub const ENV_ISSUER: &str = "ISSUER";
+pub const ENV_OAUTH_APP: &str = "OAUTH_APP";
+pub const ENV_JWT_KEYS: &str = "JWT_KEYS";
+pub const ENV_TRUSTED_PROXIES: &str = "TRUSTED_PROXIES";
 
 #[cfg(test)]
 pub mod tests {
+    use crate::models::session::Metadata;
+
     pub static DUMMY_NAME: &str = "dummy";
     pub static DUMMY_EMAIL: &str = "dummy@testing.com";
     pub static DUMMY_PWD: &str = "0C4fe7eBbfDbcCBE";
+    pub static DUMMY_DEVICE: &str = "dummy_device";
+    pub static DUMMY_META: Metadata = Metadata{
+        peer_addr: String::new(),
+        user_agent: String::new(),
+        forwarded_for: String::new(),
+    };
     pub static DUMMY_URL: &str = "dummy.com";
     pub static DUMMY_DESCR: &str = "this is a dummy application";
 
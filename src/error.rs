@@ -0,0 +1,296 @@
+use std::fmt;
+use std::error;
+use std::time::Duration;
+use prost::Message;
+use tonic::{Status, Code};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use diesel::result::{Error as DieselError, DatabaseErrorKind};
+use crate::proto::rpc_proto;
+use crate::oauth;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/google.rpc.";
+const BAD_REQUEST_TYPE: &str = "BadRequest";
+const RETRY_INFO_TYPE: &str = "RetryInfo";
+const ERROR_INFO_TYPE: &str = "ErrorInfo";
+const OAUTH_DOMAIN: &str = "oauth";
+const RETRY_AFTER_HEADER: &str = "retry-after";
+const ERR_NOT_FOUND: &str = "The requested resource does not exist";
+const ERR_ALREADY_EXISTS: &str = "The resource already exists";
+const ERR_INVALID_ARGUMENT: &str = "The request has invalid arguments";
+const ERR_UNAVAILABLE: &str = "The service is temporarily unavailable";
+const ERR_INTERNAL: &str = "Internal error";
+
+// a field violation tells which field of the request is wrong and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub field: String,
+    pub description: String,
+}
+
+// Error is the set of failures any transaction may end with, each of them mapped to a gRPC code
+#[derive(Debug)]
+pub enum Error {
+    NotFound(String),
+    AlreadyExists(String),
+    InvalidArgument(Vec<Violation>),
+    Unauthenticated(String),
+    PermissionDenied(String),
+    DeadlineExceeded(String),
+    ResourceExhausted(String, Duration), // the client may try again once the duration is over
+    OAuth(&'static str, String), // an error code as defined by RFC 6749, along with its description
+    Unavailable(String),
+    Internal(String),
+}
+
+impl Error {
+    pub fn invalid(field: &str, description: &str) -> Self {
+        Error::InvalidArgument(vec![Violation{
+            field: field.to_string(),
+            description: description.to_string(),
+        }])
+    }
+
+    // classify turns any boxed error into one of the known kinds, foreign ones included
+    pub fn classify(err: Box<dyn error::Error>) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return *err,
+            Err(err) => err,
+        };
+
+        if let Some(derr) = err.downcast_ref::<DieselError>() {
+            return match derr {
+                DieselError::NotFound => Error::NotFound(ERR_NOT_FOUND.into()),
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => Error::AlreadyExists(ERR_ALREADY_EXISTS.into()),
+                DieselError::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _) => Error::Unavailable(derr.to_string()),
+                _ => Error::Internal(derr.to_string()),
+            };
+        }
+
+        if err.is::<diesel::r2d2::PoolError>() || err.is::<mongodb::error::Error>() {
+            return Error::Unavailable(err.to_string());
+        }
+
+        Error::Internal(err.to_string())
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Error::NotFound(_) => Code::NotFound,
+            Error::AlreadyExists(_) => Code::AlreadyExists,
+            Error::InvalidArgument(_) => Code::InvalidArgument,
+            Error::Unauthenticated(_) => Code::Unauthenticated,
+            Error::PermissionDenied(_) => Code::PermissionDenied,
+            Error::DeadlineExceeded(_) => Code::DeadlineExceeded,
+            Error::ResourceExhausted(..) => Code::ResourceExhausted,
+            Error::OAuth(oauth::INVALID_CLIENT, _)
+            | Error::OAuth(oauth::INVALID_TOKEN, _) => Code::Unauthenticated,
+            Error::OAuth(oauth::ACCESS_DENIED, _)
+            | Error::OAuth(oauth::INSUFFICIENT_SCOPE, _) => Code::PermissionDenied,
+            Error::OAuth(oauth::SERVER_ERROR, _) => Code::Internal,
+            Error::OAuth(oauth::TEMPORARILY_UNAVAILABLE, _) => Code::Unavailable,
+            Error::OAuth(..) => Code::InvalidArgument,
+            Error::Unavailable(_) => Code::Unavailable,
+            Error::Internal(_) => Code::Internal,
+        }
+    }
+
+    // the message sent to the client never contains the details of an unavailable or internal error
+    pub fn public_message(&self) -> String {
+        match self {
+            Error::NotFound(msg)
+            | Error::AlreadyExists(msg)
+            | Error::Unauthenticated(msg)
+            | Error::PermissionDenied(msg)
+            | Error::DeadlineExceeded(msg)
+            | Error::ResourceExhausted(msg, _)
+            | Error::OAuth(_, msg) => msg.to_string(),
+            Error::InvalidArgument(violations) if violations.len() == 1 => violations[0].description.to_string(),
+            Error::InvalidArgument(_) => ERR_INVALID_ARGUMENT.to_string(),
+            Error::Unavailable(_) => ERR_UNAVAILABLE.to_string(),
+            Error::Internal(_) => ERR_INTERNAL.to_string(),
+        }
+    }
+
+    fn details(&self) -> Vec<prost_types::Any> {
+        let mut details = Vec::new();
+        if let Error::InvalidArgument(violations) = self {
+            let bad_request = rpc_proto::BadRequest{
+                field_violations: violations.iter()
+                    .map(|violation| rpc_proto::bad_request::FieldViolation{
+                        field: violation.field.clone(),
+                        description: violation.description.clone(),
+                    })
+                    .collect(),
+            };
+
+            details.push(pack(BAD_REQUEST_TYPE, &bad_request));
+        }
+
+        if let Error::ResourceExhausted(_, retry_after) = self {
+            let retry_info = rpc_proto::RetryInfo{
+                retry_delay: Some(prost_types::Duration{
+                    seconds: retry_after.as_secs() as i64,
+                    nanos: retry_after.subsec_nanos() as i32,
+                }),
+            };
+
+            details.push(pack(RETRY_INFO_TYPE, &retry_info));
+        }
+
+        if let Error::OAuth(code, _) = self {
+            let error_info = rpc_proto::ErrorInfo{
+                reason: code.to_uppercase(),
+                domain: OAUTH_DOMAIN.to_string(),
+                metadata: Default::default(),
+            };
+
+            details.push(pack(ERROR_INFO_TYPE, &error_info));
+        }
+
+        details
+    }
+
+    // metadata mirrors the details that plain HTTP clients are used to find in headers
+    fn metadata(&self) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        if let Error::ResourceExhausted(_, retry_after) = self {
+            // whole seconds, rounded up so no client retries too early
+            let seconds = retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64;
+            if let Ok(value) = MetadataValue::from_str(&seconds.to_string()) {
+                metadata.insert(RETRY_AFTER_HEADER, value);
+            }
+        }
+
+        metadata
+    }
+
+    pub fn to_status(&self) -> Status {
+        let code = self.code();
+        let message = self.public_message();
+        let details = self.details();
+        let metadata = self.metadata();
+        if details.is_empty() {
+            return Status::with_metadata(code, message, metadata);
+        }
+
+        let status = rpc_proto::Status{
+            code: code as i32,
+            message: message.clone(),
+            details,
+        };
+
+        let mut buf = Vec::new();
+        if status.encode(&mut buf).is_err() {
+            return Status::with_metadata(code, message, metadata);
+        }
+
+        Status::with_details_and_metadata(code, message, buf.into(), metadata)
+    }
+}
+
+fn pack(type_name: &str, msg: &impl Message) -> prost_types::Any {
+    let mut value = Vec::new();
+    // encoding into a vector cannot run out of capacity
+    let _ = msg.encode(&mut value);
+    prost_types::Any{
+        type_url: format!("{}{}", TYPE_URL_PREFIX, type_name),
+        value,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(violations) => {
+                let fields: Vec<String> = violations.iter()
+                    .map(|violation| format!("{}: {}", violation.field, violation.description))
+                    .collect();
+                write!(f, "{:?}: {}", self.code(), fields.join(", "))
+            },
+
+            Error::NotFound(msg)
+            | Error::AlreadyExists(msg)
+            | Error::Unauthenticated(msg)
+            | Error::PermissionDenied(msg)
+            | Error::DeadlineExceeded(msg)
+            | Error::Unavailable(msg)
+            | Error::Internal(msg) => write!(f, "{:?}: {}", self.code(), msg),
+
+            Error::ResourceExhausted(msg, retry_after) => write!(f, "{:?}: {}, retry after {:?}", self.code(), msg, retry_after),
+            Error::OAuth(code, msg) => write!(f, "{:?}: {}: {}", self.code(), code, msg),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::error;
+    use prost::Message;
+    use tonic::Code;
+    use crate::proto::rpc_proto;
+    use super::Error;
+
+    #[test]
+    fn classify_foreign_errors() {
+        let err: Box<dyn error::Error> = Box::new(diesel::NotFound);
+        assert_eq!(Error::classify(err).code(), Code::NotFound);
+
+        let err: Box<dyn error::Error> = "something went wrong".into();
+        assert_eq!(Error::classify(err).code(), Code::Internal);
+
+        let err: Box<dyn error::Error> = Error::Unauthenticated("".into()).into();
+        assert_eq!(Error::classify(err).code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn internal_details_are_redacted() {
+        let status = Error::Internal("connection refused at 10.0.0.1".into()).to_status();
+        assert_eq!(status.code(), Code::Internal);
+        assert!(!status.message().contains("10.0.0.1"));
+    }
+
+    #[test]
+    fn invalid_argument_has_bad_request() {
+        let status = Error::invalid("email", "wrong format").to_status();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "wrong format");
+
+        let details = rpc_proto::Status::decode(status.details()).unwrap();
+        assert_eq!(details.details.len(), 1);
+        assert!(details.details[0].type_url.ends_with("google.rpc.BadRequest"));
+
+        let bad_request = rpc_proto::BadRequest::decode(&details.details[0].value[..]).unwrap();
+        assert_eq!(bad_request.field_violations[0].field, "email");
+    }
+
+    #[test]
+    fn resource_exhausted_has_retry_info() {
+        use std::time::Duration;
+        let status = Error::ResourceExhausted("slow down".into(), Duration::from_millis(1500)).to_status();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "2");
+
+        let details = rpc_proto::Status::decode(status.details()).unwrap();
+        assert!(details.details[0].type_url.ends_with("google.rpc.RetryInfo"));
+
+        let retry_info = rpc_proto::RetryInfo::decode(&details.details[0].value[..]).unwrap();
+        assert_eq!(retry_info.retry_delay.unwrap().seconds, 1);
+    }
+
+    #[test]
+    fn oauth_has_error_info() {
+        let status = Error::OAuth(crate::oauth::INVALID_GRANT, "code expired".into()).to_status();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "code expired");
+
+        let details = rpc_proto::Status::decode(status.details()).unwrap();
+        let error_info = rpc_proto::ErrorInfo::decode(&details.details[0].value[..]).unwrap();
+        assert_eq!(error_info.reason, "INVALID_GRANT");
+        assert_eq!(error_info.domain, "oauth");
+
+        let status = Error::OAuth(crate::oauth::INVALID_CLIENT, "unknown client".into()).to_status();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+}
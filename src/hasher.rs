@@ -0,0 +1,124 @@
+use std::env;
+use std::error::Error;
+use std::convert::TryFrom;
+use argon2::{Argon2, Algorithm, Version, Params};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use rand_core::OsRng;
+use crate::default;
+
+const ERR_HASH_PARAMS: &str = "The password hashing policy is not valid";
+
+pub trait Hasher {
+    fn hash(&self, pwd: &str) -> Result<String, Box<dyn Error>>;
+    fn verify(&self, pwd: &str, hash: &str) -> bool;
+    fn needs_rehash(&self, hash: &str) -> bool;
+}
+
+lazy_static! {
+    static ref INSTANCE: Argon2id = {
+        let m_cost = env_or(default::ENV_PWD_MEMORY_COST, default::PWD_MEMORY_COST);
+        let t_cost = env_or(default::ENV_PWD_TIME_COST, default::PWD_TIME_COST);
+        let p_cost = env_or(default::ENV_PWD_PARALLELISM, default::PWD_PARALLELISM);
+        Argon2id::new(m_cost, t_cost, p_cost).expect(ERR_HASH_PARAMS)
+    };
+}
+
+pub fn get_instance() -> &'static dyn Hasher {
+    &*INSTANCE
+}
+
+fn env_or(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(value) => value.parse().unwrap_or(default),
+        Err(_) => default,
+    }
+}
+
+// Argon2id produces PHC strings, so the parameters each password got hashed with are stored along the hash
+pub struct Argon2id {
+    params: Params,
+}
+
+impl Argon2id {
+    pub fn new(m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, Box<dyn Error>> {
+        let params = Params::new(m_cost, t_cost, p_cost, None)
+            .map_err(|err| format!("{}: {}", ERR_HASH_PARAMS, err))?;
+
+        Ok(Argon2id{
+            params,
+        })
+    }
+
+    fn context(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+}
+
+impl Hasher for Argon2id {
+    fn hash(&self, pwd: &str) -> Result<String, Box<dyn Error>> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.context()
+            .hash_password(pwd.as_bytes(), &salt)
+            .map_err(|err| err.to_string())?;
+
+        Ok(hash.to_string())
+    }
+
+    fn verify(&self, pwd: &str, hash: &str) -> bool {
+        match PasswordHash::new(hash) {
+            Ok(parsed) => self.context().verify_password(pwd.as_bytes(), &parsed).is_ok(),
+            // legacy passwords were stored as they came, so they must be compared as such
+            Err(_) => pwd.len() == hash.len() && openssl::memcmp::eq(pwd.as_bytes(), hash.as_bytes()),
+        }
+    }
+
+    fn needs_rehash(&self, hash: &str) -> bool {
+        let parsed = match PasswordHash::new(hash) {
+            Ok(parsed) => parsed,
+            Err(_) => return true,
+        };
+
+        if parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+
+        match Params::try_from(&parsed) {
+            Ok(params) => params.m_cost() < self.params.m_cost()
+                || params.t_cost() < self.params.t_cost()
+                || params.p_cost() < self.params.p_cost(),
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Argon2id, Hasher};
+    use crate::default::tests::DUMMY_PWD;
+
+    #[test]
+    fn argon2id_hash_verify() {
+        let hasher = Argon2id::new(1024, 1, 1).unwrap();
+        let hash = hasher.hash(DUMMY_PWD).unwrap();
+
+        assert_ne!(hash, DUMMY_PWD);
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(hasher.verify(DUMMY_PWD, &hash));
+        assert!(!hasher.verify(&format!("{}G", DUMMY_PWD), &hash));
+        assert!(!hasher.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn argon2id_needs_rehash() {
+        let weak = Argon2id::new(1024, 1, 1).unwrap();
+        let strong = Argon2id::new(2048, 2, 1).unwrap();
+        let hash = weak.hash(DUMMY_PWD).unwrap();
+
+        assert!(strong.verify(DUMMY_PWD, &hash));
+        assert!(strong.needs_rehash(&hash));
+        // legacy passwords are stored as they came
+        assert!(strong.verify(DUMMY_PWD, DUMMY_PWD));
+        assert!(strong.needs_rehash(DUMMY_PWD));
+    }
+}
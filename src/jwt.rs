@@ -0,0 +1,535 @@
+use std::env;
+use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::sign::{Signer, Verifier};
+use serde_json::{json, Value};
+use crate::token::Token;
+use crate::default;
+use crate::error;
+use crate::time;
+
+// a jwt looks like <header>.<claims>.<signature>, all of them encoded as base64url with no padding
+const SEPARATOR: char = '.';
+const PARTS: usize = 3;
+const MAX_JWT_LEN: usize = 2048;
+const TYPE_JWT: &str = "JWT"; // id tokens
+const TYPE_ACCESS_TOKEN: &str = "at+jwt"; // as required by RFC 9068, so no id token can be taken as an access token
+const EPHEMERAL_KEY_ID: &str = "ephemeral";
+const EPHEMERAL_RSA_BITS: u32 = 2048;
+const JTI_LEN: usize = 12; // in bytes
+const MAX_ASSERTION_LIFETIME: Duration = Duration::from_secs(300); // client assertions are meant for a single request
+
+pub const ALG_RS256: &str = "RS256";
+pub const ALG_EDDSA: &str = "EdDSA";
+
+const ERR_JWT_KEYS: &str = "Jwt keys must be formatted as a comma separated list of id:base64url, each of them an RSA or Ed25519 private key in DER";
+const ERR_JWT_FORMAT: &str = "The provided token does not match with a real one";
+const ERR_JWT_SIGNATURE: &str = "The provided token is not valid";
+const ERR_JWT_EXPIRED: &str = "The provided token has expired";
+const ERR_JWT_NOT_ISSUED: &str = "The provided token has not been issued by the client for this server";
+
+// Claims are the facts an access token tells about whom it has been issued for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    pub sub: String, // the user id
+    pub aud: String, // the app label
+    pub scope: Vec<String>,
+    pub iat: SystemTime,
+    pub exp: SystemTime,
+}
+
+impl Claims {
+    pub fn new(sub: i32, aud: &str, scope: &[String], lifetime: Duration) -> Self {
+        let now = SystemTime::now();
+        Claims{
+            sub: sub.to_string(),
+            aud: aud.to_string(),
+            scope: scope.to_vec(),
+            // a jwt tells no time finer than a second
+            iat: UNIX_EPOCH + Duration::from_secs(time::unix_seconds(now).unwrap_or(0)),
+            exp: UNIX_EPOCH + Duration::from_secs(time::unix_seconds(now + lifetime).unwrap_or(0)),
+        }
+    }
+}
+
+// Jwk is the public half of a signing key, as described by RFC 7517 and RFC 8037
+#[derive(Debug, Clone, Default)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: String,
+    pub alg: String,
+    pub crv: String,
+    pub n: String,
+    pub e: String,
+    pub x: String,
+}
+
+struct SigningKey {
+    kid: String,
+    alg: &'static str,
+    key: PKey<Private>,
+}
+
+impl SigningKey {
+    fn new(kid: &str, key: PKey<Private>) -> Result<Self, Box<dyn Error>> {
+        let alg = match key.id() {
+            Id::RSA => ALG_RS256,
+            Id::ED25519 => ALG_EDDSA,
+            _ => return Err(ERR_JWT_KEYS.into()),
+        };
+
+        Ok(SigningKey{
+            kid: kid.to_string(),
+            alg,
+            key,
+        })
+    }
+
+    fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut signer = match self.alg {
+            ALG_RS256 => Signer::new(MessageDigest::sha256(), &self.key)?,
+            _ => Signer::new_without_digest(&self.key)?,
+        };
+
+        Ok(signer.sign_oneshot_to_vec(payload)?)
+    }
+
+    fn verify(&self, payload: &[u8], signature: &[u8]) -> Result<bool, Box<dyn Error>> {
+        let mut verifier = match self.alg {
+            ALG_RS256 => Verifier::new(MessageDigest::sha256(), &self.key)?,
+            _ => Verifier::new_without_digest(&self.key)?,
+        };
+
+        // a malformed signature is just as wrong as any other
+        Ok(verifier.verify_oneshot(signature, payload).unwrap_or(false))
+    }
+
+    fn to_jwk(&self) -> Result<Jwk, Box<dyn Error>> {
+        let jwk = match self.alg {
+            ALG_RS256 => {
+                let rsa = self.key.rsa()?;
+                Jwk{
+                    kty: "RSA".to_string(),
+                    n: encode(&rsa.n().to_vec()),
+                    e: encode(&rsa.e().to_vec()),
+                    ..Default::default()
+                }
+            },
+
+            _ => Jwk{
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                x: encode(&self.key.raw_public_key()?),
+                ..Default::default()
+            },
+        };
+
+        Ok(Jwk{
+            kid: self.kid.clone(),
+            alg: self.alg.to_string(),
+            ..jwk
+        })
+    }
+}
+
+// Keyset holds the keys access tokens get signed with: the first one signs all the new tokens, while the others are
+// kept so the tokens signed before a rotation remain valid until they expire
+pub struct Keyset {
+    keys: Vec<SigningKey>,
+    issuer: String,
+}
+
+lazy_static! {
+    static ref KEYSET: Keyset = {
+        let issuer = env::var(default::ENV_ISSUER)
+            .unwrap_or(default::ISSUER.to_string());
+
+        match env::var(default::ENV_JWT_KEYS) {
+            Ok(value) => Keyset::parse(&value, &issuer).expect(ERR_JWT_KEYS),
+            Err(_) => {
+                // no token will be valid after a restart, nor for any other replica
+                println!("No jwt keys have been set, an ephemeral one is used instead");
+                Keyset::ephemeral(&issuer).expect(ERR_JWT_KEYS)
+            },
+        }
+    };
+}
+
+pub fn get_instance() -> &'static Keyset {
+    &KEYSET
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_json(data: &str) -> Result<Value, Box<dyn Error>> {
+    let data = base64::decode_config(data, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| error::Error::Unauthenticated(ERR_JWT_FORMAT.into()))?;
+    serde_json::from_slice(&data)
+        .map_err(|_| error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into())
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> Result<&'a str, Box<dyn Error>> {
+    value.get(key)
+        .and_then(|value| value.as_str())
+        .ok_or(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into())
+}
+
+fn get_time(value: &Value, key: &str) -> Result<SystemTime, Box<dyn Error>> {
+    value.get(key)
+        .and_then(|value| value.as_u64())
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+        .ok_or(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into())
+}
+
+// decode_assertion makes sure the jwt is a client assertion the client has issued for any of the audiences, as described by
+// RFC 7523, section 3, and it has been signed by the client's key the verifier is made of
+pub fn decode_assertion(jwt: &str, mut verifier: Verifier, client_id: &str, audiences: &[&str]) -> Result<(), Box<dyn Error>> {
+    let parts: Vec<&str> = jwt.split(SEPARATOR).collect();
+    if jwt.len() > MAX_JWT_LEN || parts.len() != PARTS {
+        return Err(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into());
+    }
+
+    // the keys of the clients are all RSA ones
+    let header = decode_json(parts[0])?;
+    if get_str(&header, "alg")? != ALG_RS256 {
+        return Err(error::Error::Unauthenticated(ERR_JWT_SIGNATURE.into()).into());
+    }
+
+    let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)
+        .map_err(|_| error::Error::Unauthenticated(ERR_JWT_FORMAT.into()))?;
+    let payload_len = parts[0].len() + 1 + parts[1].len();
+    verifier.update(&jwt.as_bytes()[..payload_len])?;
+    // a malformed signature is just as wrong as any other
+    if !verifier.verify(&signature).unwrap_or(false) {
+        return Err(error::Error::Unauthenticated(ERR_JWT_SIGNATURE.into()).into());
+    }
+
+    let claims = decode_json(parts[1])?;
+    if get_str(&claims, "iss")? != client_id || get_str(&claims, "sub")? != client_id {
+        return Err(error::Error::Unauthenticated(ERR_JWT_NOT_ISSUED.into()).into());
+    }
+
+    let audience = match claims.get("aud") {
+        Some(Value::String(aud)) => audiences.contains(&aud.as_str()),
+        Some(Value::Array(all)) => all.iter().any(|aud| aud.as_str().is_some_and(|aud| audiences.contains(&aud))),
+        _ => false,
+    };
+
+    if !audience {
+        return Err(error::Error::Unauthenticated(ERR_JWT_NOT_ISSUED.into()).into());
+    }
+
+    let exp = get_time(&claims, "exp")?;
+    if exp <= SystemTime::now() {
+        return Err(error::Error::Unauthenticated(ERR_JWT_EXPIRED.into()).into());
+    }
+
+    // no assertion may be replayed for longer than a single request takes
+    if exp > SystemTime::now() + MAX_ASSERTION_LIFETIME {
+        return Err(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into());
+    }
+
+    Ok(())
+}
+
+// encode_assertion returns a client assertion signed by the given key, just like clients issue them; the server never does
+#[cfg(test)]
+pub fn encode_assertion(key: &PKey<Private>, client_id: &str, audience: &str, lifetime: Duration) -> String {
+    let header = json!({
+        "alg": ALG_RS256,
+        "typ": TYPE_JWT,
+    });
+
+    let claims = json!({
+        "iss": client_id,
+        "sub": client_id,
+        "aud": audience,
+        "exp": time::unix_seconds(SystemTime::now() + lifetime).unwrap(),
+        "jti": Token::new(JTI_LEN).to_string(),
+    });
+
+    let payload = format!("{}{}{}", encode(header.to_string().as_bytes()), SEPARATOR, encode(claims.to_string().as_bytes()));
+    let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+    signer.update(payload.as_bytes()).unwrap();
+    format!("{}{}{}", payload, SEPARATOR, encode(&signer.sign_to_vec().unwrap()))
+}
+
+impl Keyset {
+    pub fn parse(value: &str, issuer: &str) -> Result<Self, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        for item in value.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()) {
+            let (kid, der) = match item.split_once(':') {
+                Some((kid, der)) if !kid.is_empty() => (kid, der),
+                _ => return Err(ERR_JWT_KEYS.into()),
+            };
+
+            let der = base64::decode_config(der, base64::URL_SAFE_NO_PAD)?;
+            keys.push(SigningKey::new(kid, PKey::private_key_from_der(&der)?)?);
+        }
+
+        if keys.is_empty() {
+            return Err(ERR_JWT_KEYS.into());
+        }
+
+        Ok(Keyset{
+            keys,
+            issuer: issuer.to_string(),
+        })
+    }
+
+    fn ephemeral(issuer: &str) -> Result<Self, Box<dyn Error>> {
+        let key = PKey::from_rsa(Rsa::generate(EPHEMERAL_RSA_BITS)?)?;
+        Ok(Keyset{
+            keys: vec![SigningKey::new(EPHEMERAL_KEY_ID, key)?],
+            issuer: issuer.to_string(),
+        })
+    }
+
+    pub fn get_issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    // algorithms returns all the signing algorithms in use, with no repetitions
+    pub fn algorithms(&self) -> Vec<&'static str> {
+        let mut algs: Vec<&'static str> = self.keys.iter().map(|key| key.alg).collect();
+        algs.sort();
+        algs.dedup();
+        algs
+    }
+
+    // sign returns the claims as a jwt of the given type, signed by the current key
+    fn sign(&self, claims: &Value, typ: &str) -> Result<String, Box<dyn Error>> {
+        let key = &self.keys[0];
+        let header = json!({
+            "alg": key.alg,
+            "typ": typ,
+            "kid": key.kid,
+        });
+
+        let payload = format!("{}{}{}", encode(header.to_string().as_bytes()), SEPARATOR, encode(claims.to_string().as_bytes()));
+        let signature = key.sign(payload.as_bytes())?;
+        Ok(format!("{}{}{}", payload, SEPARATOR, encode(&signature)))
+    }
+
+    // verify returns the claims of the jwt of the given type, once its signature has been verified by the key it tells
+    fn verify(&self, jwt: &str, typ: &str) -> Result<Value, Box<dyn Error>> {
+        let parts: Vec<&str> = jwt.split(SEPARATOR).collect();
+        if jwt.len() > MAX_JWT_LEN || parts.len() != PARTS {
+            return Err(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into());
+        }
+
+        let header = decode_json(parts[0])?;
+        if get_str(&header, "typ")? != typ {
+            return Err(error::Error::Unauthenticated(ERR_JWT_FORMAT.into()).into());
+        }
+
+        let kid = get_str(&header, "kid")?;
+        // the algorithm is told by the key, never by the token, so no token can choose how it gets verified
+        let key = match self.keys.iter().find(|key| key.kid == kid) {
+            Some(key) if get_str(&header, "alg")? == key.alg => key,
+            _ => return Err(error::Error::Unauthenticated(ERR_JWT_SIGNATURE.into()).into()),
+        };
+
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)
+            .map_err(|_| error::Error::Unauthenticated(ERR_JWT_FORMAT.into()))?;
+        let payload_len = parts[0].len() + 1 + parts[1].len();
+        if !key.verify(&jwt.as_bytes()[..payload_len], &signature)? {
+            return Err(error::Error::Unauthenticated(ERR_JWT_SIGNATURE.into()).into());
+        }
+
+        let claims = decode_json(parts[1])?;
+        if get_str(&claims, "iss")? != self.issuer {
+            return Err(error::Error::Unauthenticated(ERR_JWT_SIGNATURE.into()).into());
+        }
+
+        if get_time(&claims, "exp")? <= SystemTime::now() {
+            return Err(error::Error::Unauthenticated(ERR_JWT_EXPIRED.into()).into());
+        }
+
+        Ok(claims)
+    }
+
+    // encode returns the access token of the given claims
+    pub fn encode(&self, claims: &Claims) -> Result<String, Box<dyn Error>> {
+        let mut value = json!({
+            "iss": self.issuer,
+            "sub": claims.sub,
+            "aud": claims.aud,
+            "iat": time::unix_seconds(claims.iat)?,
+            "exp": time::unix_seconds(claims.exp)?,
+            "jti": Token::new(JTI_LEN).to_string(),
+        });
+
+        if !claims.scope.is_empty() {
+            // as described by RFC 9068, section 2.2.3
+            value["scope"] = Value::from(claims.scope.join(" "));
+        }
+
+        self.sign(&value, TYPE_ACCESS_TOKEN)
+    }
+
+    // decode returns the claims of the access token, as long as it is valid
+    pub fn decode(&self, jwt: &str) -> Result<Claims, Box<dyn Error>> {
+        let value = self.verify(jwt, TYPE_ACCESS_TOKEN)?;
+        let scope = value.get("scope")
+            .and_then(|scope| scope.as_str())
+            .map(|scope| scope.split(' ').filter(|item| !item.is_empty()).map(|item| item.to_string()).collect())
+            .unwrap_or_default();
+
+        Ok(Claims{
+            sub: get_str(&value, "sub")?.to_string(),
+            aud: get_str(&value, "aud")?.to_string(),
+            scope,
+            iat: get_time(&value, "iat")?,
+            exp: get_time(&value, "exp")?,
+        })
+    }
+
+    // encode_id_token returns the id token of the user, as described by OpenID Connect Core, section 2; the nonce is told
+    // back as is, so the app can match the token with its authorization request
+    pub fn encode_id_token(&self, sub: i32, aud: &str, nonce: &str, auth_time: SystemTime, lifetime: Duration) -> Result<String, Box<dyn Error>> {
+        let claims = Claims::new(sub, aud, &[], lifetime);
+        let mut value = json!({
+            "iss": self.issuer,
+            "sub": claims.sub,
+            "aud": claims.aud,
+            "iat": time::unix_seconds(claims.iat)?,
+            "exp": time::unix_seconds(claims.exp)?,
+            "auth_time": time::unix_seconds(auth_time)?,
+        });
+
+        if !nonce.is_empty() {
+            value["nonce"] = Value::from(nonce);
+        }
+
+        self.sign(&value, TYPE_JWT)
+    }
+
+    // decode_id_token returns all the claims of the id token, as long as it is valid; relying parties are the ones decoding them
+    #[cfg(test)]
+    pub fn decode_id_token(&self, jwt: &str) -> Result<Value, Box<dyn Error>> {
+        self.verify(jwt, TYPE_JWT)
+    }
+
+    // jwks returns the public half of all the keys, so anyone can verify the tokens with no need of calling back
+    pub fn jwks(&self) -> Result<Vec<Jwk>, Box<dyn Error>> {
+        self.keys.iter().map(|key| key.to_jwk()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use super::*;
+
+    const ISSUER: &str = "https://testing.com";
+
+    fn der_of(key: &PKey<Private>) -> String {
+        base64::encode_config(key.private_key_to_der().unwrap(), base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn jwt_roundtrip() {
+        let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let ed25519 = PKey::generate_ed25519().unwrap();
+        let scope = vec!["read".to_string(), "write".to_string()];
+
+        for key in &[rsa, ed25519] {
+            let keyset = Keyset::parse(&format!("current:{}", der_of(key)), ISSUER).unwrap();
+            let claims = Claims::new(42, "dummy_label", &scope, Duration::from_secs(60));
+            let jwt = keyset.encode(&claims).unwrap();
+            assert_eq!(keyset.decode(&jwt).unwrap(), claims);
+
+            // no part of the token can be changed
+            let mut tampered: Vec<String> = jwt.split('.').map(|part| part.to_string()).collect();
+            let forged = Claims::new(43, "dummy_label", &scope, Duration::from_secs(60));
+            tampered[1] = keyset.encode(&forged).unwrap().split('.').nth(1).unwrap().to_string();
+            assert!(keyset.decode(&tampered.join(".")).is_err());
+        }
+    }
+
+    #[test]
+    fn jwt_expired() {
+        let key = PKey::generate_ed25519().unwrap();
+        let keyset = Keyset::parse(&format!("current:{}", der_of(&key)), ISSUER).unwrap();
+        let mut claims = Claims::new(42, "dummy_label", &[], Duration::from_secs(60));
+        claims.exp = claims.iat;
+        let jwt = keyset.encode(&claims).unwrap();
+        assert!(keyset.decode(&jwt).is_err());
+
+        // nor is a token of another issuer valid
+        let other = Keyset::parse(&format!("current:{}", der_of(&key)), "https://another.com").unwrap();
+        let jwt = other.encode(&Claims::new(42, "dummy_label", &[], Duration::from_secs(60))).unwrap();
+        assert!(keyset.decode(&jwt).is_err());
+    }
+
+    #[test]
+    fn jwt_rotation() {
+        let old = PKey::generate_ed25519().unwrap();
+        let new = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let before = Keyset::parse(&format!("old:{}", der_of(&old)), ISSUER).unwrap();
+        let after = Keyset::parse(&format!("new:{},old:{}", der_of(&new), der_of(&old)), ISSUER).unwrap();
+
+        let claims = Claims::new(42, "dummy_label", &[], Duration::from_secs(60));
+        let jwt = before.encode(&claims).unwrap();
+        assert!(after.decode(&jwt).is_ok());
+
+        let jwt = after.encode(&claims).unwrap();
+        assert!(jwt.starts_with(&encode(json!({"alg": ALG_RS256, "typ": TYPE_ACCESS_TOKEN, "kid": "new"}).to_string().as_bytes())));
+        assert!(before.decode(&jwt).is_err());
+        assert_eq!(after.algorithms(), vec![ALG_EDDSA, ALG_RS256]);
+
+        let jwks = after.jwks().unwrap();
+        assert_eq!(jwks.len(), 2);
+        assert_eq!((jwks[0].kty.as_str(), jwks[0].kid.as_str(), jwks[0].e.as_str()), ("RSA", "new", "AQAB"));
+        assert_eq!((jwks[1].kty.as_str(), jwks[1].crv.as_str()), ("OKP", "Ed25519"));
+        assert_eq!(jwks[1].x, encode(&old.raw_public_key().unwrap()));
+    }
+
+    #[test]
+    fn jwt_id_token() {
+        let key = PKey::generate_ed25519().unwrap();
+        let keyset = Keyset::parse(&format!("current:{}", der_of(&key)), ISSUER).unwrap();
+        let auth_time = UNIX_EPOCH + Duration::from_secs(1234567890);
+        let id_token = keyset.encode_id_token(42, "dummy_label", "n-0S6_WzA2Mj", auth_time, Duration::from_secs(60)).unwrap();
+
+        let claims = keyset.decode_id_token(&id_token).unwrap();
+        assert_eq!(claims["sub"], "42");
+        assert_eq!(claims["nonce"], "n-0S6_WzA2Mj");
+        assert_eq!(claims["auth_time"], 1234567890);
+
+        // id tokens and access tokens cannot be taken for each other
+        assert!(keyset.decode(&id_token).is_err());
+        let access_token = keyset.encode(&Claims::new(42, "dummy_label", &[], Duration::from_secs(60))).unwrap();
+        assert!(keyset.decode_id_token(&access_token).is_err());
+    }
+
+    #[test]
+    fn jwt_client_assertion() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let public = PKey::public_key_from_pem(&key.public_key_to_pem().unwrap()).unwrap();
+        let audience = "https://testing.com/token";
+        let assertion = encode_assertion(&key, "dummy_label", audience, Duration::from_secs(60));
+        assert!(decode_assertion(&assertion, Verifier::new(MessageDigest::sha256(), &public).unwrap(), "dummy_label", &[audience]).is_ok());
+
+        // it is bound to the client and the server it has been issued for
+        assert!(decode_assertion(&assertion, Verifier::new(MessageDigest::sha256(), &public).unwrap(), "another_label", &[audience]).is_err());
+        assert!(decode_assertion(&assertion, Verifier::new(MessageDigest::sha256(), &public).unwrap(), "dummy_label", &[ISSUER]).is_err());
+
+        // and it must be signed by the client's own key
+        let other = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let forged = encode_assertion(&other, "dummy_label", audience, Duration::from_secs(60));
+        assert!(decode_assertion(&forged, Verifier::new(MessageDigest::sha256(), &public).unwrap(), "dummy_label", &[audience]).is_err());
+
+        // nor may it be valid for longer than a single request takes
+        let lasting = encode_assertion(&key, "dummy_label", audience, Duration::from_secs(3600));
+        assert!(decode_assertion(&lasting, Verifier::new(MessageDigest::sha256(), &public).unwrap(), "dummy_label", &[audience]).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::process;
+use std::path::PathBuf;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use super::{Mailer, Message, build_email};
+
+pub const BACKEND_NAME: &str = "file";
+
+const MAILDIR_TMP: &str = "tmp";
+const MAILDIR_NEW: &str = "new";
+const MAILDIR_CUR: &str = "cur";
+
+// FileMailer delivers each message into a local maildir, so no mail server is required for dev and testing
+pub struct FileMailer {
+    from: String,
+    dir: PathBuf,
+    count: AtomicUsize,
+}
+
+impl FileMailer {
+    pub fn new(from: &str, dir: &str) -> Self {
+        FileMailer{
+            from: from.to_string(),
+            dir: PathBuf::from(dir),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn unique_name(&self) -> Result<String, Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let count = self.count.fetch_add(1, Ordering::SeqCst);
+        Ok(format!("{}.{}_{}.{}.eml", now.as_secs(), now.subsec_nanos(), count, process::id()))
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, msg: &Message) -> Result<(), Box<dyn Error>> {
+        for sub in &[MAILDIR_TMP, MAILDIR_NEW, MAILDIR_CUR] {
+            fs::create_dir_all(self.dir.join(sub))?;
+        }
+
+        let email = build_email(&self.from, msg)?;
+        let name = self.unique_name()?;
+
+        // messages are written into tmp and then moved into new, so readers never see them half-written
+        let tmp = self.dir.join(MAILDIR_TMP).join(&name);
+        fs::write(&tmp, email.formatted())?;
+        fs::rename(&tmp, self.dir.join(MAILDIR_NEW).join(&name))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+pub mod smtp;
+pub mod file;
+pub mod template;
+pub mod outbox;
+
+use std::env;
+use std::error::Error;
+use crate::default;
+
+const ERR_NO_SMTP_HOST: &str = "Smtp host must be set";
+const ERR_UNKNOWN_BACKEND: &str = "Unknown mailer backend";
+const ERR_NO_BACKEND: &str = "Mailer backend must be set";
+
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer {
+    fn send(&self, msg: &Message) -> Result<(), Box<dyn Error>>;
+}
+
+lazy_static! {
+    static ref INSTANCE: Box<dyn Mailer + Send + Sync> = {
+        let from = env::var(default::ENV_MAIL_FROM)
+            .unwrap_or(default::MAIL_FROM.to_string());
+
+        // mails hold secrets, so they must never end up anywhere the deploy has not chosen
+        let backend = env::var(default::ENV_MAILER_BACKEND)
+            .expect(ERR_NO_BACKEND);
+
+        match backend.as_str() {
+            smtp::BACKEND_NAME => {
+                let host = env::var(default::ENV_SMTP_HOST).expect(ERR_NO_SMTP_HOST);
+                let username = env::var(default::ENV_SMTP_USERNAME).ok();
+                let password = env::var(default::ENV_SMTP_PASSWORD).ok();
+                Box::new(smtp::SmtpMailer::new(&from, &host, username, password).expect(ERR_NO_SMTP_HOST))
+            },
+
+            file::BACKEND_NAME => {
+                let dir = env::var(default::ENV_MAILER_DIR)
+                    .unwrap_or(default::MAILER_DIR.to_string());
+                Box::new(file::FileMailer::new(&from, &dir))
+            },
+
+            _ => panic!("{} {}", ERR_UNKNOWN_BACKEND, backend),
+        }
+    };
+}
+
+// must_configure makes sure the mailer backend has been set, so the server does not start without it
+pub fn must_configure() {
+    lazy_static::initialize(&INSTANCE);
+}
+
+pub fn get_instance() -> &'static dyn Mailer {
+    INSTANCE.as_ref()
+}
+
+fn build_email(from: &str, msg: &Message) -> Result<lettre::Message, Box<dyn Error>> {
+    let email = lettre::Message::builder()
+        .from(from.parse()?)
+        .to(msg.to.parse()?)
+        .subject(&msg.subject)
+        .body(msg.body.clone())?;
+
+    Ok(email)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{Mailer, file, template};
+    use crate::default::tests::{get_prefixed_data, DUMMY_PWD};
+
+    #[test]
+    fn file_mailer_send() {
+        const PREFIX: &str = "file_mailer_send";
+
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        let dir = std::env::temp_dir().join(PREFIX);
+        let _ = fs::remove_dir_all(&dir);
+
+        let mailer = file::FileMailer::new("oauth@testing.com", dir.to_str().unwrap());
+        let msg = template::restore_credentials(&email, &name, PREFIX, DUMMY_PWD);
+        mailer.send(&msg).unwrap();
+        mailer.send(&msg).unwrap();
+
+        let delivered: Vec<_> = fs::read_dir(dir.join("new")).unwrap().collect();
+        assert_eq!(delivered.len(), 2);
+        assert_eq!(fs::read_dir(dir.join("tmp")).unwrap().count(), 0);
+
+        let content = fs::read_to_string(delivered[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains(&email));
+        assert!(content.contains(DUMMY_PWD));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+use crate::schema::outbox;
+use crate::diesel::prelude::*;
+use diesel::pg::PgConnection;
+use crate::postgres::*;
+use crate::default;
+use super::Message;
+
+// Any mail is stored in the outbox before being sent, so it is not lost if the server crashes before delivering it
+
+const REDACTED_BODY: &str = "";
+
+// Mail holds just those columns required to send the mail, the rest are for the outbox's bookkeeping only
+#[derive(Queryable)]
+#[derive(Clone)]
+struct Mail {
+    pub id: i32,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub attempts: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="outbox"]
+struct NewMail<'a> {
+    pub recipient: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+pub fn enqueue(msg: &Message) -> Result<i32, Box<dyn Error>> {
+    let connection = open_stream().get()?;
+    enqueue_on(&connection, msg)
+}
+
+// enqueue_on stores the mail through the given connection, so it can be part of a wider transaction
+pub fn enqueue_on(connection: &PgConnection, msg: &Message) -> Result<i32, Box<dyn Error>> {
+    let new_mail = NewMail {
+        recipient: &msg.to,
+        subject: &msg.subject,
+        body: &msg.body,
+    };
+
+    let mail_id = diesel::insert_into(outbox::table)
+        .values(&new_mail)
+        .returning(outbox::id)
+        .get_result::<i32>(connection)?;
+
+    Ok(mail_id)
+}
+
+// flush sends all the pending mails whose retry delay has expired, returning how many of them got delivered
+pub fn flush() -> Result<usize, Box<dyn Error>> {
+    use crate::schema::outbox::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        outbox.filter(next_attempt_at.le(SystemTime::now()))
+            .filter(attempts.lt(default::MAIL_MAX_ATTEMPTS))
+            .select((id, recipient, subject, body, attempts))
+            .load::<Mail>(&connection)?
+    };
+
+    let mut delivered = 0;
+    for mail in results.iter() {
+        if claim(mail.id)? && send(mail).is_ok() {
+            delivered += 1;
+        }
+    }
+
+    Ok(delivered)
+}
+
+// claim postpones the next attempt of the given mail for as long as it may take to send it, so no other
+// worker will pick it meanwhile
+fn claim(target: i32) -> Result<bool, Box<dyn Error>> {
+    use crate::schema::outbox::dsl::*;
+
+    let now = SystemTime::now();
+    let lease = now + Duration::from_secs(default::MAIL_LEASE);
+    let updated = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::update(
+            outbox.filter(id.eq(target))
+                .filter(next_attempt_at.le(now))
+                .filter(attempts.lt(default::MAIL_MAX_ATTEMPTS))
+        )
+        .set(next_attempt_at.eq(lease))
+        .execute(&connection)?
+    };
+
+    Ok(updated > 0)
+}
+
+fn send(mail: &Mail) -> Result<(), Box<dyn Error>> {
+    use crate::schema::outbox::dsl::*;
+
+    let msg = Message{
+        to: mail.recipient.clone(),
+        subject: mail.subject.clone(),
+        body: mail.body.clone(),
+    };
+
+    match super::get_instance().send(&msg) {
+        Ok(_) => { // sent mails are removed, so no secret remains stored in the outbox
+            let connection = open_stream().get()?;
+            diesel::delete(outbox.filter(id.eq(mail.id)))
+                .execute(&connection)?;
+            Ok(())
+        },
+
+        Err(err) => {
+            println!("Mail {} could not be sent: {}", mail.id, err);
+            let delay = default::MAIL_RETRY_DELAY << mail.attempts.min(16);
+            let connection = open_stream().get()?;
+            diesel::update(outbox.filter(id.eq(mail.id)))
+                .set((attempts.eq(mail.attempts + 1),
+                      next_attempt_at.eq(SystemTime::now() + Duration::from_secs(delay)),
+                      last_error.eq(err.to_string())))
+                .execute(&connection)?;
+
+            if mail.attempts + 1 >= default::MAIL_MAX_ATTEMPTS {
+                // the mail will never be sent, so there is no reason to keep the secrets it may hold
+                diesel::update(outbox.filter(id.eq(mail.id)))
+                    .set(body.eq(REDACTED_BODY))
+                    .execute(&connection)?;
+            }
+
+            Err(err)
+        },
+    }
+}
@@ -0,0 +1,33 @@
+use std::error::Error;
+use lettre::{SmtpTransport, Transport};
+use lettre::transport::smtp::authentication::Credentials;
+use super::{Mailer, Message, build_email};
+
+pub const BACKEND_NAME: &str = "smtp";
+
+pub struct SmtpMailer {
+    from: String,
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    pub fn new(from: &str, host: &str, username: Option<String>, password: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let mut builder = SmtpTransport::relay(host)?;
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer{
+            from: from.to_string(),
+            transport: builder.build(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, msg: &Message) -> Result<(), Box<dyn Error>> {
+        let email = build_email(&self.from, msg)?;
+        self.transport.send(&email)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use super::Message;
+
+pub fn restore_credentials(to: &str, name: &str, ticket: &str, secret: &str) -> Message {
+    Message{
+        to: to.to_string(),
+        subject: "Restore your credentials".to_string(),
+        body: format!("Hi {},\n\n\
+            Someone has requested to restore the credentials of your account. \
+            If it was you, use the following data to set a new password:\n\n\
+            \tTicket: {}\n\
+            \tSecret: {}\n\n\
+            If it was not you, just ignore this message: your password remains the same.\n",
+            name, ticket, secret),
+    }
+}
+
+pub fn verify_email(to: &str, name: &str, ticket: &str, secret: &str) -> Message {
+    Message{
+        to: to.to_string(),
+        subject: "Verify your email".to_string(),
+        body: format!("Hi {},\n\n\
+            Welcome aboard! Use the following data to verify this email belongs to you:\n\n\
+            \tTicket: {}\n\
+            \tSecret: {}\n\n\
+            If you did not sign up, just ignore this message.\n",
+            name, ticket, secret),
+    }
+}
+
+pub fn new_login(to: &str, name: &str, app: &str, device: &str, addr: &str, agent: &str) -> Message {
+    Message{
+        to: to.to_string(),
+        subject: "New login into your account".to_string(),
+        body: format!("Hi {},\n\n\
+            A new session has been opened for your account in order to log into {}:\n\n\
+            \tDevice: {}\n\
+            \tAddress: {}\n\
+            \tAgent: {}\n\n\
+            If it was not you, restore your credentials as soon as possible.\n",
+            name, app, device, addr, agent),
+    }
+}
+
+pub fn grant_consent(to: &str, name: &str, app: &str, scope: &[String], ticket: &str, secret: &str) -> Message {
+    Message{
+        to: to.to_string(),
+        subject: "Grant access to your account".to_string(),
+        body: format!("Hi {},\n\n\
+            {} is asking for access to your account with the following scopes: {}. \
+            If you agree, use the following data to grant it:\n\n\
+            \tTicket: {}\n\
+            \tSecret: {}\n\n\
+            If you do not, just ignore this message: the application gets no further access.\n",
+            name, app, scope.join(", "), ticket, secret),
+    }
+}
@@ -23,6 +23,14 @@ mod mongo;
 mod proto;
 mod time;
 mod token;
+mod cookie;
+mod throttle;
+mod totp;
+mod oauth;
+mod jwt;
+mod hasher;
+mod mailer;
+mod error;
 mod default;
 
 const ERR_NO_PORT: &str = "Service port must be set";
@@ -40,6 +48,8 @@ pub fn initialize() {
 
         postgres::must_connect(); // checking postgres connectivity
         mongo::must_connect(); // checking mongodb connectivity
+        totp::must_configure(); // checking the totp key has been set
+        mailer::must_configure(); // checking the mailer backend has been set
     });
 }
 
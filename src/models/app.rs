@@ -10,31 +10,38 @@ use crate::token;
 use super::client::Ctrl as ClientCtrl;
 extern crate diesel;
 use crate::default;
+use crate::oauth;
 
-pub trait Ctrl {
+pub trait Ctrl: Send {
     fn get_id(&self) -> i32;
     fn get_url(&self) -> &str;
     fn get_name(&self) -> &str;
     fn get_label(&self) -> &str;
     fn get_descr(&self) -> &str;
     fn get_client_id(&self) -> i32;
+    fn get_force_reauth(&self) -> bool;
+    fn set_force_reauth(&mut self, force: bool);
+    fn get_scope(&self) -> Vec<String>;
+    fn set_scope(&mut self, scope: &[String]);
 }
 
-//pub fn find_by_id(target: i32) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
-//    use crate::schema::apps::dsl::*;
-//
-//    let connection = open_stream().get()?;
-//    let results = apps.filter(id.eq(target))
-//        .load::<App>(&connection)?;
-//
-//    if results.len() > 0 {
-//        let client = client::find_by_id(results[0].client_id)?;
-//        let wrapper = results[0].build(client)?;
-//        Ok(Box::new(wrapper))
-//    } else {
-//        Err(Box::new(NotFound))
-//    }
-//}
+pub fn find_by_id(target: i32) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::apps::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        apps.filter(id.eq(target))
+            .load::<App>(&connection)?
+    };
+
+    if !results.is_empty() {
+        let client = client::find_by_id(results[0].client_id)?;
+        let wrapper = results[0].build(client)?;
+        Ok(Box::new(wrapper))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
 
 pub fn find_by_label<'a>(target: &'a str) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
     use crate::schema::apps::dsl::*;
@@ -103,6 +110,8 @@ pub struct App {
     pub label: String,
     pub url: String,
     pub description: String,
+    pub force_reauth: bool, // single sign-on is not allowed, so users must always provide their credentials
+    pub scope: String, // the scopes the app may ask its users for
 }
 
 #[derive(Insertable)]
@@ -112,6 +121,8 @@ struct NewApp<'a> {
     pub label: &'a str,
     pub url: &'a str,
     pub description: &'a str,
+    pub force_reauth: bool,
+    pub scope: &'a str,
 }
 
 impl App {
@@ -126,6 +137,8 @@ impl App {
             label: label,
             url: url.to_string(),
             description: descr.to_string(),
+            force_reauth: false,
+            scope: String::new(),
         };
 
         let wrapper = app.build(client)?;
@@ -169,6 +182,22 @@ impl Ctrl for Wrapper {
     fn get_client_id(&self) -> i32 {
         self.client.get_id()
     }
+
+    fn get_force_reauth(&self) -> bool {
+        self.app.force_reauth
+    }
+
+    fn set_force_reauth(&mut self, force: bool) {
+        self.app.force_reauth = force;
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        oauth::split_scope(&self.app.scope)
+    }
+
+    fn set_scope(&mut self, scope: &[String]) {
+        self.app.scope = scope.join(" ");
+    }
 }
 
 impl super::Gateway for Wrapper {
@@ -184,6 +213,8 @@ impl super::Gateway for Wrapper {
             label: &self.app.label,
             url: &self.app.url,
             description: &self.app.description,
+            force_reauth: self.app.force_reauth,
+            scope: &self.app.scope,
         };
 
         let result = { // block is required because of connection release
@@ -203,7 +234,9 @@ impl super::Gateway for Wrapper {
             let connection = open_stream().get()?;
             diesel::update(&self.app)
             .set((apps::url.eq(&self.app.url),
-                  apps::description.eq(&self.app.description)))
+                  apps::description.eq(&self.app.description),
+                  apps::force_reauth.eq(self.app.force_reauth),
+                  apps::scope.eq(&self.app.scope)))
             .execute(&connection)?;
         }
 
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+use diesel::NotFound;
+use crate::schema::authcodes;
+use crate::diesel::prelude::*;
+use crate::postgres::*;
+use crate::token::Token;
+use crate::default;
+use crate::error;
+use crate::oauth;
+
+const ERR_CODE_CONSUMED: &str = "The authorization code has already been consumed";
+const ERR_DEADLINE_EXCEEDED: &str = "The authorization code has expired";
+
+pub trait Ctrl {
+    fn get_app_id(&self) -> i32;
+    fn get_cookie(&self) -> Token;
+    fn get_redirect_uri(&self) -> &str;
+    fn get_scope(&self) -> Vec<String>;
+    fn get_nonce(&self) -> &str;
+    fn match_verifier(&self, verifier: &str) -> bool;
+    fn is_alive(&self) -> Result<(), Box<dyn Error>>;
+    fn consume(&mut self);
+}
+
+// find_by_code returns the authorization code, which is looked up by its digest
+pub fn find_by_code(target: &Token) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::authcodes::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        authcodes.filter(code.eq(target.digest().as_str()))
+            .load::<AuthCode>(&connection)?
+    };
+
+    if !results.is_empty() {
+        Ok(Box::new(results[0].clone()))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
+// delete_expired removes all the authorization codes past their deadline, whether consumed or not
+pub fn delete_expired() -> Result<usize, Box<dyn Error>> {
+    use crate::schema::authcodes::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            authcodes.filter(deadline.lt(SystemTime::now()))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+#[derive(Insertable)]
+#[derive(Identifiable)]
+#[derive(Queryable)]
+#[derive(Clone)]
+#[table_name="authcodes"]
+#[primary_key(code)]
+pub struct AuthCode {
+    pub code: String,
+    pub app_id: i32,
+    pub cookie: String,
+    pub redirect_uri: String,
+    pub code_challenge: String,
+    pub created_at: SystemTime,
+    pub deadline: SystemTime,
+    pub consumed_at: Option<SystemTime>,
+    pub scope: String,
+    pub nonce: String,
+}
+
+impl AuthCode {
+    // the returned token is the code itself, the only moment it is available as plain text
+    pub fn new(app_id: i32, cookie: &Token, redirect_uri: &str, code_challenge: &str, scope: &[String], nonce: &str, timeout: Duration) -> (Box<impl Ctrl + super::Gateway>, Token) {
+        let code = Token::new(default::TOKEN_LEN);
+        let authcode = AuthCode {
+            code: code.digest().to_string(),
+            app_id,
+            cookie: cookie.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            code_challenge: code_challenge.to_string(),
+            created_at: SystemTime::now(),
+            deadline: SystemTime::now() + timeout,
+            consumed_at: None,
+            scope: scope.join(" "),
+            nonce: nonce.to_string(),
+        };
+
+        (Box::new(authcode), code)
+    }
+}
+
+impl Ctrl for AuthCode {
+    fn get_app_id(&self) -> i32 {
+        self.app_id
+    }
+
+    fn get_cookie(&self) -> Token {
+        Token::from_string(&self.cookie)
+    }
+
+    fn get_redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        oauth::split_scope(&self.scope)
+    }
+
+    fn get_nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    fn match_verifier(&self, verifier: &str) -> bool {
+        oauth::match_pkce(&self.code_challenge, verifier)
+    }
+
+    fn is_alive(&self) -> Result<(), Box<dyn Error>> {
+        if self.consumed_at.is_some() {
+            return Err(error::Error::PermissionDenied(ERR_CODE_CONSUMED.into()).into());
+        }
+
+        if self.deadline < SystemTime::now() {
+            return Err(error::Error::DeadlineExceeded(ERR_DEADLINE_EXCEEDED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn consume(&mut self) {
+        self.consumed_at = Some(SystemTime::now());
+    }
+}
+
+impl super::Gateway for AuthCode {
+    fn select(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("".into())
+    }
+
+    fn insert(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(authcodes::table)
+            .values(&*self)
+            .get_result::<AuthCode>(&connection)?
+        };
+
+        self.created_at = result.created_at;
+        Ok(())
+    }
+
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::authcodes::dsl::*;
+
+        let updated = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            // a code can be exchanged just once, no matter how many requests are racing for it
+            diesel::update(
+                authcodes.filter(code.eq(&self.code))
+                    .filter(consumed_at.is_null())
+            )
+            .set(consumed_at.eq(self.consumed_at))
+            .execute(&connection)?
+        };
+
+        if updated == 0 {
+            return Err(error::Error::PermissionDenied(ERR_CODE_CONSUMED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::authcodes::dsl::*;
+
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                authcodes.filter(
+                    code.eq(&self.code)
+                )
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
+}
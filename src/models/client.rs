@@ -8,7 +8,8 @@ use crate::postgres::*;
 use crate::regex::*;
 
 pub trait Ctrl {
-    fn get_status(&self) -> i32;
+    fn get_status(&self) -> enums::Status;
+    fn set_status(&mut self, status: enums::Status);
     fn get_id(&self) -> i32;
     fn get_name(&self) -> &str;
     fn get_kind(&self) -> enums::Kind;
@@ -80,7 +81,7 @@ impl Client {
         let client = Client {
             id: 0,
             name: name.to_string(),
-            status_id: 1,
+            status_id: enums::Status::PENDING.to_int32(),
             kind_id: kind.to_int32(),
             created_at: SystemTime::now(),
             updated_at: SystemTime::now(),
@@ -92,9 +93,11 @@ impl Client {
 
     fn build(&self) -> Result<Wrapper, Box<dyn Error>> {
         let kind = enums::Kind::from_i32(self.kind_id)?;
+        let status = enums::Status::from_i32(self.status_id)?;
         Ok(Wrapper{
             client: self.clone(),
-            kind: kind,
+            kind,
+            status,
         })
     }
 }
@@ -102,11 +105,17 @@ impl Client {
 pub struct Wrapper {
     client: Client,
     kind: enums::Kind,
+    status: enums::Status,
 }
 
 impl Ctrl for Wrapper {
-    fn get_status(&self) -> i32 {
-        self.client.status_id
+    fn get_status(&self) -> enums::Status {
+        self.status
+    }
+
+    fn set_status(&mut self, status: enums::Status) {
+        self.client.status_id = status.to_int32();
+        self.status = status;
     }
 
     fn get_id(&self) -> i32 {
@@ -138,7 +147,7 @@ impl super::Gateway for Wrapper {
     fn insert(&mut self) -> Result<(), Box<dyn Error>> {
         let new_client = NewClient {
             name: &self.client.name,
-            status_id: 1,
+            status_id: self.client.status_id,
             kind_id: self.client.kind_id,
         };
 
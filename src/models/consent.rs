@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::time::SystemTime;
+use diesel::NotFound;
+use crate::schema::consents;
+use crate::diesel::prelude::*;
+use crate::postgres::*;
+use crate::oauth;
+
+pub trait Ctrl {
+    fn get_client_id(&self) -> i32;
+    fn get_app_id(&self) -> i32;
+    fn get_scope(&self) -> Vec<String>;
+    fn get_created_at(&self) -> SystemTime;
+    fn get_updated_at(&self) -> SystemTime;
+    fn covers(&self, scope: &[String]) -> bool;
+    fn grant(&mut self, scope: &[String]);
+}
+
+// find_by_client_and_app returns the consent the user has given to the app, if any
+pub fn find_by_client_and_app(target_client: i32, target_app: i32) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::consents::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        consents.filter(client_id.eq(target_client))
+            .filter(app_id.eq(target_app))
+            .load::<Consent>(&connection)?
+    };
+
+    if !results.is_empty() {
+        Ok(Box::new(results[0].clone()))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
+// find_all_by_client returns all the consents the user has given, no matter the app
+pub fn find_all_by_client(target: i32) -> Result<Vec<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::consents::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        consents.filter(client_id.eq(target))
+            .load::<Consent>(&connection)?
+    };
+
+    Ok(results)
+}
+
+#[derive(Insertable)]
+#[derive(Identifiable)]
+#[derive(Queryable)]
+#[derive(Clone)]
+#[table_name="consents"]
+#[primary_key(client_id, app_id)]
+pub struct Consent {
+    pub client_id: i32,
+    pub app_id: i32,
+    pub scope: String,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+impl Consent {
+    pub fn new(client_id: i32, app_id: i32, scope: &[String]) -> Box<impl Ctrl + super::Gateway> {
+        let consent = Consent {
+            client_id,
+            app_id,
+            scope: scope.join(" "),
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        };
+
+        Box::new(consent)
+    }
+}
+
+impl Ctrl for Consent {
+    fn get_client_id(&self) -> i32 {
+        self.client_id
+    }
+
+    fn get_app_id(&self) -> i32 {
+        self.app_id
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        oauth::split_scope(&self.scope)
+    }
+
+    fn get_created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    fn get_updated_at(&self) -> SystemTime {
+        self.updated_at
+    }
+
+    // covers tells whether all of the given scopes have been granted already
+    fn covers(&self, scope: &[String]) -> bool {
+        let granted = self.get_scope();
+        scope.iter().all(|item| granted.contains(item))
+    }
+
+    // grant adds the given scopes to the ones granted already
+    fn grant(&mut self, scope: &[String]) {
+        let mut granted = self.get_scope();
+        for item in scope {
+            if !granted.contains(item) {
+                granted.push(item.clone());
+            }
+        }
+
+        self.scope = granted.join(" ");
+        self.updated_at = SystemTime::now();
+    }
+}
+
+impl super::Gateway for Consent {
+    fn select(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("".into())
+    }
+
+    fn insert(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(consents::table)
+            .values(&*self)
+            .get_result::<Consent>(&connection)?
+        };
+
+        self.created_at = result.created_at;
+        Ok(())
+    }
+
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::update(&*self)
+            .set((consents::scope.eq(&self.scope),
+                  consents::updated_at.eq(self.updated_at)))
+            .execute(&connection)?;
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::consents::dsl::*;
+
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                consents.filter(client_id.eq(self.client_id))
+                    .filter(app_id.eq(self.app_id))
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
 use std::error::Error;
+use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
 use mongodb::bson;
 
 pub trait Ctrl {
     fn get_user_id(&self) -> i32;
     fn get_app_id(&self) -> i32;
+    fn get_opened_at(&self) -> SystemTime;
     fn get_data(&self) -> &bson::Document;
 }
 
@@ -14,15 +16,18 @@ pub struct Dir {
     id: Option<bson::oid::ObjectId>,
     user_id: i32,
     app_id: i32,
+    #[serde(skip, default = "SystemTime::now")]
+    opened_at: SystemTime, // the directory token is derived from it
     data: bson::Document,
 }
 
 impl Dir {
-    pub fn new(user: i32, app: i32) -> Self {
+    pub fn new(user: i32, app: i32, opened_at: SystemTime) -> Self {
         Dir{
             id: None,
             user_id: user,
             app_id: app,
+            opened_at,
             data: bson::Document::new(),
         }
     }
@@ -37,6 +42,10 @@ impl Ctrl for Dir {
         self.app_id
     }
 
+    fn get_opened_at(&self) -> SystemTime {
+        self.opened_at
+    }
+
     fn get_data(&self) -> &bson::Document {
         &self.data
     }
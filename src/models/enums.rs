@@ -83,14 +83,23 @@ custom_derive! {
 }
 
 impl Status {
+    pub fn from_i32(value: i32) -> Result<Status, Box<dyn Error>> {
+        match value {
+            1 => Ok(Status::PENDING),
+            2 => Ok(Status::ACTIVATED),
+            3 => Ok(Status::HIDDEN),
+            _ => Err(ERR_UNKNOWN_VALUE.into()),
+        }
+    }
+
     pub fn _from_string(name: &str) -> Result<Status, Box<dyn Error>> {
         let upper = name.to_uppercase();
         let status: Status = upper.parse()?;
         Ok(status)
     }
 
-    pub fn _to_int32(&self) -> i32 {
-        *self as i32 + 1
+    pub fn to_int32(self) -> i32 {
+        self as i32 + 1
     }
 }
 
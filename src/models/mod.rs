@@ -5,6 +5,11 @@ pub mod session;
 pub mod secret;
 pub mod enums;
 pub mod namesp;
+pub mod ticket;
+pub mod redirect;
+pub mod authcode;
+pub mod refresh;
+pub mod consent;
 
 mod client;
 mod dir;
@@ -18,14 +23,15 @@ pub trait Gateway {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
     use std::time::SystemTime;
     use openssl::encrypt::Decrypter;
     use openssl::sign::Signer;
     use openssl::hash::MessageDigest;
     use openssl::pkey::PKey;
     use openssl::rsa::{Rsa, Padding};
-    use super::{enums, user, client, secret, app, session, namesp};
-    use crate::default::tests::{get_prefixed_data, DUMMY_DESCR, DUMMY_PWD};
+    use super::{enums, user, client, secret, app, session, namesp, ticket};
+    use crate::default::tests::{get_prefixed_data, DUMMY_DESCR, DUMMY_PWD, DUMMY_DEVICE, DUMMY_META};
 
     #[test]
     fn client_new_ok() {
@@ -225,6 +231,26 @@ mod tests {
         assert_eq!(&*decrypted, data);
     }
 
+    #[test]
+    fn ticket_new_ok() {
+        use std::time::Duration;
+        use crate::proto::TicketKind;
+        use super::ticket::Ctrl;
+
+        let timeout = Duration::from_secs(60);
+        let (mut ticket, secret) = ticket::Ticket::new(0, TicketKind::RestoreCredentials, timeout).unwrap();
+
+        assert_eq!(ticket.get_client_id(), 0);
+        assert_eq!(ticket.get_kind(), TicketKind::RestoreCredentials);
+        assert!(ticket.get_deadline() > SystemTime::now());
+        assert!(ticket.match_secret(secret.as_str()));
+        assert!(!ticket.match_secret(&format!("{}G", secret)));
+        assert!(ticket.is_alive().is_ok());
+
+        ticket.consume();
+        assert!(ticket.is_alive().is_err());
+    }
+
     #[test]
     fn namesp_new_ok() {
         use super::app::Ctrl as AppCtrl;
@@ -244,6 +270,8 @@ mod tests {
         let secret = secret::Secret::new(0, &name, &public).unwrap();
 
         let namesp = namesp::get_instance().new_namespace(app, secret).unwrap();
+
+        let namesp = namesp.lock();
         assert_eq!(namesp.get_id(), app_id);
         assert_eq!(namesp.get_label(), app_label);
 
@@ -290,7 +318,9 @@ mod tests {
         let secret = secret::Secret::new(0, &name, &public).unwrap();
 
         let np = namesp::get_instance().new_namespace(app, secret).unwrap();
-        let want_cookie = Token::new(8);
+
+        let mut np = np.lock();
+        let want_cookie = Token::new(crate::default::TOKEN_LEN);
         let want_token = want_cookie.clone();
 
         assert!(np.set_token(want_cookie.clone(), want_token.clone()).is_ok());
@@ -316,7 +346,9 @@ mod tests {
         let secret = secret::Secret::new(0, &name, &public).unwrap();
 
         let np = namesp::get_instance().new_namespace(app, secret).unwrap();
-        let want_cookie = Token::new(8);
+
+        let mut np = np.lock();
+        let want_cookie = Token::new(crate::default::TOKEN_LEN);
         let want_token = want_cookie.clone();
 
         assert!(np.set_token(want_cookie.clone(), want_token.clone()).is_ok());
@@ -335,10 +367,11 @@ mod tests {
         let before = SystemTime::now();
         let (name, email) = get_prefixed_data(PREFIX, false);
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
-        assert!(session::get_instance().get_by_email(user.get_email()).is_none());
+        assert!(session::get_instance().get_by_email(user.get_email()).unwrap().is_empty());
         
         let user_id = user.get_id();
-        let sess = session::get_instance().new_session(user).unwrap();
+        let shared = session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let sess = shared.lock();
         let after = SystemTime::now();
     
         assert_eq!(sess.get_user_id(), user_id);
@@ -348,16 +381,24 @@ mod tests {
         assert!(sess.match_pwd(DUMMY_PWD));
         
         assert_eq!(sess.get_status(), Status::New);
+
+        // only the digest of the session's token is kept, while the token can be told again
+        let token = sess.reopen().unwrap();
+        assert!(token.digest() == *sess.get_cookie());
+        assert!(token != *sess.get_cookie());
         
+        // all lookups must lead to the very same session
         let cookie = sess.get_cookie();
-        let sess = session::get_instance().get_by_cookie(cookie).unwrap();
-        assert_eq!(sess.get_user_id(), user_id);
+        let got = session::get_instance().get_by_cookie(cookie).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&got, &shared));
         
-        let sess = session::get_instance().get_by_email(&email).unwrap();
-        assert_eq!(sess.get_user_id(), user_id);
+        let got = session::get_instance().get_by_email(&email).unwrap();
+        assert_eq!(got.len(), 1);
+        assert!(Arc::ptr_eq(&got[0], &shared));
         
-        let sess = session::get_instance().get_by_name(&name).unwrap();
-        assert_eq!(sess.get_user_id(), user_id);
+        let got = session::get_instance().get_by_name(&name).unwrap();
+        assert_eq!(got.len(), 1);
+        assert!(Arc::ptr_eq(&got[0], &shared));
     }
     
     #[test]
@@ -367,27 +408,77 @@ mod tests {
     
         let (name, email) = get_prefixed_data(PREFIX, false);
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
-        assert!(session::get_instance().get_by_email(user.get_email()).is_none());
-        assert!(session::get_instance().new_session(user).is_ok());
+        assert!(session::get_instance().get_by_email(user.get_email()).unwrap().is_empty());
+        assert!(session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).is_ok());
     
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
-        assert!(session::get_instance().new_session(user).is_err());
+        assert!(session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).is_err());
+
+        // the very same user may have a session on another device
+        let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
+        assert!(session::get_instance().new_session(user, "another_device", &DUMMY_META).is_ok());
+        assert_eq!(session::get_instance().get_by_email(&email).unwrap().len(), 2);
     }
     
     #[test]
     fn session_destroy() {
+        use crate::proto::Status;
         const PREFIX: &str = "session_destroy";
     
         let (name, email) = get_prefixed_data(PREFIX, false);
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
         
-        let sess = session::get_instance().new_session(user).unwrap();
+        let shared = session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let mut sess = shared.lock();
         let cookie = sess.get_cookie().clone(); // if not cloned memory address gets invalid due the owner session has been deleted
     
-        assert!(session::get_instance().destroy_session(&cookie).is_ok());
-        assert!(session::get_instance().get_by_cookie(&cookie).is_none());
-        assert!(session::get_instance().get_by_name(&name).is_none());
-        assert!(session::get_instance().get_by_email(&email).is_none());
+        assert!(session::get_instance().destroy_session(&mut sess).is_ok());
+        assert_eq!(sess.get_status(), Status::Dead);
+        assert!(session::get_instance().get_by_name(&name).unwrap().is_empty());
+        assert!(session::get_instance().get_by_email(&email).unwrap().is_empty());
+
+        // the cookie still leads to the dead session for a while
+        let got = session::get_instance().get_by_cookie(&cookie).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&got, &shared));
+    }
+
+    #[test]
+    fn session_lifecycle() {
+        use std::time::Duration;
+        use super::session::Factory;
+        use crate::proto::Status;
+        use crate::default;
+        const PREFIX: &str = "session_lifecycle";
+
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let sessions = session::Provider::new(lifetime, idle, Box::new(session::memory::MemoryStore::new()));
+
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
+        let shared = sessions.new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let mut sess = shared.lock();
+        assert_eq!(sess.get_status(), Status::New);
+
+        // NEW -> ALIVE
+        sessions.touch_session(&mut sess).unwrap();
+        assert_eq!(sess.get_status(), Status::Alive);
+        sessions.touch_session(&mut sess).unwrap();
+        assert_eq!(sess.get_status(), Status::Alive);
+
+        // ALIVE -> DEAD
+        sessions.destroy_session(&mut sess).unwrap();
+        assert_eq!(sess.get_status(), Status::Dead);
+        assert!(sess.is_alive().is_err());
+
+        // DEAD is final, and it cannot be destroyed twice
+        sess.touch();
+        assert_eq!(sess.get_status(), Status::Dead);
+        assert!(sessions.destroy_session(&mut sess).is_err());
+
+        // the tombstone is kept until it gets too old
+        assert_eq!(sessions.purge_dead().unwrap(), 0);
+        assert!(sessions.get_by_cookie(sess.get_cookie()).unwrap().is_some());
     }
 
     #[test]
@@ -399,11 +490,12 @@ mod tests {
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
         let user_id = user.get_id();
         
-        let sess = session::get_instance().new_session(user).unwrap();
+        let sess = session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let mut sess = sess.lock();
     
         let app_id = 0_i32;
         let token = sess.new_directory(app_id).unwrap();
-        let dir = sess.get_directory(&token).unwrap();
+        let dir = sess.get_directory(&token.digest()).unwrap();
 
         assert_eq!(dir.get_user_id(), user_id);
         assert_eq!(dir.get_app_id(), app_id);
@@ -415,12 +507,115 @@ mod tests {
     
         let (name, email) = get_prefixed_data(PREFIX, false);
         let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
-        let sess = session::get_instance().new_session(user).unwrap();
+        let sess = session::get_instance().new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let mut sess = sess.lock();
     
         let want_app_id = 0_i32;
         let token = sess.new_directory(want_app_id).unwrap();
-        let got_app_id = sess.delete_directory(&token).unwrap();
+        let got_app_id = sess.delete_directory(&token.digest()).unwrap();
         assert_eq!(got_app_id, want_app_id);
-        assert!(sess.get_directory(&token).is_none());
+        assert!(sess.get_directory(&token.digest()).is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn session_idle_timeout() {
+        use std::thread;
+        use std::time::Duration;
+        use super::session::Factory;
+        use crate::default;
+        const PREFIX: &str = "session_idle_timeout";
+
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_millis(100);
+        let sessions = session::Provider::new(lifetime, idle, Box::new(session::memory::MemoryStore::new()));
+
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        let user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
+        let shared = sessions.new_session(user, DUMMY_DEVICE, &DUMMY_META).unwrap();
+        let mut sess = shared.lock();
+        assert!(sess.is_alive().is_ok());
+        assert!(sess.get_idle_deadline() < sess.get_deadline());
+        
+        thread::sleep(Duration::from_millis(150));
+        assert!(sess.is_alive().is_err());
+        assert_eq!(sessions.get_expired().unwrap().len(), 1);
+
+        // any use of the session slides its idle deadline, but never its lifetime
+        let deadline = sess.get_deadline();
+        sessions.touch_session(&mut sess).unwrap();
+        assert!(sess.is_alive().is_ok());
+        assert_eq!(sess.get_deadline(), deadline);
+        assert!(sessions.get_expired().unwrap().is_empty());
+    }
+
+    #[test]
+    fn session_postgres_store() {
+        use std::time::Duration;
+        use super::Gateway;
+        use super::app::Ctrl as AppCtrl;
+        use super::user::Ctrl as UserCtrl;
+        use super::session::Factory;
+        use crate::default;
+        crate::initialize();
+        const PREFIX: &str = "session_store";
+
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        let mut user = user::User::new(&name, &email, DUMMY_PWD).unwrap();
+        user.insert().unwrap();
+        let user_id = user.get_id();
+
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let mut app = app::App::new(&app_name, &url, DUMMY_DESCR).unwrap();
+        app.insert().unwrap();
+
+        // two providers on top of the same database behave as two replicas
+        let timeout = Duration::from_secs(default::SESSION_LIFETIME);
+        let replica_a = session::Provider::new(timeout, timeout, Box::new(session::postgres::PostgresStore::new(timeout, timeout)));
+        let replica_b = session::Provider::new(timeout, timeout, Box::new(session::postgres::PostgresStore::new(timeout, timeout)));
+
+        let meta = session::Metadata{
+            peer_addr: "127.0.0.1:50051".to_string(),
+            user_agent: "grpc-web-javascript/0.1".to_string(),
+            forwarded_for: "10.0.0.1".to_string(),
+        };
+
+        let shared = replica_a.new_session(user, DUMMY_DEVICE, &meta).unwrap();
+        let mut sess = shared.lock();
+        let token = replica_a.new_directory(&mut sess, app.get_id()).unwrap();
+        let cookie = sess.get_cookie().clone();
+
+        // any lookup on the same replica gets the very same handle, so its lock is shared as well
+        let again = replica_a.get_by_cookie(&cookie).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&shared, &again));
+        assert!(Arc::ptr_eq(&shared, &replica_a.get_by_email(&email).unwrap()[0]));
+
+        let got = replica_b.get_by_cookie(&cookie).unwrap().unwrap();
+        let got = got.lock();
+        assert_eq!(got.get_user_id(), user_id);
+        assert_eq!(got.get_token(app.get_id()).unwrap().as_str(), token.digest().as_str());
+        assert_eq!(got.get_metadata().peer_addr, meta.peer_addr);
+        assert_eq!(got.get_metadata().user_agent, meta.user_agent);
+        assert_eq!(got.get_metadata().forwarded_for, meta.forwarded_for);
+        assert!(!replica_b.get_by_email(&email).unwrap().is_empty());
+        assert!(!replica_b.get_by_name(&name).unwrap().is_empty());
+        assert_eq!(replica_b.get_dirs_by_app(app.get_id()).unwrap().len(), 1);
+
+        // a user cannot have two sessions, no matter the replica
+        let user = user::find_by_id(user_id).unwrap();
+        assert!(replica_b.new_session(user, DUMMY_DEVICE, &DUMMY_META).is_err());
+
+        assert_eq!(replica_b.delete_directory(&mut sess, &token.digest()).unwrap(), Some(app.get_id()));
+        assert!(replica_a.get_dirs_by_app(app.get_id()).unwrap().is_empty());
+
+        replica_b.destroy_session(&mut sess).unwrap();
+        assert!(replica_a.get_by_email(&email).unwrap().is_empty());
+        let got = replica_a.get_by_cookie(&cookie).unwrap().unwrap();
+        let mut got = got.lock();
+        assert_eq!(got.get_status(), crate::proto::Status::Dead);
+        assert_eq!(got.get_metadata().user_agent, meta.user_agent); // tombstones keep the client metadata
+        assert!(replica_a.destroy_session(&mut got).is_err());
+
+        app.delete().unwrap();
+        user::find_by_id(user_id).unwrap().delete().unwrap();
+    }
+}
@@ -1,20 +1,30 @@
 use std::collections::HashMap;
 use std::collections::hash_map;
 use std::error::Error;
+use std::sync::Arc;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use parking_lot::Mutex;
 use super::{app, secret};
 use crate::token::Token;
+use crate::error;
 
 const ERR_NO_NAMESPACE: &str = "Namespace not found";
 const ERR_NAMESPACE_ALREADY_EXISTS: &str = "The provided application already has an namespace";
-const ERR_NAMESPACE_BUILD: &str = "Something has failed while building namespace";
 const ERR_TOKEN_ALREADY_EXISTS: &str = "The namespace already has a dir for the provided token";
 const ERR_USER_HAS_DIR: &str = "User already has a directory in this namespace";
 
-static mut INSTANCE: Option<Box<dyn Factory>> = None;
+pub type Shared = Arc<Mutex<Box<dyn Ctrl>>>;
 
-pub trait Ctrl {
+lazy_static! {
+    static ref INSTANCE: Arc<dyn Factory> = Arc::new(Provider::new());
+}
+
+pub trait Ctrl: Send {
     fn get_id(&self) -> i32;
     fn get_label(&self) -> &str;
+    fn get_force_reauth(&self) -> bool;
+    fn get_scope(&self) -> Vec<String>;
     fn set_token(&mut self,  cookie: Token, dir: Token,) -> Result<(), Box<dyn Error>>;
     fn get_secret(&self) -> &Box<dyn secret::Ctrl>;
     fn delete_token(&mut self, cookie: &Token) -> Option<Token>;
@@ -22,94 +32,98 @@ pub trait Ctrl {
     fn get_dirs_iter(&self) -> hash_map::Iter<Token, Token>;
 }
 
-pub trait Factory {
-    fn new_namespace(&mut self, client: Box<dyn app::Ctrl>, secret: Box<dyn secret::Ctrl>) -> Result<&mut Box<dyn Ctrl>, Box<dyn Error>>;
-    fn get_by_label(&mut self, label: &str) -> Option<&mut Box<dyn Ctrl>>;
-    fn get_by_id(&mut self, app: i32) ->  Option<&mut Box<dyn Ctrl>>;
-    fn destroy_namespace(&mut self, label: &str) -> Result<(), Box<dyn Error>>;
+pub trait Factory: Send + Sync {
+    fn new_namespace(&self, client: Box<dyn app::Ctrl>, secret: Box<dyn secret::Ctrl>) -> Result<Shared, Box<dyn Error>>;
+    fn get_by_label(&self, label: &str) -> Option<Shared>;
+    fn get_by_id(&self, app: i32) ->  Option<Shared>;
+    fn destroy_namespace(&self, label: &str) -> Result<(), Box<dyn Error>>;
+    fn destroy_by_client(&self, client: i32) -> Result<Shared, Box<dyn Error>>;
 }
 
-pub fn get_instance<'a>() -> &'a mut Box<dyn Factory> {
-    let provider: &mut Option<Box<dyn Factory>>;
-    unsafe {
-        provider = &mut INSTANCE
-    }
-
-    match provider {
-        Some(ctrl) => {
-            ctrl
-        },
-        None => {
-            let instance = Provider::new();
-            
-            unsafe {
-                INSTANCE = Some(Box::new(instance));
-            }
-            
-            get_instance()
-        },
-    }
+pub fn get_instance() -> Arc<dyn Factory> {
+    INSTANCE.clone()
+}
+
+// the app's ids are kept apart from the namespace so no namespace has to be locked while looking for another one
+struct Record {
+    id: i32,
+    client_id: i32,
+    np: Shared,
 }
 
-struct Provider {
+pub struct Provider {
     // all app's namespaces sorted by app's label
-    allnp: HashMap<String, Box<dyn Ctrl>>,
+    allnp: DashMap<String, Record>,
 }
 
 impl Provider {
-    fn new() -> impl Factory {
+    pub fn new() -> impl Factory {
         Provider{
-            allnp: HashMap::new(),        
+            allnp: DashMap::new(),
         }
     }
 }
 
 impl Factory for Provider {
-    fn new_namespace(&mut self, app: Box<dyn app::Ctrl>, secret: Box<dyn secret::Ctrl>) -> Result<&mut Box<dyn Ctrl>, Box<dyn Error>> {
+    fn new_namespace(&self, app: Box<dyn app::Ctrl>, secret: Box<dyn secret::Ctrl>) -> Result<Shared, Box<dyn Error>> {
         let label = app.get_label().to_string();
+        match self.allnp.entry(label) {
+            Entry::Vacant(entry) => {
+                let id = app.get_id();
+                let client_id = app.get_client_id();
+                let np: Shared = Arc::new(Mutex::new(Box::new(Namespace::new(app, secret))));
+                entry.insert(Record{
+                    id,
+                    client_id,
+                    np: np.clone(),
+                });
 
-        if let None = self.allnp.get(&label) {
-            //let client_id = app.get_client_id();
-            //let secret = secret::find_by_client_and_name(client_id, default::RSA_NAME)?;
-            let np = Namespace::new(app, secret);
-
-            self.allnp.insert(label.clone(), Box::new(np));
-            if let Some(np) = self.allnp.get_mut(&label) {
                 Ok(np)
-            } else {
-                let msg = format!("{}", ERR_NAMESPACE_BUILD);
-                Err(msg.into())
-            }
+            },
 
-        } else {
-            // checking if there is already a session for the provided email
-            let msg = format!("{}", ERR_NAMESPACE_ALREADY_EXISTS);
-            Err(msg.into())
+            Entry::Occupied(_) => {
+                // checking if there is already a namespace for the provided app
+                let msg = ERR_NAMESPACE_ALREADY_EXISTS.to_string();
+                Err(error::Error::AlreadyExists(msg).into())
+            },
         }
     }
 
-    fn get_by_label(&mut self, label: &str) ->  Option<&mut Box<dyn Ctrl>> {
-        self.allnp.get_mut(label)
+    fn get_by_label(&self, label: &str) ->  Option<Shared> {
+        let record = self.allnp.get(label)?;
+        Some(record.np.clone())
     }
 
-    fn get_by_id(&mut self, target: i32) ->  Option<&mut Box<dyn Ctrl>> {
-        if let Some((_, np)) = self.allnp.iter_mut().find(|(_, np)| np.get_id() == target) {
-            Some(np)
-        } else {
-            None
-        }
+    fn get_by_id(&self, target: i32) ->  Option<Shared> {
+        self.allnp.iter()
+            .find(|record| record.id == target)
+            .map(|record| record.np.clone())
     }
 
-    fn destroy_namespace(&mut self, label: &str) -> Result<(), Box<dyn Error>> {
+    fn destroy_namespace(&self, label: &str) -> Result<(), Box<dyn Error>> {
         if let Some(_) = self.allnp.remove(label) {
             Ok(())
         } else {
             let msg = format!("{}", ERR_NO_NAMESPACE);
-            Err(msg.into())
+            Err(error::Error::NotFound(msg).into())
+        }
+    }
+
+    fn destroy_by_client(&self, client: i32) -> Result<Shared, Box<dyn Error>> {
+        let label = self.allnp.iter()
+            .find(|record| record.client_id == client)
+            .map(|record| record.key().clone());
+
+        if let Some((_, record)) = label.and_then(|label| self.allnp.remove(&label)) {
+            Ok(record.np)
+        } else {
+            let msg = ERR_NO_NAMESPACE.to_string();
+            Err(error::Error::NotFound(msg).into())
         }
     }
 }
 
+
 struct Namespace {
     app: Box<dyn app::Ctrl>,
     public: Box<dyn secret::Ctrl>,
@@ -135,17 +149,25 @@ impl Ctrl for Namespace {
         self.app.get_label()
     }
 
+    fn get_force_reauth(&self) -> bool {
+        self.app.get_force_reauth()
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        self.app.get_scope()
+    }
+
     fn get_secret(&self) -> &Box<dyn secret::Ctrl> {
         &self.public
     }
 
     fn set_token(&mut self,  cookie: Token, dir: Token) -> Result<(), Box<dyn Error>> {
         if let Some(_) = self.dirs.get(&cookie) {
-            return Err(ERR_TOKEN_ALREADY_EXISTS.into());
+            return Err(error::Error::AlreadyExists(ERR_TOKEN_ALREADY_EXISTS.into()).into());
         }
 
         if let Some(_) = self.dirs.iter().find(|(_, d)| d.as_str() == dir.to_string()) {
-            return Err(ERR_USER_HAS_DIR.into());
+            return Err(error::Error::AlreadyExists(ERR_USER_HAS_DIR.into()).into());
         }
 
         self.dirs.insert(cookie, dir);
@@ -0,0 +1,51 @@
+use std::error::Error;
+use crate::schema::redirects;
+use crate::diesel::prelude::*;
+use crate::postgres::*;
+
+#[derive(Insertable)]
+#[table_name="redirects"]
+struct NewRedirect<'a> {
+    pub app_id: i32,
+    pub uri: &'a str,
+}
+
+// find_by_app returns all the redirect uris the app has registered
+pub fn find_by_app(target: i32) -> Result<Vec<String>, Box<dyn Error>> {
+    use crate::schema::redirects::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        redirects.filter(app_id.eq(target))
+            .select(uri)
+            .load::<String>(&connection)?
+    };
+
+    Ok(results)
+}
+
+// replace_all sets the given uris as the only ones the app may be redirected to
+pub fn replace_all(target: i32, uris: &[String]) -> Result<(), Box<dyn Error>> {
+    use crate::schema::redirects::dsl::*;
+
+    let new_redirects: Vec<NewRedirect> = uris.iter()
+        .map(|value| NewRedirect{
+            app_id: target,
+            uri: value,
+        })
+        .collect();
+
+    { // block is required because of connection release
+        let connection = open_stream().get()?;
+        connection.transaction::<_, diesel::result::Error, _>(|| {
+            diesel::delete(redirects.filter(app_id.eq(target)))
+                .execute(&connection)?;
+            diesel::insert_into(redirects)
+                .values(&new_redirects)
+                .execute(&connection)?;
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
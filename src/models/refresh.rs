@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::time::SystemTime;
+use diesel::NotFound;
+use crate::schema::refreshtokens;
+use crate::diesel::prelude::*;
+use crate::postgres::*;
+use crate::token::Token;
+use crate::default;
+use crate::error;
+use crate::oauth;
+
+const ERR_TOKEN_ROTATED: &str = "The refresh token has already been used";
+const ERR_DEADLINE_EXCEEDED: &str = "The refresh token has expired";
+
+pub trait Ctrl {
+    fn get_family(&self) -> &str;
+    fn get_client_id(&self) -> i32;
+    fn get_app_id(&self) -> i32;
+    fn get_cookie(&self) -> Token;
+    fn get_device(&self) -> &str;
+    fn get_deadline(&self) -> SystemTime;
+    fn get_scope(&self) -> Vec<String>;
+    fn is_rotated(&self) -> bool;
+    fn is_alive(&self) -> Result<(), Box<dyn Error>>;
+    fn rotate(&mut self);
+}
+
+// find_by_token returns the refresh token, which is looked up by its digest
+pub fn find_by_token(target: &Token) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::refreshtokens::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        refreshtokens.filter(token.eq(target.digest().as_str()))
+            .load::<RefreshToken>(&connection)?
+    };
+
+    if !results.is_empty() {
+        Ok(Box::new(results[0].clone()))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
+// delete_by_family removes the whole family of refresh tokens, returning the sessions any of them was issued for
+pub fn delete_by_family(target: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    use crate::schema::refreshtokens::dsl::*;
+
+    let mut cookies = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            refreshtokens.filter(family.eq(target))
+        )
+        .returning(cookie)
+        .get_results::<String>(&connection)?
+    };
+
+    cookies.sort();
+    cookies.dedup();
+    Ok(cookies.iter().map(|value| Token::from_string(value)).collect())
+}
+
+// delete_by_cookie removes all the refresh tokens issued for the session, or just those of the given app if any
+pub fn delete_by_cookie(target: &Token, app: Option<i32>) -> Result<usize, Box<dyn Error>> {
+    use crate::schema::refreshtokens::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        match app {
+            Some(target_app) => diesel::delete(
+                refreshtokens.filter(cookie.eq(target.as_str()))
+                    .filter(app_id.eq(target_app))
+            ).execute(&connection)?,
+
+            None => diesel::delete(
+                refreshtokens.filter(cookie.eq(target.as_str()))
+            ).execute(&connection)?,
+        }
+    };
+
+    Ok(deleted)
+}
+
+// delete_by_client removes all the refresh tokens issued to the app on behalf of the user, no matter the session
+pub fn delete_by_client(target_client: i32, target_app: i32) -> Result<usize, Box<dyn Error>> {
+    use crate::schema::refreshtokens::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            refreshtokens.filter(client_id.eq(target_client))
+                .filter(app_id.eq(target_app))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+// delete_expired removes all the refresh tokens past their deadline, whether rotated or not
+pub fn delete_expired() -> Result<usize, Box<dyn Error>> {
+    use crate::schema::refreshtokens::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            refreshtokens.filter(deadline.lt(SystemTime::now()))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+#[derive(Insertable)]
+#[derive(Identifiable)]
+#[derive(Queryable)]
+#[derive(Clone)]
+#[table_name="refreshtokens"]
+#[primary_key(token)]
+pub struct RefreshToken {
+    pub token: String,
+    pub family: String,
+    pub client_id: i32,
+    pub app_id: i32,
+    pub cookie: String,
+    pub device: String,
+    pub created_at: SystemTime,
+    pub deadline: SystemTime,
+    pub rotated_at: Option<SystemTime>,
+    pub scope: String,
+}
+
+impl RefreshToken {
+    // the returned token is the refresh token itself, the only moment it is available as plain text; a token with
+    // no family starts a brand new one
+    pub fn new(family: Option<&str>, client_id: i32, app_id: i32, cookie: &Token, device: &str, scope: &[String], deadline: SystemTime) -> (Box<impl Ctrl + super::Gateway>, Token) {
+        let token = Token::new(default::TOKEN_LEN);
+        let family = match family {
+            Some(family) => family.to_string(),
+            None => Token::new(default::TOKEN_LEN).to_string(),
+        };
+
+        let refresh = RefreshToken {
+            token: token.digest().to_string(),
+            family,
+            client_id,
+            app_id,
+            cookie: cookie.to_string(),
+            device: device.to_string(),
+            created_at: SystemTime::now(),
+            deadline,
+            rotated_at: None,
+            scope: scope.join(" "),
+        };
+
+        (Box::new(refresh), token)
+    }
+}
+
+impl Ctrl for RefreshToken {
+    fn get_family(&self) -> &str {
+        &self.family
+    }
+
+    fn get_client_id(&self) -> i32 {
+        self.client_id
+    }
+
+    fn get_app_id(&self) -> i32 {
+        self.app_id
+    }
+
+    fn get_cookie(&self) -> Token {
+        Token::from_string(&self.cookie)
+    }
+
+    fn get_device(&self) -> &str {
+        &self.device
+    }
+
+    fn get_deadline(&self) -> SystemTime {
+        self.deadline
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        oauth::split_scope(&self.scope)
+    }
+
+    fn is_rotated(&self) -> bool {
+        self.rotated_at.is_some()
+    }
+
+    fn is_alive(&self) -> Result<(), Box<dyn Error>> {
+        if self.deadline < SystemTime::now() {
+            return Err(error::Error::DeadlineExceeded(ERR_DEADLINE_EXCEEDED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) {
+        self.rotated_at = Some(SystemTime::now());
+    }
+}
+
+impl super::Gateway for RefreshToken {
+    fn select(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("".into())
+    }
+
+    fn insert(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(refreshtokens::table)
+            .values(&*self)
+            .get_result::<RefreshToken>(&connection)?
+        };
+
+        self.created_at = result.created_at;
+        Ok(())
+    }
+
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::refreshtokens::dsl::*;
+
+        let updated = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            // a token can be rotated just once, no matter how many requests are racing for it
+            diesel::update(
+                refreshtokens.filter(token.eq(&self.token))
+                    .filter(rotated_at.is_null())
+            )
+            .set(rotated_at.eq(self.rotated_at))
+            .execute(&connection)?
+        };
+
+        if updated == 0 {
+            return Err(error::Error::PermissionDenied(ERR_TOKEN_ROTATED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::refreshtokens::dsl::*;
+
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                refreshtokens.filter(
+                    token.eq(&self.token)
+                )
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
+}
@@ -10,13 +10,24 @@ use crate::schema::secrets;
 use crate::postgres::*;
 use crate::diesel::prelude::*;
 use crate::regex::*;
+use crate::error;
 
-pub trait Ctrl {
+const ERR_SEALED_CHANGED: &str = "The secret has changed in the meanwhile";
+
+pub trait Ctrl: Send {
     fn get_client_id(&self) -> i32;
     fn get_verifier(&self) -> Result<Verifier, Box<dyn Error>>;
     fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
 }
 
+// SealedCtrl is the controller of those secrets holding data this service has encrypted for itself, rather than a public key
+pub trait SealedCtrl: Send {
+    fn get_client_id(&self) -> i32;
+    fn get_document(&self) -> &str;
+    fn set_document(&mut self, document: &str);
+    fn get_deadline(&self) -> Option<SystemTime>;
+}
+
 pub fn _find_all_by_client(target_id: i32) -> Result<Vec<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
     use crate::schema::secrets::dsl::*;
     let results = { // block is required because of connection release
@@ -38,6 +49,25 @@ pub fn _find_all_by_client(target_id: i32) -> Result<Vec<impl Ctrl + super::Gate
     }
 }
 
+// find_all_by_client_and_prefix returns all the client's public keys whose name starts with the given prefix
+pub fn find_all_by_client_and_prefix(target_id: i32, prefix: &str) -> Result<Vec<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::secrets::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        secrets.filter(client_id.eq(target_id))
+        .filter(name.like(format!("{}%", prefix)))
+        .load::<Secret>(&connection)?
+    };
+
+    let mut wrappers = Vec::with_capacity(results.len());
+    for secret in results.iter() {
+        wrappers.push(secret.build()?);
+    }
+
+    Ok(wrappers)
+}
+
 pub fn find_by_client_and_name(target_id: i32, target_name: &str) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
     use crate::schema::secrets::dsl::*;
 
@@ -56,6 +86,55 @@ pub fn find_by_client_and_name(target_id: i32, target_name: &str) -> Result<Box<
     }
 }
 
+pub fn find_sealed_by_client_and_name(target_id: i32, target_name: &str) -> Result<Box<impl SealedCtrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::secrets::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        secrets.filter(client_id.eq(target_id))
+        .filter(name.eq(target_name))
+        .load::<Secret>(&connection)?
+    };
+
+    if !results.is_empty() {
+        Ok(Box::new(Sealed{
+            stored: results[0].document.clone(),
+            secret: results[0].clone(),
+        }))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
+// delete_by_client_and_prefix removes all the client's secrets whose name starts with the given prefix
+pub fn delete_by_client_and_prefix(target_id: i32, prefix: &str) -> Result<usize, Box<dyn Error>> {
+    use crate::schema::secrets::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            secrets.filter(client_id.eq(target_id))
+                .filter(name.like(format!("{}%", prefix)))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+// delete_expired removes all the secrets past their deadline and returns the clients they belonged to
+pub fn delete_expired() -> Result<Vec<i32>, Box<dyn Error>> {
+    use crate::schema::secrets::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            secrets.filter(deadline.lt(SystemTime::now()))
+        ).get_results::<Secret>(&connection)?
+    };
+
+    Ok(results.iter().map(|secret| secret.client_id).collect())
+}
+
 #[derive(Insertable)]
 #[derive(Identifiable)]
 #[derive(Queryable)]
@@ -101,6 +180,25 @@ impl Secret {
         Ok(Box::new(wrapper))
     }
 
+    // the document must have been sealed already, since it is stored as it comes
+    pub fn new_sealed(client_id: i32, name: &str, document: &str, deadline: Option<SystemTime>) -> Result<Box<impl SealedCtrl + super::Gateway>, Box<dyn Error>> {
+        match_name(name)?;
+
+        let secret = Secret {
+            id: 0,
+            client_id,
+            name: name.to_string(),
+            document: document.to_string(),
+            created_at: SystemTime::now(),
+            deadline,
+        };
+
+        Ok(Box::new(Sealed{
+            stored: document.to_string(),
+            secret,
+        }))
+    }
+
     fn build(&self) -> Result<Wrapper, Box<dyn Error>> {
         let public = Rsa::public_key_from_pem(self.document.as_bytes())?;
         let public = PKey::from_rsa(public)?;
@@ -110,6 +208,52 @@ impl Secret {
             pkey: public,
         })
     }
+
+    fn insert_row(&mut self) -> Result<(), Box<dyn Error>> {
+        let new_secret = NewSecret {
+            client_id: self.client_id,
+            name: &self.name,
+            document: &self.document,
+            deadline: self.deadline,
+        };
+
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(secrets::table)
+            .values(&new_secret)
+            .get_result::<Secret>(&connection)?
+        };
+
+        self.id = result.id;
+        self.created_at = result.created_at;
+        Ok(())
+    }
+
+    fn update_row(&self) -> Result<(), Box<dyn Error>> {
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::update(self)
+            .set((secrets::document.eq(&self.document), secrets::deadline.eq(self.deadline)))
+            .execute(&connection)?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_row(&self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::secrets::dsl::*;
+
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                secrets.filter(
+                    id.eq(self.id)
+                )
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
 }
 
 pub struct Wrapper {
@@ -148,48 +292,100 @@ impl super::Gateway for Wrapper {
     }
     
     fn insert(&mut self) -> Result<(), Box<dyn Error>> {
-        let new_secret = NewSecret {
-            client_id: self.secret.client_id,
-            name: &self.secret.name,
-            document: &self.secret.document,
-            deadline: self.secret.deadline,
-        };
-
-        let result = { // block is required because of connection release
-            let connection = open_stream().get()?;
-            diesel::insert_into(secrets::table)
-            .values(&new_secret)
-            .get_result::<Secret>(&connection)?
-        };
-
-        self.secret.id = result.id;
-        self.secret.created_at = result.created_at;
-        Ok(())
+        self.secret.insert_row()
     }
     
     fn update(&mut self) -> Result<(), Box<dyn Error>> {
-        { // block is required because of connection release
+        self.secret.update_row()
+    }
+    
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        self.secret.delete_row()
+    }
+}
+
+pub struct Sealed {
+    secret: Secret,
+    stored: String, // the document as it is in the database, so no change made by someone else gets overwritten
+}
+
+impl Sealed {
+    // update_row stores the current document as long as the one in the database is the one this secret was built from
+    fn update_row(&mut self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::secrets::dsl::*;
+
+        let updated = { // block is required because of connection release
             let connection = open_stream().get()?;
-            diesel::update(&self.secret)
-            .set(secrets::deadline.eq(self.secret.deadline))
-            .execute(&connection)?;
+            diesel::update(
+                secrets.filter(id.eq(self.secret.id))
+                    .filter(document.eq(&self.stored))
+            )
+            .set((document.eq(&self.secret.document), deadline.eq(self.secret.deadline)))
+            .execute(&connection)?
+        };
+
+        if updated == 0 {
+            return Err(error::Error::PermissionDenied(ERR_SEALED_CHANGED.into()).into());
         }
 
+        self.stored = self.secret.document.clone();
         Ok(())
     }
-    
-    fn delete(&self) -> Result<(), Box<dyn Error>> {
+
+    // delete_row removes the secret as long as it has not changed since it was built
+    fn delete_row(&self) -> Result<(), Box<dyn Error>> {
         use crate::schema::secrets::dsl::*;
 
-        { // block is required because of connection release
+        let deleted = { // block is required because of connection release
             let connection = open_stream().get()?;
             diesel::delete(
-                secrets.filter(
-                    id.eq(self.secret.id)
-                )
-            ).execute(&connection)?;
+                secrets.filter(id.eq(self.secret.id))
+                    .filter(document.eq(&self.stored))
+            ).execute(&connection)?
+        };
+
+        if deleted == 0 {
+            return Err(error::Error::PermissionDenied(ERR_SEALED_CHANGED.into()).into());
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl SealedCtrl for Sealed {
+    fn get_client_id(&self) -> i32 {
+        self.secret.client_id
+    }
+
+    fn get_document(&self) -> &str {
+        &self.secret.document
+    }
+
+    fn set_document(&mut self, document: &str) {
+        self.secret.document = document.to_string();
+    }
+
+    fn get_deadline(&self) -> Option<SystemTime> {
+        self.secret.deadline
+    }
+}
+
+impl super::Gateway for Sealed {
+    fn select(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("".into())
+    }
+    
+    fn insert(&mut self) -> Result<(), Box<dyn Error>> {
+        self.secret.insert_row()?;
+        self.stored = self.secret.document.clone();
+        Ok(())
+    }
+    
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        self.update_row()
+    }
+    
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        self.delete_row()
+    }
+}
@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::SystemTime;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use parking_lot::Mutex;
+use crate::token::Token;
+use crate::proto::Status;
+use crate::error;
+use super::{Ctrl, Shared, SessionStore};
+
+pub const BACKEND_NAME: &str = "memory";
+
+// the email, name and device are kept apart from the session so no session has to be locked while looking for another one
+struct Record {
+    email: String,
+    name: String,
+    device: String,
+    created_at: SystemTime,
+    touch_at: SystemTime,
+    sess: Shared,
+}
+
+// a tombstone keeps a dead session until it gets purged
+struct Tombstone {
+    died_at: SystemTime,
+    sess: Shared,
+}
+
+pub struct MemoryStore {
+    allsess: DashMap<Token, Record>, // session's digest to its record, the token itself is never kept
+    devices: DashMap<(String, String), Token>, // user's email & device to session's digest
+    emails: DashMap<String, Vec<Token>>,
+    names: DashMap<String, Vec<Token>>,
+    dirs: DashMap<(Token, Token), i32>, // session's & dir token's digests to app id
+    tombstones: DashMap<Token, Tombstone>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore{
+            allsess: DashMap::new(),
+            devices: DashMap::new(),
+            emails: DashMap::new(),
+            names: DashMap::new(),
+            dirs: DashMap::new(),
+            tombstones: DashMap::new(),
+        }
+    }
+
+    fn find_by_index(&self, index: &DashMap<String, Vec<Token>>, key: &str) -> Vec<Shared> {
+        let tokens = match index.get(key) {
+            Some(tokens) => tokens.value().clone(),
+            None => return Vec::new(),
+        };
+
+        tokens.iter()
+            .filter_map(|token| self.allsess.get(token).map(|record| record.sess.clone()))
+            .collect()
+    }
+
+    fn unindex(index: &DashMap<String, Vec<Token>>, key: &str, token: &Token) {
+        if let Some(mut tokens) = index.get_mut(key) {
+            tokens.retain(|value| value != token);
+        }
+
+        index.remove_if(key, |_, tokens| tokens.is_empty());
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn insert(&self, sess: Box<dyn Ctrl>) -> Result<Shared, Box<dyn Error>> {
+        let email = sess.get_email().to_string();
+        let name = sess.get_name().to_string();
+        let device = sess.get_device().to_string();
+        let token = sess.get_cookie().clone();
+        let touch_at = sess.get_touch_at();
+
+        // the device's entry is held until the session is registered, so two racing logins cannot both succeed
+        let entry = match self.devices.entry((email.clone(), device.clone())) {
+            Entry::Vacant(entry) => entry,
+            Entry::Occupied(_) => {
+                // checking if there is already a session for the provided email and device
+                let msg = format!("{} {}", super::ERR_SESSION_ALREADY_EXISTS, email);
+                return Err(error::Error::AlreadyExists(msg).into());
+            },
+        };
+
+        if self.allsess.contains_key(&token) {
+            return Err(error::Error::AlreadyExists(super::ERR_TOKEN_EXISTS.into()).into());
+        }
+
+        let sess: Shared = Arc::new(Mutex::new(sess));
+        self.allsess.insert(token.clone(), Record{
+            email: email.clone(),
+            name: name.clone(),
+            device,
+            created_at: token.get_created_at(),
+            touch_at,
+            sess: sess.clone(),
+        });
+
+        self.emails.entry(email).or_default().push(token.clone());
+        self.names.entry(name).or_default().push(token.clone());
+        entry.insert(token);
+        Ok(sess)
+    }
+
+    fn find_by_cookie(&self, token: &Token) -> Result<Option<Shared>, Box<dyn Error>> {
+        if let Some(record) = self.allsess.get(token) {
+            return Ok(Some(record.sess.clone()));
+        }
+
+        Ok(self.tombstones.get(token).map(|tombstone| tombstone.sess.clone()))
+    }
+
+    fn find_by_email(&self, email: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        Ok(self.find_by_index(&self.emails, email))
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        Ok(self.find_by_index(&self.names, name))
+    }
+
+    fn remove(&self, token: &Token) -> Result<(), Box<dyn Error>> {
+        if let Some((_, record)) = self.allsess.remove(token) {
+            self.devices.remove_if(&(record.email.clone(), record.device.clone()), |_, value| value == token);
+            MemoryStore::unindex(&self.emails, &record.email, token);
+            MemoryStore::unindex(&self.names, &record.name, token);
+            self.dirs.retain(|(cookie, _), _| cookie != token);
+            self.tombstones.insert(token.clone(), Tombstone{
+                died_at: SystemTime::now(),
+                sess: record.sess,
+            });
+
+            Ok(())
+        } else {
+            Err(error::Error::NotFound(super::ERR_COOKIE_NOT_FOUND.into()).into())
+        }
+    }
+
+    fn insert_dir(&self, cookie: &Token, token: &Token, app: i32, _: SystemTime) -> Result<(), Box<dyn Error>> {
+        self.dirs.insert((cookie.clone(), token.clone()), app);
+        Ok(())
+    }
+
+    fn remove_dir(&self, cookie: &Token, token: &Token) -> Result<(), Box<dyn Error>> {
+        self.dirs.remove(&(cookie.clone(), token.clone()));
+        Ok(())
+    }
+
+    fn find_dirs_by_app(&self, app: i32) -> Result<Vec<(Token, Token)>, Box<dyn Error>> {
+        let dirs = self.dirs.iter()
+            .filter(|entry| *entry.value() == app)
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        Ok(dirs)
+    }
+
+    fn touch(&self, cookie: &Token, _: Status, touch_at: SystemTime) -> Result<(), Box<dyn Error>> {
+        if let Some(mut record) = self.allsess.get_mut(cookie) {
+            record.touch_at = touch_at;
+            Ok(())
+        } else {
+            Err(error::Error::NotFound(super::ERR_COOKIE_NOT_FOUND.into()).into())
+        }
+    }
+
+    fn find_expired(&self, created_before: SystemTime, touched_before: SystemTime) -> Result<Vec<Shared>, Box<dyn Error>> {
+        let sessions = self.allsess.iter()
+            .filter(|record| record.created_at < created_before || record.touch_at < touched_before)
+            .map(|record| record.sess.clone())
+            .collect();
+
+        Ok(sessions)
+    }
+
+    fn purge_tombstones(&self, died_before: SystemTime) -> Result<usize, Box<dyn Error>> {
+        let mut purged = 0;
+        self.tombstones.retain(|_, tombstone| {
+            let keep = tombstone.died_at >= died_before;
+            if !keep {
+                purged += 1;
+            }
+
+            keep
+        });
+
+        Ok(purged)
+    }
+}
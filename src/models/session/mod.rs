@@ -0,0 +1,447 @@
+pub mod memory;
+pub mod postgres;
+
+use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::net::IpAddr;
+use std::env;
+use parking_lot::Mutex;
+use crate::token::Token;
+use crate::proto::Status;
+use crate::default;
+use crate::cookie;
+use super::{user, dir};
+use super::dir::Ctrl as DirCtrl;
+use crate::error;
+
+const ERR_UNKNOWN_STORE: &str = "Unknown session store";
+const ERR_DEADLINE_EXCEEDED: &str = "Deadline exceeded";
+const ERR_IDLE_EXCEEDED: &str = "Session has been idle for too long";
+const ERR_SESSION_DEAD: &str = "Session has already been closed";
+const ERR_SESSION_ALREADY_EXISTS: &str = "A session already exists for the device of client";
+const ERR_COOKIE_NOT_FOUND: &str = "No session has been found for the provided cookie";
+const ERR_TOKEN_EXISTS: &str = "Provided token already exists";
+const ERR_APP_ALREADY_EXISTS: &str = "Application already has a directory for this user";
+const ERR_TRUSTED_PROXIES: &str = "Trusted proxies must be formatted as a comma separated list of ip addresses";
+
+// a shared session may be held by many requests at once, but only one of them can change it at a time
+pub type Shared = Arc<Mutex<Box<dyn Ctrl>>>;
+
+lazy_static! {
+    static ref INSTANCE: Arc<dyn Factory> = {
+        let lifetime = env::var(default::ENV_SESSION_LIFETIME).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::SESSION_LIFETIME);
+
+        let idle = env::var(default::ENV_SESSION_IDLE_TIMEOUT).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::SESSION_IDLE_TIMEOUT);
+
+        let lifetime = Duration::from_secs(lifetime);
+        let idle = Duration::from_secs(idle);
+        let backend = env::var(default::ENV_SESSION_STORE)
+            .unwrap_or(default::SESSION_STORE.to_string());
+
+        let store: Box<dyn SessionStore> = match backend.as_str() {
+            memory::BACKEND_NAME => Box::new(memory::MemoryStore::new()),
+            postgres::BACKEND_NAME => Box::new(postgres::PostgresStore::new(lifetime, idle)),
+            _ => panic!("{} {}", ERR_UNKNOWN_STORE, backend),
+        };
+
+        Arc::new(Provider::new(lifetime, idle, store))
+    };
+
+    // only these proxies are trusted to tell the address of the client they are forwarding the request of
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = env::var(default::ENV_TRUSTED_PROXIES).ok()
+        .map(|value| value.split(',')
+            .map(|addr| addr.trim())
+            .filter(|addr| !addr.is_empty())
+            .map(|addr| addr.parse().expect(ERR_TRUSTED_PROXIES))
+            .collect())
+        .unwrap_or_default();
+}
+
+// Metadata describes the client a session has been opened from, as told by the transport
+#[derive(Clone, Default)]
+pub struct Metadata {
+    pub peer_addr: String,
+    pub user_agent: String,
+    pub forwarded_for: String,
+}
+
+impl Metadata {
+    // client_addr is the address of the client itself, as told by the trusted proxies in between, if any
+    pub fn client_addr(&self) -> &str {
+        self.client_addr_behind(&TRUSTED_PROXIES)
+    }
+
+    // client_addr_behind trusts no forwarded address but those appended by the given proxies, since anyone else could
+    // have forged them; so the closest address not belonging to a trusted proxy is the client's one
+    fn client_addr_behind(&self, trusted: &[IpAddr]) -> &str {
+        let is_trusted = |addr: &str| addr.parse::<IpAddr>()
+            .map(|addr| trusted.contains(&addr))
+            .unwrap_or(false);
+
+        let mut client = match self.peer_addr.rfind(':') {
+            Some(index) => self.peer_addr[..index].trim_start_matches('[').trim_end_matches(']'),
+            None => &self.peer_addr,
+        };
+
+        for addr in self.forwarded_for.rsplit(',').map(|addr| addr.trim()).filter(|addr| !addr.is_empty()) {
+            if !is_trusted(client) {
+                break;
+            }
+
+            client = addr;
+        }
+
+        client
+    }
+}
+
+pub trait Ctrl: Send {
+    fn get_client_id(&self) -> i32;
+    fn get_cookie(&self) -> &Token;
+    fn reopen(&self) -> Option<Token>;
+    fn get_touch_at(&self) -> SystemTime;
+    fn get_deadline(&self) -> SystemTime;
+    fn get_idle_deadline(&self) -> SystemTime;
+    fn get_status(&self) -> Status;
+    fn get_email(&self) -> &str;
+    fn get_name(&self) -> &str;
+    fn get_device(&self) -> &str;
+    fn get_metadata(&self) -> &Metadata;
+    fn get_user_id(&self) -> i32;
+    fn get_token(&self,  app: i32) -> Option<&Token>;
+    fn get_open_dirs(&self) -> Vec<Token>;
+    fn get_directory(&self, token: &Token) -> Option<Box<&dyn dir::Ctrl>>;
+    fn new_directory(&mut self, app: i32) -> Result<Token, Box<dyn Error>>;
+    fn reopen_directory(&self, app: i32) -> Option<Token>;
+    fn delete_directory(&mut self, token: &Token) -> Option<i32>;
+    fn match_pwd(&self, pwd: &str) -> bool;
+    fn is_alive(&self) -> Result<(), Box<dyn Error>>;
+    fn touch(&mut self);
+    fn kill(&mut self);
+}
+
+// Factory only knows about the digests of session and directory tokens, so any cookie the user tells has to be digested
+// before looking its session up; the tokens themselves are derived again for the user to hold
+pub trait Factory: Send + Sync {
+    fn new_session(&self, client: Box<dyn user::Ctrl>, device: &str, meta: &Metadata) -> Result<Shared, Box<dyn Error>>;
+    fn get_by_cookie(&self, token: &Token) -> Result<Option<Shared>, Box<dyn Error>>;
+    fn get_by_email(&self, addr: &str) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn get_by_name(&self, name: &str) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn destroy_session(&self, sess: &mut Box<dyn Ctrl>) -> Result<(), Box<dyn Error>>;
+    fn new_directory(&self, sess: &mut Box<dyn Ctrl>, app: i32) -> Result<Token, Box<dyn Error>>;
+    fn delete_directory(&self, sess: &mut Box<dyn Ctrl>, token: &Token) -> Result<Option<i32>, Box<dyn Error>>;
+    fn get_dirs_by_app(&self, app: i32) -> Result<Vec<(Token, Token)>, Box<dyn Error>>;
+    fn touch_session(&self, sess: &mut Box<dyn Ctrl>) -> Result<(), Box<dyn Error>>;
+    fn get_expired(&self) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn purge_dead(&self) -> Result<usize, Box<dyn Error>>;
+}
+
+// SessionStore keeps the sessions and their directories, so they may outlive the process that opened them
+pub trait SessionStore: Send + Sync {
+    fn insert(&self, sess: Box<dyn Ctrl>) -> Result<Shared, Box<dyn Error>>;
+    fn find_by_cookie(&self, cookie: &Token) -> Result<Option<Shared>, Box<dyn Error>>;
+    fn find_by_email(&self, email: &str) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn find_by_name(&self, name: &str) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn remove(&self, cookie: &Token) -> Result<(), Box<dyn Error>>; // leaves a tombstone behind
+    fn insert_dir(&self, cookie: &Token, token: &Token, app: i32, opened_at: SystemTime) -> Result<(), Box<dyn Error>>;
+    fn remove_dir(&self, cookie: &Token, token: &Token) -> Result<(), Box<dyn Error>>;
+    fn find_dirs_by_app(&self, app: i32) -> Result<Vec<(Token, Token)>, Box<dyn Error>>;
+    fn touch(&self, cookie: &Token, status: Status, touch_at: SystemTime) -> Result<(), Box<dyn Error>>;
+    fn find_expired(&self, created_before: SystemTime, touched_before: SystemTime) -> Result<Vec<Shared>, Box<dyn Error>>;
+    fn purge_tombstones(&self, died_before: SystemTime) -> Result<usize, Box<dyn Error>>;
+}
+
+pub fn get_instance() -> Arc<dyn Factory> {
+    INSTANCE.clone()
+}
+
+pub struct Provider {
+    lifetime: Duration,
+    idle: Duration,
+    store: Box<dyn SessionStore>,
+}
+
+impl Provider {
+    pub fn new(lifetime: Duration, idle: Duration, store: Box<dyn SessionStore>) -> impl Factory {
+        Provider{
+            lifetime,
+            idle,
+            store,
+        }
+    }
+
+}
+
+impl Factory for Provider {
+    fn new_session(&self, user: Box<dyn user::Ctrl>, device: &str, meta: &Metadata) -> Result<Shared, Box<dyn Error>> {
+        // [Testing] sess.get_user_id() == user.get_id() fails when non-inserted users are used: foreach id == 0
+        // the moment is kept as precise as the store is, so the token can be derived again once restored
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let created_at = UNIX_EPOCH + Duration::from_micros(now.as_micros() as u64);
+        let token = cookie::session_token(user.get_id(), device, created_at)?;
+        let sess = Session::new(user, device, meta.clone(), token.digest(), self.lifetime, self.idle);
+        self.store.insert(Box::new(sess))
+    }
+
+    fn get_by_cookie(&self, token: &Token) -> Result<Option<Shared>, Box<dyn Error>> {
+        self.store.find_by_cookie(token)
+    }
+
+    fn get_by_email(&self, email: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        self.store.find_by_email(email)
+    }
+
+    fn get_by_name(&self, name: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        self.store.find_by_name(name)
+    }
+
+    fn destroy_session(&self, sess: &mut Box<dyn Ctrl>) -> Result<(), Box<dyn Error>> {
+        sess.kill();
+        self.store.remove(sess.get_cookie())
+    }
+
+    fn new_directory(&self, sess: &mut Box<dyn Ctrl>, app: i32) -> Result<Token, Box<dyn Error>> {
+        let token = sess.new_directory(app)?;
+        let opened_at = sess.get_directory(&token.digest())
+            .map(|dir| dir.get_opened_at())
+            .unwrap_or_else(SystemTime::now);
+
+        if let Err(err) = self.store.insert_dir(sess.get_cookie(), &token.digest(), app, opened_at) {
+            // the session must not keep a directory the store knows nothing about
+            sess.delete_directory(&token.digest());
+            return Err(err);
+        }
+
+        Ok(token)
+    }
+
+    fn delete_directory(&self, sess: &mut Box<dyn Ctrl>, token: &Token) -> Result<Option<i32>, Box<dyn Error>> {
+        let app = sess.delete_directory(token);
+        if app.is_some() {
+            self.store.remove_dir(sess.get_cookie(), token)?;
+        }
+
+        Ok(app)
+    }
+
+    fn get_dirs_by_app(&self, app: i32) -> Result<Vec<(Token, Token)>, Box<dyn Error>> {
+        self.store.find_dirs_by_app(app)
+    }
+
+    fn touch_session(&self, sess: &mut Box<dyn Ctrl>) -> Result<(), Box<dyn Error>> {
+        sess.touch();
+        self.store.touch(sess.get_cookie(), sess.get_status(), sess.get_touch_at())
+    }
+
+    fn get_expired(&self) -> Result<Vec<Shared>, Box<dyn Error>> {
+        // a session expires once it has outlived its lifetime or has been idle for too long
+        let now = SystemTime::now();
+        self.store.find_expired(now - self.lifetime, now - self.idle)
+    }
+
+    fn purge_dead(&self) -> Result<usize, Box<dyn Error>> {
+        // dead sessions are only kept for a while, so late callers know what happened to them
+        let ttl = Duration::from_secs(default::SESSION_TOMBSTONE);
+        self.store.purge_tombstones(SystemTime::now() - ttl)
+    }
+}
+
+struct Session {
+    cookie: Token, // the digest of the session's token, which identifies the session while its directory tokens prove it
+    touch_at: SystemTime,
+    status: Status,
+    user: Box<dyn user::Ctrl>,
+    device: String,
+    meta: Metadata,
+    dirs: HashMap<Token, dir::Dir>, // token's digest & app label
+    lifetime: Duration,
+    idle: Duration,
+}
+
+impl Session {
+    pub fn new(user: Box<dyn user::Ctrl>, device: &str, meta: Metadata, cookie: Token, lifetime: Duration, idle: Duration) -> impl Ctrl {
+        Session{
+            cookie,
+            touch_at: SystemTime::now(),
+            status: Status::New,
+            user,
+            device: device.to_string(),
+            meta,
+            dirs: HashMap::new(),
+            lifetime,
+            idle,
+        }
+    }
+}
+
+impl Ctrl for Session {
+    fn get_client_id(&self) -> i32 {
+        self.user.get_client_id()
+    }
+
+    fn get_user_id(&self) -> i32 {
+        self.user.get_id()
+    }
+
+    fn get_cookie(&self) -> &Token {
+        &self.cookie
+    }
+
+    fn reopen(&self) -> Option<Token> {
+        // the token cannot be told again once the key it was derived from is no longer the current one
+        cookie::session_token(self.user.get_id(), &self.device, self.cookie.get_created_at()).ok()
+            .filter(|token| token.digest() == self.cookie)
+    }
+
+    fn get_email(&self) -> &str {
+        self.user.get_email()
+    }
+
+    fn get_name(&self) -> &str {
+        self.user.get_name()
+    }
+
+    fn get_device(&self) -> &str {
+        &self.device
+    }
+
+    fn get_metadata(&self) -> &Metadata {
+        &self.meta
+    }
+
+    fn get_touch_at(&self) -> SystemTime {
+        self.touch_at
+    }
+
+    fn get_deadline(&self) -> SystemTime {
+        self.cookie.get_created_at() + self.lifetime
+    }
+
+    fn get_idle_deadline(&self) -> SystemTime {
+        self.touch_at + self.idle
+    }
+
+    fn get_status(&self) -> Status {
+        self.status
+    }
+
+    fn get_open_dirs(&self) -> Vec<Token> {
+        self.dirs.iter().map(|(token, _)| (token.clone())).collect()
+    }
+
+    fn get_token(&self, app: i32) -> Option<&Token> {
+        if let Some((token, _)) = self.dirs.iter().find(|(_, dir)| dir.get_app_id() == app) {
+            Some(token)
+        } else {
+            None
+        }
+    }
+
+    fn delete_directory(&mut self, token: &Token) -> Option<i32> {
+        if let Some(dir) = self.dirs.remove(token) {
+            Some(dir.get_app_id())
+        } else {
+            None
+        }
+    }
+
+    fn new_directory(&mut self, app: i32) -> Result<Token, Box<dyn Error>> {
+        if let Some(_) = self.dirs.iter().find(|(_, dir)| dir.get_app_id() == app) {
+            return Err(error::Error::AlreadyExists(ERR_APP_ALREADY_EXISTS.into()).into());
+        }
+
+        // the moment is kept as precise as the store is, so the token can be derived again once restored
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let opened_at = UNIX_EPOCH + Duration::from_micros(now.as_micros() as u64);
+        let token = cookie::directory_token(&self.cookie, app, opened_at)?;
+        if self.dirs.contains_key(&token.digest()) {
+            return Err(error::Error::AlreadyExists(ERR_TOKEN_EXISTS.into()).into());
+        }
+
+        // only the digest is kept, the token itself is for the user to hold
+        let dir = dir::Dir::new(self.user.get_id(), app, opened_at);
+        self.dirs.insert(token.digest(), dir);
+        Ok(token)
+    }
+
+    fn reopen_directory(&self, app: i32) -> Option<Token> {
+        let (digest, dir) = self.dirs.iter().find(|(_, dir)| dir.get_app_id() == app)?;
+        // the token cannot be told again once the key it was derived from is no longer the current one
+        cookie::directory_token(&self.cookie, app, dir.get_opened_at()).ok()
+            .filter(|token| token.digest() == *digest)
+    }
+
+    fn get_directory(&self, token: &Token) -> Option<Box<&dyn dir::Ctrl>> {
+        if let Some(dir) = self.dirs.get(&token) {
+            Some(Box::new(dir))
+        } else {
+            None
+        }
+    }
+
+    fn match_pwd(&self, pwd: &str) -> bool {
+        self.user.match_pwd(pwd)
+    }
+
+    fn is_alive(&self) -> Result<(), Box<dyn Error>> {
+        let now = SystemTime::now();
+        if self.status == Status::Dead {
+            Err(error::Error::Unauthenticated(ERR_SESSION_DEAD.into()).into())
+        } else if self.get_deadline() < now {
+            Err(error::Error::DeadlineExceeded(ERR_DEADLINE_EXCEEDED.into()).into())
+        } else if self.get_idle_deadline() < now {
+            Err(error::Error::DeadlineExceeded(ERR_IDLE_EXCEEDED.into()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // a session is NEW until it gets used again, and ALIVE from then on until it gets killed
+    fn touch(&mut self) {
+        if self.status == Status::Dead {
+            return;
+        }
+
+        self.touch_at = SystemTime::now();
+        self.status = Status::Alive;
+    }
+
+    fn kill(&mut self) {
+        self.status = Status::Dead;
+        self.dirs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_client_addr() {
+        let trusted: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        let meta = Metadata{
+            peer_addr: "10.0.0.1:50051".to_string(),
+            user_agent: String::new(),
+            forwarded_for: "1.1.1.1, 2.2.2.2, 10.0.0.2".to_string(),
+        };
+
+        // the forged address on the left is never reached
+        assert_eq!(meta.client_addr_behind(&trusted), "2.2.2.2");
+        // while no forwarded address is told by an untrusted peer
+        assert_eq!(meta.client_addr_behind(&[]), "10.0.0.1");
+
+        let meta = Metadata{
+            peer_addr: "[::1]:50051".to_string(),
+            user_agent: String::new(),
+            forwarded_for: "1.1.1.1".to_string(),
+        };
+
+        assert_eq!(meta.client_addr_behind(&trusted), "::1");
+    }
+}
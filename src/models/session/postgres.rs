@@ -0,0 +1,382 @@
+use std::error::Error;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use std::collections::HashMap;
+use diesel::result::{Error as DieselError, DatabaseErrorKind};
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use parking_lot::Mutex;
+use crate::schema::{sessions, directories, tombstones, users, clients};
+use crate::diesel::prelude::*;
+use crate::postgres::*;
+use crate::token::Token;
+use crate::proto::Status;
+use crate::models::{user, dir};
+use crate::error;
+use super::{Ctrl, Shared, Session, SessionStore, Metadata};
+
+pub const BACKEND_NAME: &str = "postgres";
+
+const ERR_UNKNOWN_STATUS: &str = "Unknown session status";
+
+#[derive(Insertable)]
+#[derive(Queryable)]
+#[table_name="sessions"]
+struct SessionRow {
+    pub cookie: String,
+    pub user_id: i32,
+    pub status: i32,
+    pub created_at: SystemTime,
+    pub touch_at: SystemTime,
+    pub device: String,
+    pub peer_addr: String,
+    pub user_agent: String,
+    pub forwarded_for: String,
+}
+
+#[derive(Insertable)]
+#[derive(Queryable)]
+#[table_name="tombstones"]
+struct TombstoneRow {
+    pub cookie: String,
+    pub user_id: i32,
+    pub created_at: SystemTime,
+    pub died_at: SystemTime,
+    pub device: String,
+    pub peer_addr: String,
+    pub user_agent: String,
+    pub forwarded_for: String,
+}
+
+#[derive(Queryable)]
+struct DirRow {
+    pub _cookie: String,
+    pub token: String,
+    pub app_id: i32,
+    pub created_at: SystemTime,
+}
+
+#[derive(Insertable)]
+#[table_name="directories"]
+struct NewDir<'a> {
+    pub cookie: &'a str,
+    pub token: &'a str,
+    pub app_id: i32,
+    pub created_at: SystemTime,
+}
+
+// PostgresStore shares the sessions among all the replicas; a session nobody is holding is got as it is in the database,
+// while any other lookup gets the very same handle, so the requests of this replica cannot change it at once
+pub struct PostgresStore {
+    lifetime: Duration,
+    idle: Duration,
+    handles: DashMap<String, Weak<Mutex<Box<dyn Ctrl>>>>, // session's digest to the handle in use, if any
+}
+
+impl PostgresStore {
+    pub fn new(lifetime: Duration, idle: Duration) -> Self {
+        PostgresStore{
+            lifetime,
+            idle,
+            handles: DashMap::new(),
+        }
+    }
+
+    // share returns the handle in use for the session of the given row, if any, or builds a brand new one otherwise
+    fn share(&self, row: SessionRow) -> Result<Shared, Box<dyn Error>> {
+        match self.handles.entry(row.cookie.clone()) {
+            Entry::Occupied(mut entry) => {
+                if let Some(sess) = entry.get().upgrade() {
+                    return Ok(sess);
+                }
+
+                let sess = self.build_row(row)?;
+                entry.insert(Arc::downgrade(&sess));
+                Ok(sess)
+            },
+
+            Entry::Vacant(entry) => {
+                let sess = self.build_row(row)?;
+                entry.insert(Arc::downgrade(&sess));
+                Ok(sess)
+            },
+        }
+    }
+
+    fn build(&self, results: Vec<SessionRow>) -> Result<Vec<Shared>, Box<dyn Error>> {
+        let mut sessions = Vec::with_capacity(results.len());
+        for row in results {
+            sessions.push(self.share(row)?);
+        }
+
+        Ok(sessions)
+    }
+
+    fn build_row(&self, row: SessionRow) -> Result<Shared, Box<dyn Error>> {
+        let status = match Status::from_i32(row.status) {
+            Some(status) => status,
+            None => return Err(error::Error::Internal(ERR_UNKNOWN_STATUS.into()).into()),
+        };
+
+        let user = user::find_by_id(row.user_id)?;
+        let dirs = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            directories::table.filter(directories::cookie.eq(&row.cookie))
+                .load::<DirRow>(&connection)?
+        };
+
+        let dirs: HashMap<Token, dir::Dir> = dirs.iter()
+            .map(|dir| (Token::from_string(&dir.token), dir::Dir::new(row.user_id, dir.app_id, dir.created_at)))
+            .collect();
+
+        let sess: Box<dyn Ctrl> = Box::new(Session{
+            cookie: Token::restore(&row.cookie, row.created_at),
+            touch_at: row.touch_at,
+            status,
+            user,
+            device: row.device,
+            meta: Metadata{
+                peer_addr: row.peer_addr,
+                user_agent: row.user_agent,
+                forwarded_for: row.forwarded_for,
+            },
+            dirs,
+            lifetime: self.lifetime,
+            idle: self.idle,
+        });
+
+        Ok(Arc::new(Mutex::new(sess)))
+    }
+
+    fn build_tombstone(&self, row: TombstoneRow) -> Result<Shared, Box<dyn Error>> {
+        let user = user::find_by_id(row.user_id)?;
+        let sess: Box<dyn Ctrl> = Box::new(Session{
+            cookie: Token::restore(&row.cookie, row.created_at),
+            touch_at: row.died_at,
+            status: Status::Dead,
+            user,
+            device: row.device,
+            meta: Metadata{
+                peer_addr: row.peer_addr,
+                user_agent: row.user_agent,
+                forwarded_for: row.forwarded_for,
+            },
+            dirs: HashMap::new(),
+            lifetime: self.lifetime,
+            idle: self.idle,
+        });
+
+        Ok(Arc::new(Mutex::new(sess)))
+    }
+}
+
+impl SessionStore for PostgresStore {
+    fn insert(&self, sess: Box<dyn Ctrl>) -> Result<Shared, Box<dyn Error>> {
+        let row = SessionRow{
+            cookie: sess.get_cookie().to_string(),
+            user_id: sess.get_user_id(),
+            status: sess.get_status() as i32,
+            created_at: sess.get_cookie().get_created_at(),
+            touch_at: sess.get_touch_at(),
+            device: sess.get_device().to_string(),
+            peer_addr: sess.get_metadata().peer_addr.clone(),
+            user_agent: sess.get_metadata().user_agent.clone(),
+            forwarded_for: sess.get_metadata().forwarded_for.clone(),
+        };
+
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(sessions::table)
+                .values(&row)
+                .execute(&connection)
+        };
+
+        match result {
+            Ok(_) => {
+                let cookie = sess.get_cookie().to_string();
+                let sess = Arc::new(Mutex::new(sess));
+                self.handles.insert(cookie, Arc::downgrade(&sess));
+                Ok(sess)
+            },
+            Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                // checking if there is already a session for the provided email and device
+                let msg = format!("{} {}", super::ERR_SESSION_ALREADY_EXISTS, sess.get_email());
+                Err(error::Error::AlreadyExists(msg).into())
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn find_by_cookie(&self, token: &Token) -> Result<Option<Shared>, Box<dyn Error>> {
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            sessions::table.filter(sessions::cookie.eq(token.as_str()))
+                .load::<SessionRow>(&connection)?
+        };
+
+        if let Some(row) = results.into_iter().next() {
+            return Ok(Some(self.share(row)?));
+        }
+
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            tombstones::table.filter(tombstones::cookie.eq(token.as_str()))
+                .load::<TombstoneRow>(&connection)?
+        };
+
+        match results.into_iter().next() {
+            Some(row) => Ok(Some(self.build_tombstone(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn find_by_email(&self, email: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            sessions::table.inner_join(users::table)
+                .filter(users::email.eq(email))
+                .select(sessions::all_columns)
+                .load::<SessionRow>(&connection)?
+        };
+
+        self.build(results)
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Vec<Shared>, Box<dyn Error>> {
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            sessions::table.inner_join(users::table.inner_join(clients::table))
+                .filter(clients::name.eq(name))
+                .select(sessions::all_columns)
+                .load::<SessionRow>(&connection)?
+        };
+
+        self.build(results)
+    }
+
+    fn remove(&self, token: &Token) -> Result<(), Box<dyn Error>> {
+        let deleted = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            connection.transaction::<_, DieselError, _>(|| {
+                let deleted = diesel::delete(sessions::table.filter(sessions::cookie.eq(token.as_str())))
+                    .get_results::<SessionRow>(&connection)?;
+
+                let tombstones: Vec<TombstoneRow> = deleted.iter()
+                    .map(|row| TombstoneRow{
+                        cookie: row.cookie.clone(),
+                        user_id: row.user_id,
+                        created_at: row.created_at,
+                        died_at: SystemTime::now(),
+                        device: row.device.clone(),
+                        peer_addr: row.peer_addr.clone(),
+                        user_agent: row.user_agent.clone(),
+                        forwarded_for: row.forwarded_for.clone(),
+                    })
+                    .collect();
+
+                diesel::insert_into(tombstones::table)
+                    .values(&tombstones)
+                    .execute(&connection)?;
+
+                Ok(deleted.len())
+            })?
+        };
+
+        self.handles.remove(token.as_str());
+        if deleted == 0 {
+            return Err(error::Error::NotFound(super::ERR_COOKIE_NOT_FOUND.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn insert_dir(&self, cookie: &Token, token: &Token, app: i32, opened_at: SystemTime) -> Result<(), Box<dyn Error>> {
+        let new_dir = NewDir{
+            cookie: cookie.as_str(),
+            token: token.as_str(),
+            app_id: app,
+            created_at: opened_at,
+        };
+
+        let result = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::insert_into(directories::table)
+                .values(&new_dir)
+                .execute(&connection)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            // another replica may have opened a directory for the same app in the meanwhile
+            Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) =>
+                Err(error::Error::AlreadyExists(super::ERR_APP_ALREADY_EXISTS.into()).into()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn remove_dir(&self, cookie: &Token, token: &Token) -> Result<(), Box<dyn Error>> {
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                directories::table
+                    .filter(directories::cookie.eq(cookie.as_str()))
+                    .filter(directories::token.eq(token.as_str()))
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
+
+    fn find_dirs_by_app(&self, app: i32) -> Result<Vec<(Token, Token)>, Box<dyn Error>> {
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            directories::table.filter(directories::app_id.eq(app))
+                .select((directories::cookie, directories::token))
+                .load::<(String, String)>(&connection)?
+        };
+
+        let dirs = results.iter()
+            .map(|(cookie, token)| (Token::from_string(cookie), Token::from_string(token)))
+            .collect();
+
+        Ok(dirs)
+    }
+
+    fn touch(&self, cookie: &Token, status: Status, touch_at: SystemTime) -> Result<(), Box<dyn Error>> {
+        let updated = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::update(sessions::table.filter(sessions::cookie.eq(cookie.as_str())))
+                .set((sessions::status.eq(status as i32), sessions::touch_at.eq(touch_at)))
+                .execute(&connection)?
+        };
+
+        if updated == 0 {
+            return Err(error::Error::NotFound(super::ERR_COOKIE_NOT_FOUND.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn find_expired(&self, created_before: SystemTime, touched_before: SystemTime) -> Result<Vec<Shared>, Box<dyn Error>> {
+        // the sweep is a good moment to forget about those handles no one is holding anymore
+        self.handles.retain(|_, handle| handle.strong_count() > 0);
+
+        let results = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            sessions::table.filter(sessions::created_at.lt(created_before).or(sessions::touch_at.lt(touched_before)))
+                .load::<SessionRow>(&connection)?
+        };
+
+        self.build(results)
+    }
+
+    fn purge_tombstones(&self, died_before: SystemTime) -> Result<usize, Box<dyn Error>> {
+        let purged = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(tombstones::table.filter(tombstones::died_at.lt(died_before)))
+                .execute(&connection)?
+        };
+
+        Ok(purged)
+    }
+}
@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+use diesel::NotFound;
+use crate::schema::tickets;
+use crate::diesel::prelude::*;
+use diesel::pg::PgConnection;
+use crate::postgres::*;
+use crate::token::Token;
+use crate::proto::TicketKind;
+use crate::default;
+use crate::error;
+use crate::oauth;
+
+const ERR_UNKNOWN_KIND: &str = "Unknown ticket kind";
+const ERR_TICKET_CONSUMED: &str = "The ticket has already been consumed";
+const ERR_DEADLINE_EXCEEDED: &str = "Deadline exceeded";
+
+pub trait Ctrl {
+    fn get_id(&self) -> &str;
+    fn get_client_id(&self) -> i32;
+    fn get_kind(&self) -> TicketKind;
+    fn get_deadline(&self) -> SystemTime;
+    fn get_app_id(&self) -> Option<i32>;
+    fn get_scope(&self) -> Vec<String>;
+    fn match_secret(&self, secret: &str) -> bool;
+    fn is_alive(&self) -> Result<(), Box<dyn Error>>;
+    fn consume(&mut self);
+    fn bind(&mut self, app_id: i32, scope: &[String]);
+    // insert_along inserts the ticket along whatever the given function stores, so either both persist or none
+    fn insert_along<T, F>(&mut self, along: F) -> Result<T, Box<dyn Error>>
+        where F: FnOnce(&PgConnection) -> Result<T, Box<dyn Error>>, Self: Sized;
+}
+
+pub fn find_by_id(target: &str) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::tickets::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        tickets.filter(id.eq(target))
+            .load::<Ticket>(&connection)?
+    };
+
+    if !results.is_empty() {
+        let wrapper = results[0].build()?;
+        Ok(Box::new(wrapper))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
+// delete_by_client_and_kind removes all the tickets of the given kind the client has, no matter their state
+pub fn delete_by_client_and_kind(target_id: i32, target_kind: TicketKind) -> Result<usize, Box<dyn Error>> {
+    use crate::schema::tickets::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            tickets.filter(client_id.eq(target_id))
+                .filter(kind.eq(target_kind as i32))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+// delete_spent removes all the tickets that either have been consumed or are past their deadline, since none of them
+// can be resolved anymore
+pub fn delete_spent() -> Result<usize, Box<dyn Error>> {
+    use crate::schema::tickets::dsl::*;
+
+    let deleted = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        diesel::delete(
+            tickets.filter(consumed_at.is_not_null().or(deadline.lt(SystemTime::now())))
+        ).execute(&connection)?
+    };
+
+    Ok(deleted)
+}
+
+#[derive(Insertable)]
+#[derive(Identifiable)]
+#[derive(Queryable)]
+#[derive(Clone)]
+#[table_name="tickets"]
+pub struct Ticket {
+    pub id: String,
+    pub client_id: i32,
+    pub kind: i32,
+    pub secret: String,
+    pub created_at: SystemTime,
+    pub deadline: SystemTime,
+    pub consumed_at: Option<SystemTime>,
+    pub app_id: Option<i32>,
+    pub scope: String,
+}
+
+#[derive(Insertable)]
+#[table_name="tickets"]
+struct NewTicket<'a> {
+    pub id: &'a str,
+    pub client_id: i32,
+    pub kind: i32,
+    pub secret: &'a str,
+    pub deadline: SystemTime,
+    pub app_id: Option<i32>,
+    pub scope: &'a str,
+}
+
+impl Ticket {
+    // the returned token is the ticket's secret, the only moment it is available as plain text; being as random as any
+    // other token, just its digest is kept
+    pub fn new(client_id: i32, kind: TicketKind, timeout: Duration) -> Result<(Box<impl Ctrl + super::Gateway>, Token), Box<dyn Error>> {
+        let secret = Token::new(default::TOKEN_LEN);
+        let ticket = Ticket {
+            id: Token::new(default::TOKEN_LEN).to_string(),
+            client_id,
+            kind: kind as i32,
+            secret: secret.digest().to_string(),
+            created_at: SystemTime::now(),
+            deadline: SystemTime::now() + timeout,
+            consumed_at: None,
+            app_id: None,
+            scope: String::new(),
+        };
+
+        let wrapper = ticket.build()?;
+        Ok((Box::new(wrapper), secret))
+    }
+
+    fn build(&self) -> Result<Wrapper, Box<dyn Error>> {
+        let kind = match TicketKind::from_i32(self.kind) {
+            Some(kind) => kind,
+            None => return Err(ERR_UNKNOWN_KIND.into()),
+        };
+
+        Ok(Wrapper{
+            ticket: self.clone(),
+            kind,
+        })
+    }
+}
+
+pub struct Wrapper {
+    ticket: Ticket,
+    kind: TicketKind,
+}
+
+impl Ctrl for Wrapper {
+    fn get_id(&self) -> &str {
+        &self.ticket.id
+    }
+
+    fn get_client_id(&self) -> i32 {
+        self.ticket.client_id
+    }
+
+    fn get_kind(&self) -> TicketKind {
+        self.kind
+    }
+
+    fn get_deadline(&self) -> SystemTime {
+        self.ticket.deadline
+    }
+
+    fn get_app_id(&self) -> Option<i32> {
+        self.ticket.app_id
+    }
+
+    fn get_scope(&self) -> Vec<String> {
+        oauth::split_scope(&self.ticket.scope)
+    }
+
+    fn match_secret(&self, secret: &str) -> bool {
+        Token::from_string(secret).digest() == Token::from_string(&self.ticket.secret)
+    }
+
+    fn is_alive(&self) -> Result<(), Box<dyn Error>> {
+        if self.ticket.consumed_at.is_some() {
+            return Err(error::Error::PermissionDenied(ERR_TICKET_CONSUMED.into()).into());
+        }
+
+        if self.ticket.deadline < SystemTime::now() {
+            return Err(error::Error::DeadlineExceeded(ERR_DEADLINE_EXCEEDED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn consume(&mut self) {
+        self.ticket.consumed_at = Some(SystemTime::now());
+    }
+
+    // bind restricts the ticket to the given app and scopes, so it cannot be resolved for any other
+    fn bind(&mut self, app_id: i32, scope: &[String]) {
+        self.ticket.app_id = Some(app_id);
+        self.ticket.scope = scope.join(" ");
+    }
+
+    fn insert_along<T, F>(&mut self, along: F) -> Result<T, Box<dyn Error>>
+        where F: FnOnce(&PgConnection) -> Result<T, Box<dyn Error>> {
+
+        let connection = open_stream().get()?;
+        connection.transaction::<T, Box<dyn Error>, _>(|| {
+            self.insert_row(&connection)?;
+            along(&connection)
+        })
+    }
+}
+
+impl Wrapper {
+    fn insert_row(&mut self, connection: &PgConnection) -> Result<(), Box<dyn Error>> {
+        let new_ticket = NewTicket {
+            id: &self.ticket.id,
+            client_id: self.ticket.client_id,
+            kind: self.ticket.kind,
+            secret: &self.ticket.secret,
+            deadline: self.ticket.deadline,
+            app_id: self.ticket.app_id,
+            scope: &self.ticket.scope,
+        };
+
+        let result = diesel::insert_into(tickets::table)
+            .values(&new_ticket)
+            .get_result::<Ticket>(connection)?;
+
+        self.ticket.created_at = result.created_at;
+        Ok(())
+    }
+}
+
+impl super::Gateway for Wrapper {
+    fn select(&mut self) -> Result<(), Box<dyn Error>> {
+        Err("".into())
+    }
+
+    fn insert(&mut self) -> Result<(), Box<dyn Error>> {
+        let connection = open_stream().get()?;
+        self.insert_row(&connection)
+    }
+
+    fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::tickets::dsl::*;
+
+        let updated = { // block is required because of connection release
+            let connection = open_stream().get()?;
+            // a ticket can be consumed just once, no matter how many requests are racing for it
+            diesel::update(
+                tickets.filter(id.eq(&self.ticket.id))
+                    .filter(consumed_at.is_null())
+            )
+            .set(consumed_at.eq(self.ticket.consumed_at))
+            .execute(&connection)?
+        };
+
+        if updated == 0 {
+            return Err(error::Error::PermissionDenied(ERR_TICKET_CONSUMED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<(), Box<dyn Error>> {
+        use crate::schema::tickets::dsl::*;
+
+        { // block is required because of connection release
+            let connection = open_stream().get()?;
+            diesel::delete(
+                tickets.filter(
+                    id.eq(&self.ticket.id)
+                )
+            ).execute(&connection)?;
+        }
+
+        Ok(())
+    }
+}
@@ -2,23 +2,30 @@
 
 use diesel::NotFound;
 use std::error::Error;
+use std::time::SystemTime;
 use crate::models::client;
 use crate::models::enums;
 use crate::regex::*;
 use crate::diesel::prelude::*;
 use crate::postgres::*;
 use crate::schema::users;
+use crate::hasher;
 use super::client::{Ctrl as ClientCtrl, Wrapper as ClientWp, Client};
 extern crate diesel;
 
 const ERR_IDENT_NOT_MATCH: &str = "The provided indentity is not of the expected type";
 
-pub trait Ctrl {
+pub trait Ctrl: Send {
     fn get_client_id(&self) -> i32;
     fn get_id(&self) -> i32;
     fn get_email(&self) -> &str;
     fn get_name(&self) -> &str;
+    fn get_status(&self) -> enums::Status;
+    fn set_status(&mut self, status: enums::Status);
+    fn get_created_at(&self) -> SystemTime;
     fn match_pwd(&self, pwd: &str) -> bool;
+    fn needs_rehash(&self) -> bool;
+    fn set_pwd(&mut self, pwd: &str) -> Result<(), Box<dyn Error>>;
 }
 
 pub fn find_by_id(target: i32) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
@@ -81,6 +88,24 @@ pub fn find_by_email<'a>(target: &'a str) -> Result<Box<impl Ctrl + super::Gatew
     }
 }
 
+pub fn find_by_client_id(target: i32) -> Result<Box<impl Ctrl + super::Gateway>, Box<dyn Error>>  {
+    use crate::schema::users::dsl::*;
+
+    let results = { // block is required because of connection release
+        let connection = open_stream().get()?;
+        users.filter(client_id.eq(target))
+            .load::<User>(&connection)?
+    };
+
+    if !results.is_empty() {
+        let client = client::find_by_id(results[0].client_id)?;
+        let wrapper = results[0].build(client)?;
+        Ok(Box::new(wrapper))
+    } else {
+        Err(Box::new(NotFound))
+    }
+}
+
 #[derive(Queryable, Insertable, Associations)]
 #[derive(Identifiable)]
 #[belongs_to(Client<'_>)]
@@ -111,7 +136,7 @@ impl User {
             id: 0,
             client_id: 0,
             email: email.to_string(),
-            pwd: pwd.to_string(),
+            pwd: hasher::get_instance().hash(pwd)?,
         };
 
         let wrapper = user.build(client)?;
@@ -148,8 +173,30 @@ impl Ctrl for Wrapper {
         self.client.get_name()
     }
 
+    fn get_status(&self) -> enums::Status {
+        self.client.get_status()
+    }
+
+    fn set_status(&mut self, status: enums::Status) {
+        self.client.set_status(status)
+    }
+
+    fn get_created_at(&self) -> SystemTime {
+        self.client.created_at()
+    }
+
     fn match_pwd(&self, pwd: &str) -> bool {
-        self.user.pwd == pwd
+        hasher::get_instance().verify(pwd, &self.user.pwd)
+    }
+
+    fn needs_rehash(&self) -> bool {
+        hasher::get_instance().needs_rehash(&self.user.pwd)
+    }
+
+    fn set_pwd(&mut self, pwd: &str) -> Result<(), Box<dyn Error>> {
+        match_pwd(pwd)?;
+        self.user.pwd = hasher::get_instance().hash(pwd)?;
+        Ok(())
     }
 }
 
@@ -180,6 +227,8 @@ impl super::Gateway for Wrapper {
     }
     
     fn update(&mut self) -> Result<(), Box<dyn Error>> {
+        self.client.update()?;
+
         { // block is required because of connection release
             let connection = open_stream().get()?;
             diesel::update(&self.user)
@@ -0,0 +1,196 @@
+use std::error::Error;
+use openssl::memcmp;
+use openssl::sha::sha256;
+use url::Url;
+use crate::error;
+use crate::jwt;
+
+// error codes as defined by RFC 6749, sections 4.1.2.1 and 5.2
+pub const INVALID_REQUEST: &str = "invalid_request";
+pub const INVALID_CLIENT: &str = "invalid_client";
+pub const INVALID_GRANT: &str = "invalid_grant";
+pub const UNSUPPORTED_GRANT_TYPE: &str = "unsupported_grant_type";
+pub const UNSUPPORTED_RESPONSE_TYPE: &str = "unsupported_response_type";
+pub const ACCESS_DENIED: &str = "access_denied";
+pub const SERVER_ERROR: &str = "server_error";
+pub const TEMPORARILY_UNAVAILABLE: &str = "temporarily_unavailable";
+pub const INVALID_SCOPE: &str = "invalid_scope";
+
+// error codes as defined by RFC 6750, section 3.1
+pub const INVALID_TOKEN: &str = "invalid_token";
+pub const INSUFFICIENT_SCOPE: &str = "insufficient_scope";
+
+pub const RESPONSE_TYPE_CODE: &str = "code";
+pub const GRANT_TYPE_CODE: &str = "authorization_code";
+pub const GRANT_TYPE_REFRESH: &str = "refresh_token";
+pub const TOKEN_TYPE_BEARER: &str = "Bearer";
+pub const PKCE_METHOD_S256: &str = "S256"; // the plain method is not supported, as RFC 7636 recommends
+
+// client authentication as defined by RFC 7523, section 2.2, and OpenID Connect Core, section 9
+pub const CLIENT_ASSERTION_TYPE_JWT: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+pub const AUTH_METHOD_PRIVATE_KEY_JWT: &str = "private_key_jwt";
+
+// error codes as defined by OpenID Connect Core, section 3.1.2.6
+pub const CONSENT_REQUIRED: &str = "consent_required";
+
+// scopes as defined by OpenID Connect Core, section 5.4
+pub const SCOPE_OPENID: &str = "openid";
+pub const SCOPE_PROFILE: &str = "profile";
+pub const SCOPE_EMAIL: &str = "email";
+pub const SUPPORTED_SCOPES: &[&str] = &[SCOPE_OPENID, SCOPE_PROFILE, SCOPE_EMAIL];
+
+pub const PATH_AUTHORIZE: &str = "/authorize";
+pub const PATH_LOGIN: &str = "/login";
+pub const PATH_TOKEN: &str = "/token";
+pub const PATH_USERINFO: &str = "/userinfo";
+pub const PATH_JWKS: &str = "/.well-known/jwks.json";
+pub const PATH_DISCOVERY: &str = "/.well-known/openid-configuration";
+
+const MIN_VERIFIER_LEN: usize = 43; // as required by RFC 7636, section 4.1
+const MAX_VERIFIER_LEN: usize = 128;
+const MAX_REDIRECT_LEN: usize = 256;
+const MAX_SCOPE_LEN: usize = 256;
+
+const ERR_REDIRECT_FORMAT: &str = "Redirect uris must be absolute and have no fragment";
+const ERR_SCOPE_NOT_SUPPORTED: &str = "The requested scope is not supported";
+
+// error_code returns the RFC 6749 error code any failure stands for, so plain HTTP clients can tell them apart
+pub fn error_code(err: &error::Error) -> &'static str {
+    match err {
+        error::Error::OAuth(code, _) => code,
+        error::Error::InvalidArgument(_) => INVALID_REQUEST,
+        error::Error::Unauthenticated(_) => INVALID_CLIENT,
+        error::Error::PermissionDenied(_) => ACCESS_DENIED,
+        error::Error::NotFound(_)
+        | error::Error::AlreadyExists(_)
+        | error::Error::DeadlineExceeded(_) => INVALID_GRANT,
+        error::Error::ResourceExhausted(..)
+        | error::Error::Unavailable(_) => TEMPORARILY_UNAVAILABLE,
+        error::Error::Internal(_) => SERVER_ERROR,
+    }
+}
+
+// split_scope returns each of the scopes in the space separated list, as described by RFC 6749, section 3.3
+pub fn split_scope(scope: &str) -> Vec<String> {
+    let mut all: Vec<String> = Vec::new();
+    for item in scope.split(' ').filter(|item| !item.is_empty()) {
+        if !all.iter().any(|other| other == item) {
+            all.push(item.to_string());
+        }
+    }
+
+    all
+}
+
+// parse_scope returns the requested scopes, as long as all of them are supported
+pub fn parse_scope(scope: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let all = split_scope(scope);
+    if scope.len() > MAX_SCOPE_LEN || all.iter().any(|item| !SUPPORTED_SCOPES.contains(&item.as_str())) {
+        return Err(error::Error::OAuth(INVALID_SCOPE, ERR_SCOPE_NOT_SUPPORTED.into()).into());
+    }
+
+    Ok(all)
+}
+
+// discovery returns the provider's metadata, as described by OpenID Connect Discovery, section 3
+pub fn discovery(issuer: &str, algorithms: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}{}", issuer, PATH_AUTHORIZE),
+        "token_endpoint": format!("{}{}", issuer, PATH_TOKEN),
+        "userinfo_endpoint": format!("{}{}", issuer, PATH_USERINFO),
+        "jwks_uri": format!("{}{}", issuer, PATH_JWKS),
+        "scopes_supported": SUPPORTED_SCOPES,
+        "response_types_supported": [RESPONSE_TYPE_CODE],
+        "grant_types_supported": [GRANT_TYPE_CODE, GRANT_TYPE_REFRESH],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": algorithms,
+        "token_endpoint_auth_methods_supported": [AUTH_METHOD_PRIVATE_KEY_JWT],
+        "token_endpoint_auth_signing_alg_values_supported": [jwt::ALG_RS256],
+        "code_challenge_methods_supported": [PKCE_METHOD_S256],
+        "claims_supported": ["iss", "sub", "aud", "exp", "iat", "auth_time", "nonce", "name", "preferred_username", "email", "email_verified"],
+    })
+}
+
+// match_redirect_uri makes sure the uri is one an app could register, as required by RFC 6749, section 3.1.2
+pub fn match_redirect_uri(uri: &str) -> Result<(), Box<dyn Error>> {
+    if uri.len() > MAX_REDIRECT_LEN {
+        return Err(error::Error::invalid("redirect_uri", ERR_REDIRECT_FORMAT).into());
+    }
+
+    match Url::parse(uri) {
+        Ok(url) if url.fragment().is_none() && !url.cannot_be_a_base() => Ok(()),
+        _ => Err(error::Error::invalid("redirect_uri", ERR_REDIRECT_FORMAT).into()),
+    }
+}
+
+// redirect_with returns the redirect uri with the given parameters appended to its query
+pub fn redirect_with(uri: &str, params: &[(&str, &str)]) -> Result<String, Box<dyn Error>> {
+    let mut url = Url::parse(uri)?;
+    { // block is required because of serializer's borrow release
+        let mut query = url.query_pairs_mut();
+        for (key, value) in params.iter().filter(|(_, value)| !value.is_empty()) {
+            query.append_pair(key, value);
+        }
+    }
+
+    Ok(url.into())
+}
+
+// match_pkce_challenge tells whether the challenge may be the S256 transform of any verifier
+pub fn match_pkce_challenge(challenge: &str) -> bool {
+    // a sha256 digest is always 43 characters long once encoded as base64url with no padding
+    challenge.len() == MIN_VERIFIER_LEN && challenge.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+}
+
+// match_pkce tells whether the verifier is the one the challenge was derived from, as described by RFC 7636, section 4.6
+pub fn match_pkce(challenge: &str, verifier: &str) -> bool {
+    if verifier.len() < MIN_VERIFIER_LEN || verifier.len() > MAX_VERIFIER_LEN
+        || !verifier.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'.' || c == b'_' || c == b'~') {
+        return false;
+    }
+
+    let expected = base64::encode_config(sha256(verifier.as_bytes()), base64::URL_SAFE_NO_PAD);
+    expected.len() == challenge.len() && memcmp::eq(expected.as_bytes(), challenge.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_s256() {
+        // the example of RFC 7636, appendix B
+        const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+        assert!(match_pkce_challenge(CHALLENGE));
+        assert!(match_pkce(CHALLENGE, VERIFIER));
+        assert!(!match_pkce(CHALLENGE, &VERIFIER.replace('d', "e")));
+        assert!(!match_pkce(CHALLENGE, "too_short"));
+        assert!(!match_pkce_challenge("too_short"));
+    }
+
+    #[test]
+    fn redirect_uri() {
+        assert!(match_redirect_uri("https://dummy.com/callback").is_ok());
+        assert!(match_redirect_uri("com.dummy.app:/callback").is_ok());
+        assert!(match_redirect_uri("https://dummy.com/callback#fragment").is_err());
+        assert!(match_redirect_uri("/callback").is_err());
+
+        let redirect = redirect_with("https://dummy.com/callback?app=1", &[("code", "a b"), ("state", "")]).unwrap();
+        assert_eq!(redirect, "https://dummy.com/callback?app=1&code=a+b");
+    }
+
+    #[test]
+    fn scope() {
+        assert_eq!(parse_scope("openid  email openid").unwrap(), vec!["openid", "email"]);
+        assert!(parse_scope("").unwrap().is_empty());
+        assert!(parse_scope("openid admin").is_err());
+
+        let document = discovery("https://testing.com", &["RS256"]);
+        assert_eq!(document["token_endpoint"], "https://testing.com/token");
+        assert_eq!(document["id_token_signing_alg_values_supported"][0], "RS256");
+        assert_eq!(document["token_endpoint_auth_methods_supported"][0], "private_key_jwt");
+    }
+}
@@ -17,4 +17,10 @@ pub mod client_proto {
 }
 
 // Proto ticket type enum
-pub use client_proto::TicketKind;
\ No newline at end of file
+pub use client_proto::TicketKind;
+
+// Import the generated rust code into module, not all the standard error details are in use
+#[allow(dead_code)]
+pub mod rpc_proto {
+   tonic::include_proto!("google.rpc");
+}
\ No newline at end of file
@@ -5,6 +5,23 @@ table! {
         label -> Varchar,
         url -> Varchar,
         description -> Varchar,
+        force_reauth -> Bool,
+        scope -> Varchar,
+    }
+}
+
+table! {
+    authcodes (code) {
+        code -> Varchar,
+        app_id -> Int4,
+        cookie -> Varchar,
+        redirect_uri -> Varchar,
+        code_challenge -> Varchar,
+        created_at -> Timestamp,
+        deadline -> Timestamp,
+        consumed_at -> Nullable<Timestamp>,
+        scope -> Varchar,
+        nonce -> Varchar,
     }
 }
 
@@ -19,6 +36,25 @@ table! {
     }
 }
 
+table! {
+    consents (client_id, app_id) {
+        client_id -> Int4,
+        app_id -> Int4,
+        scope -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    directories (cookie, token) {
+        cookie -> Varchar,
+        token -> Varchar,
+        app_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     kinds (id) {
         id -> Int4,
@@ -26,6 +62,42 @@ table! {
     }
 }
 
+table! {
+    outbox (id) {
+        id -> Int4,
+        recipient -> Varchar,
+        subject -> Varchar,
+        body -> Text,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+    }
+}
+
+table! {
+    redirects (app_id, uri) {
+        app_id -> Int4,
+        uri -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    refreshtokens (token) {
+        token -> Varchar,
+        family -> Varchar,
+        client_id -> Int4,
+        app_id -> Int4,
+        cookie -> Varchar,
+        device -> Varchar,
+        created_at -> Timestamp,
+        deadline -> Timestamp,
+        rotated_at -> Nullable<Timestamp>,
+        scope -> Varchar,
+    }
+}
+
 table! {
     secrets (id) {
         id -> Int4,
@@ -37,6 +109,20 @@ table! {
     }
 }
 
+table! {
+    sessions (cookie) {
+        cookie -> Varchar,
+        user_id -> Int4,
+        status -> Int4,
+        created_at -> Timestamp,
+        touch_at -> Timestamp,
+        device -> Varchar,
+        peer_addr -> Varchar,
+        user_agent -> Varchar,
+        forwarded_for -> Varchar,
+    }
+}
+
 table! {
     statuses (id) {
         id -> Int4,
@@ -44,6 +130,33 @@ table! {
     }
 }
 
+table! {
+    tickets (id) {
+        id -> Varchar,
+        client_id -> Int4,
+        kind -> Int4,
+        secret -> Varchar,
+        created_at -> Timestamp,
+        deadline -> Timestamp,
+        consumed_at -> Nullable<Timestamp>,
+        app_id -> Nullable<Int4>,
+        scope -> Varchar,
+    }
+}
+
+table! {
+    tombstones (cookie) {
+        cookie -> Varchar,
+        user_id -> Int4,
+        created_at -> Timestamp,
+        died_at -> Timestamp,
+        device -> Varchar,
+        peer_addr -> Varchar,
+        user_agent -> Varchar,
+        forwarded_for -> Varchar,
+    }
+}
+
 table! {
     users (id) {
         id -> Int4,
@@ -54,16 +167,36 @@ table! {
 }
 
 joinable!(apps -> clients (client_id));
+joinable!(authcodes -> apps (app_id));
 joinable!(clients -> kinds (kind_id));
 joinable!(clients -> statuses (status_id));
+joinable!(consents -> apps (app_id));
+joinable!(consents -> clients (client_id));
+joinable!(directories -> apps (app_id));
+joinable!(directories -> sessions (cookie));
+joinable!(redirects -> apps (app_id));
+joinable!(refreshtokens -> apps (app_id));
+joinable!(refreshtokens -> clients (client_id));
 joinable!(secrets -> clients (client_id));
+joinable!(sessions -> users (user_id));
+joinable!(tickets -> clients (client_id));
+joinable!(tombstones -> users (user_id));
 joinable!(users -> clients (client_id));
 
 allow_tables_to_appear_in_same_query!(
     apps,
+    authcodes,
     clients,
+    consents,
+    directories,
     kinds,
+    outbox,
+    redirects,
+    refreshtokens,
     secrets,
+    sessions,
     statuses,
+    tickets,
+    tombstones,
     users,
 );
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use hyper::{Body, Method, Request, Response, Server, StatusCode, header};
+use hyper::body::HttpBody;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use crate::transactions::{authorize, grant, jwks, login, userinfo};
+use crate::models::{session, namesp};
+use crate::default;
+use crate::oauth;
+use crate::jwt;
+use crate::time;
+use crate::error;
+
+const CONTENT_TYPE_JSON: &str = "application/json;charset=UTF-8";
+const NO_STORE: &str = "no-store";
+const NO_CACHE: &str = "no-cache";
+const PUBLIC_CACHE: &str = "public, max-age=3600"; // keys are rotated seldom, and clients refetch on any unknown kid
+
+const ERR_REPEATED_PARAM: &str = "Request parameters must not be included more than once";
+const ERR_BODY_TOO_LONG: &str = "The request body is too long";
+const ERR_BODY_NOT_READ: &str = "The request body could not be read";
+const ERR_CONSENT_REQUIRED: &str = "The user has not granted the requested scope to the application yet";
+const ERR_TOKEN_MISSING: &str = "An access token is required";
+const ERR_TASK_FAILED: &str = "The request could not be completed";
+
+// parse_form returns the parameters of an urlencoded query or body, none of which may be repeated as required by RFC 6749, section 3.1
+fn parse_form(input: &[u8]) -> Result<HashMap<String, String>, error::Error> {
+    let mut params = HashMap::new();
+    for (key, value) in form_urlencoded::parse(input).into_owned() {
+        if params.insert(key, value).is_some() {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_REPEATED_PARAM.into()));
+        }
+    }
+
+    Ok(params)
+}
+
+fn param<'a>(params: &'a HashMap<String, String>, key: &str) -> &'a str {
+    params.get(key).map(|value| value.as_str()).unwrap_or_default()
+}
+
+// get_cookie returns the value of the cookie the authorization endpoint expects the user's session in, if any
+fn get_cookie(req: &Request<Body>) -> String {
+    req.headers().get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == default::OAUTH_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .unwrap_or_default()
+}
+
+// get_header returns the value of the given header, no longer than what the session store keeps
+fn get_header(req: &Request<Body>, name: &str) -> String {
+    let value = req.headers().get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    value.chars().take(super::MAX_HEADER_LEN).collect()
+}
+
+// request_metadata collects whatever the connection tells about the user agent, just as client_metadata does for grpc
+fn request_metadata(req: &Request<Body>, peer: SocketAddr) -> session::Metadata {
+    session::Metadata{
+        peer_addr: peer.to_string(),
+        user_agent: get_header(req, header::USER_AGENT.as_str()),
+        forwarded_for: get_header(req, super::HEADER_FORWARDED_FOR),
+    }
+}
+
+// set_cookie returns the Set-Cookie value the user's session is kept in: no script can read it, nor is it ever sent
+// over plain HTTP; yet it is sent along the top-level redirect any app starts an authorization with, so it cannot be strict
+fn set_cookie(cookie: &str, max_age: u64) -> String {
+    format!("{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax", default::OAUTH_COOKIE, cookie, max_age)
+}
+
+// get_bearer returns the access token of the Authorization header, as described by RFC 6750, section 2.1
+fn get_bearer(req: &Request<Body>) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case(oauth::TOKEN_TYPE_BEARER) {
+        return None;
+    }
+
+    Some(token.trim().to_string())
+}
+
+// read_form returns the parameters of the request body, as long as it is no longer than allowed
+async fn read_form(req: Request<Body>) -> Result<HashMap<String, String>, error::Error> {
+    let mut body = req.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| error::Error::OAuth(oauth::INVALID_REQUEST, ERR_BODY_NOT_READ.into()))?;
+        if (buf.len() + chunk.len()) as u64 > default::MAX_FORM_LEN {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_BODY_TOO_LONG.into()));
+        }
+
+        buf.extend_from_slice(&chunk);
+    }
+
+    parse_form(&buf)
+}
+
+// json_response returns the given json with all the headers RFC 6749, section 5.1, requires
+fn json_response(status: StatusCode, json: serde_json::Value) -> Response<Body> {
+    let mut resp = Response::new(Body::from(json.to_string()));
+    *resp.status_mut() = status;
+    let headers = resp.headers_mut();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static(CONTENT_TYPE_JSON));
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static(NO_STORE));
+    headers.insert(header::PRAGMA, header::HeaderValue::from_static(NO_CACHE));
+    resp
+}
+
+// error_response returns the error as described by RFC 6749, section 5.2
+fn error_response(err: error::Error) -> Response<Body> {
+    println!("{}", err); // full details stay server side
+    let code = oauth::error_code(&err);
+    let status = match code {
+        oauth::INVALID_CLIENT | oauth::INVALID_TOKEN => StatusCode::UNAUTHORIZED,
+        oauth::ACCESS_DENIED | oauth::INSUFFICIENT_SCOPE => StatusCode::FORBIDDEN,
+        oauth::SERVER_ERROR => StatusCode::INTERNAL_SERVER_ERROR,
+        oauth::TEMPORARILY_UNAVAILABLE => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    json_response(status, serde_json::json!({
+        "error": code,
+        "error_description": err.public_message(),
+    }))
+}
+
+// bearer_error_response returns the error as described by RFC 6750, section 3
+fn bearer_error_response(err: error::Error) -> Response<Body> {
+    let code = oauth::error_code(&err);
+    let challenge = format!("{} error=\"{}\"", oauth::TOKEN_TYPE_BEARER, code);
+    let mut resp = error_response(err);
+    if let Ok(challenge) = header::HeaderValue::from_str(&challenge) {
+        resp.headers_mut().insert(header::WWW_AUTHENTICATE, challenge);
+    }
+
+    resp
+}
+
+// consent_redirect returns where the user agent must be redirected to when the user has not consented yet, since no
+// consent can be given from a plain redirect as required by OpenID Connect Core, section 3.1.2.4
+fn consent_redirect(params: &HashMap<String, String>) -> Result<String, error::Error> {
+    let error = [
+        ("error", oauth::CONSENT_REQUIRED),
+        ("error_description", ERR_CONSENT_REQUIRED),
+        ("state", param(params, "state")),
+    ];
+
+    oauth::redirect_with(param(params, "redirect_uri"), &error)
+        .map_err(|err| error::Error::Internal(err.to_string()))
+}
+
+// login logs the user in the authorization server itself, setting the cookie the authorization endpoint expects; a second
+// factor may be required before, in which case the challenge is told instead
+async fn login(req: Request<Body>, sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>, portal: Arc<String>, peer: SocketAddr) -> Response<Body> {
+    let meta = request_metadata(&req, peer);
+    let cookie = get_cookie(&req);
+    let params = match read_form(req).await {
+        Ok(params) => params,
+        Err(err) => return error_response(err),
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        // a browser tells no device of its own, so its user agent stands for it
+        let device = match param(&params, "device") {
+            "" => meta.user_agent.as_str(),
+            device => device,
+        };
+
+        let tx_login = login::TxLogin::new(
+            &*sessions,
+            &*namespaces,
+            login::LoginParams{
+                token: &cookie,
+                ident: param(&params, "ident"),
+                pwd: param(&params, "pwd"),
+                challenge: param(&params, "challenge"),
+                code: param(&params, "code"),
+                app: &portal,
+                device,
+                ..Default::default()
+            },
+            &meta,
+        );
+
+        tx_login.execute().map_err(error::Error::classify)
+    }).await;
+
+    match result {
+        Ok(Ok(resp)) if resp.cookie.is_empty() => json_response(StatusCode::OK, serde_json::json!({
+            "challenge": resp.challenge,
+            "deadline": resp.deadline,
+        })),
+
+        Ok(Ok(resp)) => {
+            // the cookie does not outlive the session
+            let now = time::unix_seconds(SystemTime::now()).unwrap_or_default();
+            let max_age = (resp.deadline.max(0) as u64).saturating_sub(now);
+            let mut json = json_response(StatusCode::OK, serde_json::json!({
+                "deadline": resp.deadline,
+                "idle_deadline": resp.idle_deadline,
+            }));
+
+            match header::HeaderValue::from_str(&set_cookie(&resp.cookie, max_age)) {
+                Ok(value) => {
+                    json.headers_mut().insert(header::SET_COOKIE, value);
+                    json
+                },
+
+                Err(err) => error_response(error::Error::Internal(err.to_string())),
+            }
+        },
+
+        Ok(Err(err)) => error_response(err),
+        Err(_) => error_response(error::Error::Internal(ERR_TASK_FAILED.into())),
+    }
+}
+
+async fn authorize(req: Request<Body>, sessions: Arc<dyn session::Factory>, portal: Arc<String>) -> Response<Body> {
+    let params = match parse_form(req.uri().query().unwrap_or_default().as_bytes()) {
+        Ok(params) => Arc::new(params),
+        Err(err) => return error_response(err),
+    };
+
+    let cookie = get_cookie(&req);
+    let shared = params.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let tx_authorize = authorize::TxAuthorize::new(
+            &*sessions,
+            &portal,
+            &cookie,
+            authorize::AuthorizeParams{
+                client_id: param(&shared, "client_id"),
+                redirect_uri: param(&shared, "redirect_uri"),
+                response_type: param(&shared, "response_type"),
+                code_challenge: param(&shared, "code_challenge"),
+                code_challenge_method: param(&shared, "code_challenge_method"),
+                state: param(&shared, "state"),
+                scope: param(&shared, "scope"),
+                nonce: param(&shared, "nonce"),
+            },
+        );
+
+        tx_authorize.execute().map_err(error::Error::classify)
+    }).await;
+
+    let redirect_uri = match result {
+        Ok(Ok(resp)) if resp.redirect_uri.is_empty() => consent_redirect(&params),
+        Ok(Ok(resp)) => Ok(resp.redirect_uri),
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err(error::Error::Internal(ERR_TASK_FAILED.into())),
+    };
+
+    match redirect_uri.and_then(|uri| header::HeaderValue::from_str(&uri).map_err(|err| error::Error::Internal(err.to_string()))) {
+        Ok(location) => {
+            let mut redirect = Response::new(Body::empty());
+            *redirect.status_mut() = StatusCode::FOUND;
+            redirect.headers_mut().insert(header::LOCATION, location);
+            redirect
+        },
+
+        Err(err) => error_response(err),
+    }
+}
+
+async fn token(req: Request<Body>, sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>) -> Response<Body> {
+    let params = match read_form(req).await {
+        Ok(params) => params,
+        Err(err) => return error_response(err),
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        let tx_grant = grant::TxGrant::new(
+            &*sessions,
+            &*namespaces,
+            grant::GrantParams{
+                grant_type: param(&params, "grant_type"),
+                code: param(&params, "code"),
+                redirect_uri: param(&params, "redirect_uri"),
+                client_id: param(&params, "client_id"),
+                code_verifier: param(&params, "code_verifier"),
+                refresh_token: param(&params, "refresh_token"),
+                client_assertion_type: param(&params, "client_assertion_type"),
+                client_assertion: param(&params, "client_assertion"),
+            },
+        );
+
+        tx_grant.execute().map_err(error::Error::classify)
+    }).await;
+
+    match result {
+        Ok(Ok(resp)) => {
+            let mut json = serde_json::json!({
+                "access_token": resp.access_token,
+                "token_type": resp.token_type,
+                "expires_in": resp.expires_in,
+                "refresh_token": resp.refresh_token,
+            });
+
+            if !resp.id_token.is_empty() {
+                json["id_token"] = serde_json::Value::from(resp.id_token);
+            }
+
+            json_response(StatusCode::OK, json)
+        },
+
+        Ok(Err(err)) => error_response(err),
+        Err(_) => error_response(error::Error::Internal(ERR_TASK_FAILED.into())),
+    }
+}
+
+async fn jwks() -> Response<Body> {
+    let result = tokio::task::spawn_blocking(|| {
+        jwks::TxJwks::new().execute().map_err(error::Error::classify)
+    }).await;
+
+    match result {
+        Ok(Ok(resp)) => {
+            let keys: Vec<serde_json::Value> = resp.keys.into_iter()
+                .map(|jwk| {
+                    let mut key = serde_json::json!({
+                        "kty": jwk.kty,
+                        "kid": jwk.kid,
+                        "alg": jwk.alg,
+                        "use": "sig",
+                    });
+
+                    // each key type has its own parameters only
+                    for (name, value) in &[("crv", jwk.crv), ("n", jwk.n), ("e", jwk.e), ("x", jwk.x)] {
+                        if !value.is_empty() {
+                            key[*name] = serde_json::Value::from(value.as_str());
+                        }
+                    }
+
+                    key
+                })
+                .collect();
+
+            let mut resp = json_response(StatusCode::OK, serde_json::json!({"keys": keys}));
+            resp.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static(PUBLIC_CACHE));
+            resp.headers_mut().remove(header::PRAGMA);
+            resp
+        },
+
+        Ok(Err(err)) => error_response(err),
+        Err(_) => error_response(error::Error::Internal(ERR_TASK_FAILED.into())),
+    }
+}
+
+async fn user_info(req: Request<Body>) -> Response<Body> {
+    let mut access_token = get_bearer(&req);
+    if access_token.is_none() && req.method() == Method::POST {
+        // the token may be sent as a form parameter instead, as described by RFC 6750, section 2.2
+        access_token = match read_form(req).await {
+            Ok(params) => params.get("access_token").cloned(),
+            Err(err) => return bearer_error_response(err),
+        };
+    }
+
+    let access_token = match access_token {
+        Some(token) if !token.is_empty() => token,
+        _ => return bearer_error_response(error::Error::OAuth(oauth::INVALID_TOKEN, ERR_TOKEN_MISSING.into())),
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        userinfo::TxUserInfo::new(&access_token).execute().map_err(error::Error::classify)
+    }).await;
+
+    match result {
+        Ok(Ok(resp)) => {
+            let mut json = serde_json::json!({"sub": resp.sub});
+            // only the claims the scope grants access to are told
+            for (name, value) in &[("name", resp.name), ("preferred_username", resp.preferred_username)] {
+                if !value.is_empty() {
+                    json[*name] = serde_json::Value::from(value.as_str());
+                }
+            }
+
+            if !resp.email.is_empty() {
+                json["email"] = serde_json::Value::from(resp.email);
+                json["email_verified"] = serde_json::Value::from(resp.email_verified);
+            }
+
+            json_response(StatusCode::OK, json)
+        },
+
+        Ok(Err(err)) => bearer_error_response(err),
+        Err(_) => error_response(error::Error::Internal(ERR_TASK_FAILED.into())),
+    }
+}
+
+fn discovery() -> Response<Body> {
+    let keyset = jwt::get_instance();
+    let document = oauth::discovery(keyset.get_issuer(), &keyset.algorithms());
+    let mut resp = json_response(StatusCode::OK, document);
+    resp.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static(PUBLIC_CACHE));
+    resp.headers_mut().remove(header::PRAGMA);
+    resp
+}
+
+async fn route(req: Request<Body>, sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>, portal: Arc<String>, peer: SocketAddr) -> Result<Response<Body>, Infallible> {
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::POST, oauth::PATH_LOGIN) => login(req, sessions, namespaces, portal, peer).await,
+        (&Method::GET, oauth::PATH_AUTHORIZE) => authorize(req, sessions, portal).await,
+        (&Method::POST, oauth::PATH_TOKEN) => token(req, sessions, namespaces).await,
+        (&Method::GET, oauth::PATH_JWKS) => jwks().await,
+        (&Method::GET, oauth::PATH_USERINFO) | (&Method::POST, oauth::PATH_USERINFO) => user_info(req).await,
+        (&Method::GET, oauth::PATH_DISCOVERY) => discovery(),
+        _ => {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = StatusCode::NOT_FOUND;
+            resp
+        },
+    };
+
+    Ok(resp)
+}
+
+// serve exposes the oauth endpoints to plain HTTP clients, such as browsers following a redirect
+pub async fn serve(addr: SocketAddr, sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>, portal: String) -> Result<(), hyper::Error> {
+    let portal = Arc::new(portal);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let peer = conn.remote_addr();
+        let sessions = sessions.clone();
+        let namespaces = namespaces.clone();
+        let portal = portal.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| route(req, sessions.clone(), namespaces.clone(), portal.clone(), peer)))
+        }
+    });
+
+    Server::bind(&addr).serve(make_service).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_params() {
+        let params = parse_form(b"grant_type=authorization_code&redirect_uri=https%3A%2F%2Fdummy.com%2Fcallback").unwrap();
+        assert_eq!(param(&params, "grant_type"), "authorization_code");
+        assert_eq!(param(&params, "redirect_uri"), "https://dummy.com/callback");
+        assert_eq!(param(&params, "code"), "");
+        assert!(parse_form(b"code=a&code=b").is_err());
+    }
+
+    #[test]
+    fn session_cookie() {
+        let req = Request::builder()
+            .header(header::COOKIE, "theme=dark; session=abc.def")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(get_cookie(&req), "abc.def");
+
+        // the very same cookie is told back once set
+        let cookie = set_cookie("abc.def", 60);
+        assert!(cookie.starts_with("session=abc.def;"));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Secure"));
+        assert!(cookie.contains("SameSite=Lax"));
+    }
+
+    #[test]
+    fn bearer_token() {
+        let req = Request::builder()
+            .header(header::AUTHORIZATION, "bearer abc.def.ghi")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(get_bearer(&req).unwrap(), "abc.def.ghi");
+
+        let req = Request::builder()
+            .header(header::AUTHORIZATION, "Basic abc")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(get_bearer(&req).is_none());
+    }
+}
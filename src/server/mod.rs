@@ -1,34 +1,132 @@
 mod session;
 mod profile;
 mod registry;
+mod oauth;
+mod http;
 
+use std::env;
 use std::error::Error;
-use tonic::{transport::Server, Status, Code};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{transport::Server, Request, Status};
 use crate::proto::{user_proto, app_proto, client_proto};
+use crate::mailer::outbox;
+use crate::transactions::sweep;
+use crate::error;
+use crate::models;
+use crate::default;
 
 // Proto generated server traits
 use user_proto::session_server::{SessionServer};
 use app_proto::registry_server::{RegistryServer};
+use app_proto::authorization_server::{AuthorizationServer};
 use client_proto::profile_server::{ProfileServer};
 
+const HEADER_USER_AGENT: &str = "user-agent";
+const HEADER_X_USER_AGENT: &str = "x-user-agent"; // as forwarded by envoy for grpc-web
+const HEADER_FORWARDED_FOR: &str = "x-forwarded-for";
+const MAX_HEADER_LEN: usize = 256;
+
+const ERR_NO_OAUTH_APP: &str = "The app the authorization server logs its users in must be set";
+
 pub fn parse_error(err: Box<dyn Error>) -> Status {
-    println!("{:?}", err.to_string());
-    let code = Code::from(Code::Unknown);
-    Status::new(code, err.to_string())
+    let err = error::Error::classify(err);
+    println!("{}", err); // full details stay server side
+    err.to_status()
+}
+
+fn get_header<T>(request: &Request<T>, keys: &[&str]) -> String {
+    let value = keys.iter()
+        .filter_map(|key| request.metadata().get(*key))
+        .filter_map(|value| value.to_str().ok())
+        .next()
+        .unwrap_or_default();
+
+    // the metadata is told by the client, so it cannot be longer than what the store keeps
+    value.chars().take(MAX_HEADER_LEN).collect()
+}
+
+// client_metadata collects whatever the transport tells about the client the request comes from
+pub fn client_metadata<T>(request: &Request<T>) -> models::session::Metadata {
+    models::session::Metadata{
+        peer_addr: request.remote_addr().map(|addr| addr.to_string()).unwrap_or_default(),
+        user_agent: get_header(request, &[HEADER_USER_AGENT, HEADER_X_USER_AGENT]),
+        forwarded_for: get_header(request, &[HEADER_FORWARDED_FOR]),
+    }
+}
+
+fn spawn_outbox_worker() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(default::MAIL_RETRY_DELAY));
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(|| outbox::flush().map_err(|err| err.to_string())).await {
+                Ok(Ok(count)) if count > 0 => println!("Outbox worker has delivered {} mails", count),
+                Ok(Err(err)) => println!("Outbox worker has failed: {}", err),
+                _ => {},
+            }
+        }
+    });
+}
+
+// the sweeper evicts all the expired sessions and secrets, so nothing outlives its deadline
+fn spawn_sweeper_worker(sessions: Arc<dyn models::session::Factory>, namespaces: Arc<dyn models::namesp::Factory>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(default::SWEEP_INTERVAL));
+        loop {
+            interval.tick().await;
+            let sessions = sessions.clone();
+            let namespaces = namespaces.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let tx_sweep = sweep::TxSweep::new(&*sessions, &*namespaces);
+                tx_sweep.execute().map_err(|err| err.to_string())
+            }).await;
+
+            match result {
+                Ok(Ok(reaped)) if !reaped.is_empty() => println!("Sweeper has reaped {} sessions, {} directories, {} secrets, {} tombstones, {} failed attempts, {} authorization codes, {} refresh tokens and {} tickets",
+                    reaped.sessions, reaped.dirs, reaped.secrets, reaped.tombstones, reaped.attempts, reaped.codes, reaped.refresh, reaped.tickets),
+                Ok(Err(err)) => println!("Sweeper has failed: {}", err),
+                _ => {},
+            }
+        }
+    });
+}
+
+fn spawn_http_server(addr: std::net::SocketAddr, sessions: Arc<dyn models::session::Factory>, namespaces: Arc<dyn models::namesp::Factory>, portal: String) {
+    println!("HTTP server listening on {}", addr);
+    tokio::spawn(async move {
+        if let Err(err) = http::serve(addr, sessions, namespaces, portal).await {
+            println!("HTTP server has failed: {}", err);
+        }
+    });
 }
 
 pub async fn start_server(address: String) -> Result<(), Box<dyn Error>> {
     let addr = address.parse().unwrap();
-    let session_server = session::SessionImplementation::default();
-    let profile_server = profile::ProfileImplementation::default();
-    let registry_server = registry::RegistryImplementation::default();
+    // the authorization server logs its users in as any other app, so its cookie is the only one authorizing the others
+    let portal = env::var(default::ENV_OAUTH_APP).map_err(|_| ERR_NO_OAUTH_APP)?;
+    // all services share the same registries
+    let sessions = models::session::get_instance();
+    let namespaces = models::namesp::get_instance();
+    let session_server = session::SessionImplementation::new(sessions.clone(), namespaces.clone());
+    let profile_server = profile::ProfileImplementation::new(sessions.clone(), namespaces.clone());
+    let registry_server = registry::RegistryImplementation::new(sessions.clone(), namespaces.clone());
+    let oauth_server = oauth::AuthorizationImplementation::new(sessions.clone(), namespaces.clone(), portal.clone());
  
+    if let Ok(port) = env::var(default::ENV_HTTP_PORT) {
+        // the oauth endpoints are served over plain HTTP as well, so browsers can be redirected to them
+        spawn_http_server(format!("{}:{}", default::SERVER_IP, port).parse()?, sessions.clone(), namespaces.clone(), portal);
+    }
+
+    spawn_outbox_worker();
+    spawn_sweeper_worker(sessions, namespaces);
     println!("Server listening on {}", addr);
  
     Server::builder()
         .add_service(SessionServer::new(session_server))
         .add_service(RegistryServer::new(registry_server))
         .add_service(ProfileServer::new(profile_server))
+        .add_service(AuthorizationServer::new(oauth_server))
         .serve(addr)
         .await?;
  
@@ -0,0 +1,95 @@
+use crate::transactions::{authorize, grant, jwks, userinfo};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use crate::models::{session, namesp};
+use crate::proto::app_proto;
+use super::*;
+
+// Proto generated server traits
+use app_proto::authorization_server::Authorization;
+
+// Proto message structs
+use app_proto::{AuthorizeRequest, AuthorizeResponse, TokenRequest, TokenResponse, JwksResponse, UserInfoRequest, UserInfoResponse};
+
+pub struct AuthorizationImplementation {
+    sessions: Arc<dyn session::Factory>,
+    namespaces: Arc<dyn namesp::Factory>,
+    portal: String, // the label of the app the authorization server logs its users in
+}
+
+impl AuthorizationImplementation {
+    pub fn new(sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>, portal: String) -> Self {
+        AuthorizationImplementation{
+            sessions,
+            namespaces,
+            portal,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Authorization for AuthorizationImplementation {
+    async fn authorize(&self, request: Request<AuthorizeRequest>) -> Result<Response<AuthorizeResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_authorize = authorize::TxAuthorize::new(
+            self.sessions.as_ref(),
+            &self.portal,
+            &msg_ref.cookie,
+            authorize::AuthorizeParams{
+                client_id: &msg_ref.client_id,
+                redirect_uri: &msg_ref.redirect_uri,
+                response_type: &msg_ref.response_type,
+                code_challenge: &msg_ref.code_challenge,
+                code_challenge_method: &msg_ref.code_challenge_method,
+                state: &msg_ref.state,
+                scope: &msg_ref.scope,
+                nonce: &msg_ref.nonce,
+            },
+        );
+        
+        match tx_authorize.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn token(&self, request: Request<TokenRequest>) -> Result<Response<TokenResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_grant = grant::TxGrant::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            grant::GrantParams{
+                grant_type: &msg_ref.grant_type,
+                code: &msg_ref.code,
+                redirect_uri: &msg_ref.redirect_uri,
+                client_id: &msg_ref.client_id,
+                code_verifier: &msg_ref.code_verifier,
+                refresh_token: &msg_ref.refresh_token,
+                client_assertion_type: &msg_ref.client_assertion_type,
+                client_assertion: &msg_ref.client_assertion,
+            },
+        );
+        
+        match tx_grant.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn jwks(&self, _: Request<()>) -> Result<Response<JwksResponse>, Status> {
+        let tx_jwks = jwks::TxJwks::new();
+        match tx_jwks.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn user_info(&self, request: Request<UserInfoRequest>) -> Result<Response<UserInfoResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_userinfo = userinfo::TxUserInfo::new(&msg_ref.access_token);
+        match tx_userinfo.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::transactions::{ticket, resolve};
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
+use crate::models::{session, namesp};
 use crate::proto::client_proto;
 use super::*;
 
@@ -9,8 +11,19 @@ use client_proto::profile_server::Profile;
 // Proto message structs
 use client_proto::{TicketRequest, TicketResponse, ResolveRequest};
 
-#[derive(Default)]
-pub struct ProfileImplementation {}
+pub struct ProfileImplementation {
+    sessions: Arc<dyn session::Factory>,
+    namespaces: Arc<dyn namesp::Factory>,
+}
+
+impl ProfileImplementation {
+    pub fn new(sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>) -> Self {
+        ProfileImplementation{
+            sessions,
+            namespaces,
+        }
+    }
+}
 
 #[tonic::async_trait]
 impl Profile for ProfileImplementation {
@@ -30,8 +43,11 @@ impl Profile for ProfileImplementation {
     async fn resolve(&self, request: Request<ResolveRequest>) -> Result<Response<()>, Status> {
         let msg_ref = request.into_inner();
         let tx_delete = resolve::TxResolve::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
             &msg_ref.id,
-            &msg_ref.data
+            &msg_ref.data,
+            &msg_ref.secret,
         );
         
         match tx_delete.execute() {
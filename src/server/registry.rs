@@ -1,5 +1,7 @@
-use crate::transactions::{register, delete_app};
+use crate::transactions::{register, delete_app, introspect, set_redirects};
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
+use crate::models::{session, namesp};
 use crate::proto::app_proto;
 use super::*;
 
@@ -7,10 +9,21 @@ use super::*;
 use app_proto::registry_server::Registry;
 
 // Proto message structs
-use app_proto::{RegisterRequest, RegisterResponse, DeleteRequest};
+use app_proto::{RegisterRequest, RegisterResponse, DeleteRequest, IntrospectRequest, IntrospectResponse, RedirectsRequest};
 
-#[derive(Default)]
-pub struct RegistryImplementation {}
+pub struct RegistryImplementation {
+    sessions: Arc<dyn session::Factory>,
+    namespaces: Arc<dyn namesp::Factory>,
+}
+
+impl RegistryImplementation {
+    pub fn new(sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>) -> Self {
+        RegistryImplementation{
+            sessions,
+            namespaces,
+        }
+    }
+}
 
 #[tonic::async_trait]
 impl Registry for RegistryImplementation {
@@ -21,6 +34,8 @@ impl Registry for RegistryImplementation {
             &msg_ref.url,
             &msg_ref.descr,
             &msg_ref.public,
+            msg_ref.reauth,
+            &msg_ref.scope,
             &msg_ref.firm,
         );
         
@@ -33,6 +48,8 @@ impl Registry for RegistryImplementation {
     async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<()>, Status> {
         let msg_ref = request.into_inner();
         let tx_delete = delete_app::TxDelete::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
             &msg_ref.label,
             &msg_ref.dust,
             &msg_ref.firm,
@@ -43,4 +60,36 @@ impl Registry for RegistryImplementation {
             Err(err) => Err(parse_error(err))
         }
     }
+
+    async fn introspect(&self, request: Request<IntrospectRequest>) -> Result<Response<IntrospectResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_introspect = introspect::TxIntrospect::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            &msg_ref.label,
+            &msg_ref.cookie,
+            &msg_ref.dust,
+            &msg_ref.firm,
+        );
+        
+        match tx_introspect.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn set_redirects(&self, request: Request<RedirectsRequest>) -> Result<Response<()>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_set_redirects = set_redirects::TxSetRedirects::new(
+            &msg_ref.label,
+            &msg_ref.uris,
+            &msg_ref.dust,
+            &msg_ref.firm,
+        );
+        
+        match tx_set_redirects.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
 }
\ No newline at end of file
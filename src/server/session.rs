@@ -1,4 +1,6 @@
+use std::sync::Arc;
 use tonic::{Request, Response, Status};
+use crate::models::{session, namesp};
 use crate::transactions::*;
 use crate::proto::user_proto;
 use super::*;
@@ -8,18 +10,44 @@ use user_proto::session_server::Session;
 
 // Proto message structs
 use user_proto::{LoginRequest, LogoutRequest, SignupRequest, LoginResponse, DeleteRequest };
+use user_proto::{SessionsRequest, SessionsResponse, RevokeRequest};
+use user_proto::{EnrollTotpRequest, TotpResponse, ConfirmTotpRequest, RecoveryResponse};
+use user_proto::{NonceRequest, NonceResponse, KeyRequest};
+use user_proto::{ConsentsResponse, RevokeConsentRequest};
 
-#[derive(Default)]
-pub struct SessionImplementation {}
+pub struct SessionImplementation {
+    sessions: Arc<dyn session::Factory>,
+    namespaces: Arc<dyn namesp::Factory>,
+}
+
+impl SessionImplementation {
+    pub fn new(sessions: Arc<dyn session::Factory>, namespaces: Arc<dyn namesp::Factory>) -> Self {
+        SessionImplementation{
+            sessions,
+            namespaces,
+        }
+    }
+}
 
 #[tonic::async_trait]
 impl Session for SessionImplementation {
     async fn login(&self, request: Request<LoginRequest>) -> Result<Response<LoginResponse>, Status> {
+        let meta = client_metadata(&request);
         let msg_ref = request.into_inner();
         let tx_login = login::TxLogin::new(
-            &msg_ref.ident,
-            &msg_ref.pwd,
-            &msg_ref.app,
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            login::LoginParams{
+                token: &msg_ref.token,
+                ident: &msg_ref.ident,
+                pwd: &msg_ref.pwd,
+                challenge: &msg_ref.challenge,
+                code: &msg_ref.code,
+                firm: &msg_ref.firm,
+                app: &msg_ref.app,
+                device: &msg_ref.device,
+            },
+            &meta,
         );
         
         match tx_login.execute() {
@@ -31,6 +59,8 @@ impl Session for SessionImplementation {
     async fn logout(&self, request: Request<LogoutRequest>) -> Result<Response<()>, Status> {
         let msg_ref = request.into_inner();
         let tx_logout = logout::TxLogout::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
             &msg_ref.cookie,
         );
         
@@ -55,10 +85,14 @@ impl Session for SessionImplementation {
     }
 
     async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<()>, Status> {
+        let meta = client_metadata(&request);
         let msg_ref = request.into_inner();
         let tx_delete = delete_user::TxDelete::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
             &msg_ref.ident,
             &msg_ref.pwd,
+            &meta,
         );
         
         match tx_delete.execute() {
@@ -66,4 +100,158 @@ impl Session for SessionImplementation {
             Err(err) => Err(parse_error(err))
         }
     }
-}
\ No newline at end of file
+
+    async fn list_sessions(&self, request: Request<SessionsRequest>) -> Result<Response<SessionsResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_list = list_sessions::TxListSessions::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            &msg_ref.cookie,
+        );
+        
+        match tx_list.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn revoke_session(&self, request: Request<RevokeRequest>) -> Result<Response<()>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_revoke = revoke_session::TxRevokeSession::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.id,
+        );
+        
+        match tx_revoke.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn revoke_other_sessions(&self, request: Request<SessionsRequest>) -> Result<Response<()>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_revoke = revoke_others::TxRevokeOthers::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            &msg_ref.cookie,
+        );
+        
+        match tx_revoke.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn enroll_totp(&self, request: Request<EnrollTotpRequest>) -> Result<Response<TotpResponse>, Status> {
+        let meta = client_metadata(&request);
+        let msg_ref = request.into_inner();
+        let tx_enroll = enroll_totp::TxEnrollTotp::new(
+            self.sessions.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.pwd,
+            &meta,
+        );
+        
+        match tx_enroll.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn confirm_totp(&self, request: Request<ConfirmTotpRequest>) -> Result<Response<RecoveryResponse>, Status> {
+        let meta = client_metadata(&request);
+        let msg_ref = request.into_inner();
+        let tx_confirm = confirm_totp::TxConfirmTotp::new(
+            self.sessions.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.code,
+            &msg_ref.pwd,
+            &meta,
+        );
+        
+        match tx_confirm.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn nonce(&self, request: Request<NonceRequest>) -> Result<Response<NonceResponse>, Status> {
+        let meta = client_metadata(&request);
+        let msg_ref = request.into_inner();
+        let tx_nonce = nonce::TxNonce::new(
+            &msg_ref.ident,
+            &meta,
+        );
+        
+        match tx_nonce.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn register_key(&self, request: Request<KeyRequest>) -> Result<Response<()>, Status> {
+        let meta = client_metadata(&request);
+        let msg_ref = request.into_inner();
+        let tx_register = register_key::TxRegisterKey::new(
+            self.sessions.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.name,
+            &msg_ref.public,
+            &msg_ref.firm,
+            &msg_ref.pwd,
+            &meta,
+        );
+        
+        match tx_register.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn delete_key(&self, request: Request<KeyRequest>) -> Result<Response<()>, Status> {
+        let meta = client_metadata(&request);
+        let msg_ref = request.into_inner();
+        let tx_delete = delete_key::TxDeleteKey::new(
+            self.sessions.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.name,
+            &msg_ref.pwd,
+            &meta,
+        );
+        
+        match tx_delete.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn list_consents(&self, request: Request<SessionsRequest>) -> Result<Response<ConsentsResponse>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_list = list_consents::TxListConsents::new(
+            self.sessions.as_ref(),
+            &msg_ref.cookie,
+        );
+        
+        match tx_list.execute() {
+            Ok(resp) => Ok(Response::new(resp)),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+
+    async fn revoke_consent(&self, request: Request<RevokeConsentRequest>) -> Result<Response<()>, Status> {
+        let msg_ref = request.into_inner();
+        let tx_revoke = revoke_consent::TxRevokeConsent::new(
+            self.sessions.as_ref(),
+            self.namespaces.as_ref(),
+            &msg_ref.cookie,
+            &msg_ref.app,
+        );
+        
+        match tx_revoke.execute() {
+            Ok(_) => Ok(Response::new(())),
+            Err(err) => Err(parse_error(err))
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::env;
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+use dashmap::DashMap;
+use crate::default;
+use crate::error;
+
+const ERR_TOO_MANY_ATTEMPTS: &str = "Too many failed attempts, try again later";
+const MAX_BACKOFF_SHIFT: u32 = 16; // no backoff gets doubled beyond it
+
+// Throttle keeps track of the failed attempts of each key, so whoever fails too many times has to wait before trying again
+pub trait Throttle: Send + Sync {
+    fn check(&self, key: &str) -> Result<(), Box<dyn Error>>;
+    fn fail(&self, key: &str);
+    fn reset(&self, key: &str);
+    fn purge(&self) -> usize;
+}
+
+lazy_static! {
+    static ref INSTANCE: Limiter = {
+        let threshold = env::var(default::ENV_LOGIN_MAX_ATTEMPTS).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::LOGIN_MAX_ATTEMPTS);
+
+        let backoff = env::var(default::ENV_LOGIN_BACKOFF).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::LOGIN_BACKOFF);
+
+        let lockout = env::var(default::ENV_LOGIN_LOCKOUT).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::LOGIN_LOCKOUT);
+
+        Limiter::new(threshold, Duration::from_secs(backoff), Duration::from_secs(lockout))
+    };
+
+    // every nonce counts, not just the failed ones, and none of them has to wait for the former one
+    static ref NONCES: Limiter = {
+        let threshold = env::var(default::ENV_NONCE_MAX_REQUESTS).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::NONCE_MAX_REQUESTS);
+
+        let window = env::var(default::ENV_NONCE_WINDOW).ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default::NONCE_WINDOW);
+
+        Limiter::new(threshold, Duration::ZERO, Duration::from_secs(window))
+    };
+}
+
+pub fn get_instance() -> &'static dyn Throttle {
+    &*INSTANCE
+}
+
+// get_nonce_instance returns the throttle nonce requests are accounted to, apart from any failed login
+pub fn get_nonce_instance() -> &'static dyn Throttle {
+    &*NONCES
+}
+
+// ident_key and peer_key keep identities and addresses apart, so none of them can be taken by the other
+pub fn ident_key(ident: &str) -> String {
+    format!("ident:{}", ident.to_lowercase())
+}
+
+pub fn peer_key(addr: &str) -> String {
+    format!("peer:{}", addr)
+}
+
+struct Record {
+    failures: u32,
+    last_failure: SystemTime,
+    blocked_until: SystemTime,
+}
+
+// Limiter doubles the waiting time on each failed attempt, and locks the key out once it has failed as many times as the threshold
+pub struct Limiter {
+    records: DashMap<String, Record>,
+    threshold: u32,
+    backoff: Duration,
+    lockout: Duration,
+}
+
+impl Limiter {
+    pub fn new(threshold: u32, backoff: Duration, lockout: Duration) -> Self {
+        Limiter{
+            records: DashMap::new(),
+            threshold,
+            backoff,
+            lockout,
+        }
+    }
+
+    // a record is forgotten once it has not failed for as long as a lockout lasts
+    fn is_stale(&self, record: &Record, now: SystemTime) -> bool {
+        record.blocked_until <= now && record.last_failure + self.lockout <= now
+    }
+}
+
+impl Throttle for Limiter {
+    fn check(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        let now = SystemTime::now();
+        if let Some(record) = self.records.get(key) {
+            if let Ok(retry_after) = record.blocked_until.duration_since(now) {
+                return Err(error::Error::ResourceExhausted(ERR_TOO_MANY_ATTEMPTS.into(), retry_after).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fail(&self, key: &str) {
+        let now = SystemTime::now();
+        let mut record = self.records.entry(key.to_string()).or_insert(Record{
+            failures: 0,
+            last_failure: now,
+            blocked_until: now,
+        });
+
+        if self.is_stale(&record, now) {
+            record.failures = 0;
+        }
+
+        record.failures += 1;
+        record.last_failure = now;
+        record.blocked_until = if record.failures >= self.threshold {
+            now + self.lockout
+        } else {
+            let shift = (record.failures - 1).min(MAX_BACKOFF_SHIFT);
+            now + (self.backoff * (1 << shift)).min(self.lockout)
+        };
+    }
+
+    fn reset(&self, key: &str) {
+        self.records.remove(key);
+    }
+
+    fn purge(&self) -> usize {
+        let now = SystemTime::now();
+        let before = self.records.len();
+        self.records.retain(|_, record| !self.is_stale(record, now));
+        before.saturating_sub(self.records.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use tonic::Code;
+    use crate::error;
+    use super::{Throttle, Limiter};
+
+    #[test]
+    fn throttle_backoff() {
+        let limiter = Limiter::new(3, Duration::from_millis(20), Duration::from_secs(60));
+        const KEY: &str = "throttle_backoff";
+
+        assert!(limiter.check(KEY).is_ok());
+        limiter.fail(KEY);
+        let err = error::Error::classify(limiter.check(KEY).unwrap_err());
+        assert_eq!(err.code(), Code::ResourceExhausted);
+
+        // the key may try again once the backoff is over
+        thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(KEY).is_ok());
+
+        // the threshold locks the key out for a whole lockout
+        limiter.fail(KEY);
+        thread::sleep(Duration::from_millis(50));
+        limiter.fail(KEY);
+        thread::sleep(Duration::from_millis(100));
+        match error::Error::classify(limiter.check(KEY).unwrap_err()) {
+            error::Error::ResourceExhausted(_, retry_after) => assert!(retry_after > Duration::from_secs(50)),
+            err => panic!("unexpected error {}", err),
+        }
+
+        limiter.reset(KEY);
+        assert!(limiter.check(KEY).is_ok());
+    }
+
+    #[test]
+    fn throttle_window() {
+        let limiter = Limiter::new(2, Duration::ZERO, Duration::from_secs(60));
+        const KEY: &str = "throttle_window";
+
+        // with no backoff, the key waits for nothing until reaching the threshold
+        limiter.fail(KEY);
+        assert!(limiter.check(KEY).is_ok());
+        limiter.fail(KEY);
+        let err = error::Error::classify(limiter.check(KEY).unwrap_err());
+        assert_eq!(err.code(), Code::ResourceExhausted);
+    }
+
+    #[test]
+    fn throttle_purge() {
+        let limiter = Limiter::new(3, Duration::from_millis(1), Duration::from_millis(10));
+        limiter.fail("throttle_purge");
+        assert_eq!(limiter.purge(), 0);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(limiter.purge(), 1);
+    }
+}
@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn _unix_seconds(current: SystemTime) -> Result<u64, Box<dyn Error>> {
+pub fn unix_seconds(current: SystemTime) -> Result<u64, Box<dyn Error>> {
 	match current.duration_since(UNIX_EPOCH) {
 		Err(err) => {
 			let msg = format!("Time went backwards: {}", err);
@@ -1,14 +1,15 @@
 use std::hash::Hasher;
 use std::hash::Hash;
 use std::fmt;
-use rand::Rng;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use openssl::memcmp;
+use openssl::sha::sha256;
 use std::time::{Duration, SystemTime};
 //use crypto::digest::Digest;
 //use crypto::sha2::Sha256;
 
-const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-                        abcdefghijklmnopqrstuvwxyz\
-                        0123456789)(*&^%$#@!~?][+-";
+const FINGERPRINT_LEN: usize = 12; // in bytes
 
 //#[derive(PartialEq, Eq)]
 //#[derive(Hash)]
@@ -16,22 +17,22 @@ const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
 pub struct Token (String, SystemTime);
 
 impl Token {
+    // new returns a token made of as many random bytes as the given size, drawn from the OS generator
     pub fn new(size: usize) -> Self {
-        let value: String = (0..size)
-        .map(|_| {
-            let mut rand = rand::thread_rng();
-            let idx = rand.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect();
-    
-        Token(value, SystemTime::now())
+        let mut value = vec![0; size];
+        OsRng.fill_bytes(&mut value);
+        Token(base64::encode_config(&value, base64::URL_SAFE_NO_PAD), SystemTime::now())
     }
 
     pub fn from_string(tid: &str) -> Token {
         Token(tid.to_string(),  SystemTime::now())
     }
 
+    // restore rebuilds a token that was issued some time ago
+    pub fn restore(tid: &str, created_at: SystemTime) -> Token {
+        Token(tid.to_string(), created_at)
+    }
+
     pub fn to_string(&self) -> String {
         self.0.clone()
     }
@@ -44,9 +45,21 @@ impl Token {
         self.1 + timeout < SystemTime::now()
     }
 
-    pub fn _get_created_at(&self) -> SystemTime {
+    pub fn get_created_at(&self) -> SystemTime {
         self.1
     }
+
+    // digest is what gets kept server side in place of the token, so no dump can tell the token back
+    pub fn digest(&self) -> Token {
+        let digest = sha256(self.0.as_bytes());
+        Token(base64::encode_config(digest, base64::URL_SAFE_NO_PAD), self.1)
+    }
+
+    // fingerprint identifies the token in public, with no way to get the token back from it
+    pub fn fingerprint(&self) -> String {
+        let digest = sha256(self.0.as_bytes());
+        base64::encode_config(&digest[..FINGERPRINT_LEN], base64::URL_SAFE_NO_PAD)
+    }
 }
 
 impl fmt::Display for Token {
@@ -65,9 +78,34 @@ impl Hash for Token {
 }
 
 impl PartialEq for Token {
+    // constant-time, so no comparison tells how much of a token has been guessed
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.0.len() == other.0.len() && memcmp::eq(self.0.as_bytes(), other.0.as_bytes())
     }
 }
 
-impl Eq for Token {}
\ No newline at end of file
+impl Eq for Token {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default;
+
+    #[test]
+    fn token_entropy() {
+        let token = Token::new(default::TOKEN_LEN);
+        let raw = base64::decode_config(token.as_str(), base64::URL_SAFE_NO_PAD).unwrap();
+        assert!(raw.len() * 8 >= 128);
+        assert!(token != Token::new(default::TOKEN_LEN));
+    }
+
+    #[test]
+    fn token_digest() {
+        let token = Token::new(default::TOKEN_LEN);
+        let digest = token.digest();
+        assert!(digest == token.digest());
+        assert!(digest != token);
+        assert!(!digest.as_str().contains(token.as_str()));
+        assert!(Token::from_string("short") != Token::from_string("shorter"));
+    }
+}
@@ -0,0 +1,227 @@
+use std::env;
+use std::error::Error;
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::symm::{self, Cipher};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use crate::default;
+
+// the parameters every authenticator app assumes when they are not told otherwise
+const SECRET_LEN: usize = 20; // in bytes, as long as a sha1 digest
+const DIGITS: u32 = 6;
+const STEP: u64 = 30; // in seconds
+const WINDOW: u64 = 1; // steps accepted before and after the current one, for clock drift
+
+const KEY_LEN: usize = 32; // in bytes, as required by aes-256
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const STEP_LEN: usize = 8; // the last accepted step is sealed along the secret
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+const ERR_TOTP_KEY: &str = "The totp key must be a base64url encoded key of 32 bytes";
+const ERR_SEALED_FORMAT: &str = "The sealed secret is not valid";
+
+lazy_static! {
+    // the key shared secrets get encrypted with before being stored
+    static ref KEY: Vec<u8> = {
+        // no secret could be unsealed after a restart, nor by any other replica, if the key was an ephemeral one
+        let value = env::var(default::ENV_TOTP_KEY).expect(ERR_TOTP_KEY);
+        base64::decode_config(&value, base64::URL_SAFE_NO_PAD).ok()
+            .filter(|key| key.len() == KEY_LEN)
+            .expect(ERR_TOTP_KEY)
+    };
+
+    static ref ISSUER: String = env::var(default::ENV_TOTP_ISSUER)
+        .unwrap_or(default::TOTP_ISSUER.to_string());
+}
+
+// must_configure makes sure the totp key has been set, so the server does not start without it
+pub fn must_configure() {
+    lazy_static::initialize(&KEY);
+}
+
+// new_secret returns a brand new shared secret
+pub fn new_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LEN];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+// encode_secret returns the secret the way authenticator apps expect it to be typed in
+pub fn encode_secret(secret: &[u8]) -> String {
+    let mut encoded = String::with_capacity((secret.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in secret {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    encoded
+}
+
+// uri returns the otpauth uri authenticator apps enroll the secret from, usually rendered as a QR code
+pub fn uri(account: &str, secret: &[u8]) -> String {
+    format!("otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = escape(&ISSUER),
+        account = escape(account),
+        secret = encode_secret(secret),
+        digits = DIGITS,
+        period = STEP)
+}
+
+fn escape(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'@' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+pub fn current_step(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH)
+        .map(|unix| unix.as_secs() / STEP)
+        .unwrap_or(0)
+}
+
+// code_at returns the one-time password of the given step, as described by RFC 4226
+pub fn code_at(secret: &[u8], step: u64) -> Result<String, Box<dyn Error>> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&step.to_be_bytes())?;
+    let hmac = signer.sign_to_vec()?;
+
+    let offset = (hmac[hmac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(hmac[offset..offset + 4].try_into()?) & 0x7fff_ffff;
+    Ok(format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize))
+}
+
+// verify returns the step the code belongs to, if any, as long as it is later than the last accepted one,
+// so no code can be used twice
+pub fn verify(secret: &[u8], code: &str, last_step: u64, now: SystemTime) -> Result<Option<u64>, Box<dyn Error>> {
+    if code.len() != DIGITS as usize {
+        return Ok(None);
+    }
+
+    let current = current_step(now);
+    for step in current.saturating_sub(WINDOW)..=current + WINDOW {
+        if step <= last_step {
+            continue;
+        }
+
+        let expected = code_at(secret, step)?;
+        if memcmp::eq(expected.as_bytes(), code.as_bytes()) {
+            return Ok(Some(step));
+        }
+    }
+
+    Ok(None)
+}
+
+// seal returns the secret, along with the last accepted step, encrypted by the totp key; the client it belongs to is
+// authenticated as well, so no sealed secret can be moved from one client to another
+pub fn seal(client_id: i32, secret: &[u8], last_step: u64) -> Result<String, Box<dyn Error>> {
+    let mut nonce = vec![0; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut plain = last_step.to_be_bytes().to_vec();
+    plain.extend_from_slice(secret);
+
+    let mut tag = vec![0; TAG_LEN];
+    let mut sealed = symm::encrypt_aead(Cipher::aes_256_gcm(), &KEY, Some(&nonce), &client_id.to_be_bytes(), &plain, &mut tag)?;
+    sealed.extend_from_slice(&tag);
+    nonce.extend_from_slice(&sealed);
+    Ok(base64::encode_config(&nonce, base64::URL_SAFE_NO_PAD))
+}
+
+// unseal returns the secret and the last accepted step it was sealed with, as long as it belongs to the client
+pub fn unseal(client_id: i32, document: &str) -> Result<(Vec<u8>, u64), Box<dyn Error>> {
+    let data = base64::decode_config(document, base64::URL_SAFE_NO_PAD)?;
+    if data.len() < NONCE_LEN + STEP_LEN + TAG_LEN {
+        return Err(ERR_SEALED_FORMAT.into());
+    }
+
+    let (nonce, rest) = data.split_at(NONCE_LEN);
+    let (sealed, tag) = rest.split_at(rest.len() - TAG_LEN);
+    let plain = symm::decrypt_aead(Cipher::aes_256_gcm(), &KEY, Some(nonce), &client_id.to_be_bytes(), sealed, tag)
+        .map_err(|_| ERR_SEALED_FORMAT)?;
+
+    let (step, secret) = plain.split_at(STEP_LEN);
+    Ok((secret.to_vec(), u64::from_be_bytes(step.try_into()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::*;
+
+    // the sha1 secret of the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn totp_rfc_vectors() {
+        assert_eq!(code_at(RFC_SECRET, 59 / STEP).unwrap(), "287082");
+        assert_eq!(code_at(RFC_SECRET, 1111111109 / STEP).unwrap(), "081804");
+        assert_eq!(code_at(RFC_SECRET, 1234567890 / STEP).unwrap(), "005924");
+        assert_eq!(code_at(RFC_SECRET, 20000000000 / STEP).unwrap(), "353130");
+    }
+
+    #[test]
+    fn totp_verify() {
+        let now = UNIX_EPOCH + Duration::from_secs(1111111109);
+        let step = current_step(now);
+        let code = code_at(RFC_SECRET, step).unwrap();
+
+        assert_eq!(verify(RFC_SECRET, &code, 0, now).unwrap(), Some(step));
+        // the code of a nearby step is accepted, for clocks may drift
+        let previous = code_at(RFC_SECRET, step - 1).unwrap();
+        assert_eq!(verify(RFC_SECRET, &previous, 0, now).unwrap(), Some(step - 1));
+        // while it cannot be accepted twice, nor when too old
+        assert_eq!(verify(RFC_SECRET, &code, step, now).unwrap(), None);
+        let old = code_at(RFC_SECRET, step - 2).unwrap();
+        assert_eq!(verify(RFC_SECRET, &old, 0, now).unwrap(), None);
+        assert_eq!(verify(RFC_SECRET, "12345", 0, now).unwrap(), None);
+    }
+
+    #[test]
+    fn totp_seal() {
+        if env::var(default::ENV_TOTP_KEY).is_err() {
+            env::set_var(default::ENV_TOTP_KEY, base64::encode_config([0; KEY_LEN], base64::URL_SAFE_NO_PAD));
+        }
+
+        let secret = new_secret();
+        let sealed = seal(1, &secret, 42).unwrap();
+        assert_ne!(sealed, seal(1, &secret, 42).unwrap());
+        assert_eq!(unseal(1, &sealed).unwrap(), (secret, 42));
+        // a secret sealed for some client cannot be unsealed for any other
+        assert!(unseal(2, &sealed).is_err());
+
+        let mut tampered = base64::decode_config(&sealed, base64::URL_SAFE_NO_PAD).unwrap();
+        tampered[NONCE_LEN] ^= 1;
+        assert!(unseal(1, &base64::encode_config(&tampered, base64::URL_SAFE_NO_PAD)).is_err());
+    }
+
+    #[test]
+    fn totp_uri() {
+        assert_eq!(encode_secret(b"12345678901234567890"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(encode_secret(b"f"), "MY");
+        let uri = uri("dummy@testing.com", RFC_SECRET);
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains("dummy@testing.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&"));
+    }
+}
@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::time::Duration;
+use crate::models::{app, authcode, redirect, session, Gateway};
+use crate::models::app::Ctrl as AppCtrl;
+use crate::default;
+use crate::oauth;
+use crate::error;
+use super::{logout, login};
+
+// Proto message structs
+use crate::proto::app_proto::AuthorizeResponse;
+
+const MAX_NONCE_LEN: usize = 256;
+
+const ERR_CLIENT_NOT_FOUND: &str = "The client is not a registered application";
+const ERR_REDIRECT_NOT_REGISTERED: &str = "The redirect uri has not been registered by the application";
+const ERR_RESPONSE_TYPE: &str = "Only the code response type is supported";
+const ERR_CHALLENGE_REQUIRED: &str = "A code challenge is required";
+const ERR_CHALLENGE_METHOD: &str = "Only the S256 code challenge method is supported";
+const ERR_CHALLENGE_FORMAT: &str = "The code challenge is not a S256 transform";
+const ERR_NONCE_FORMAT: &str = "The nonce is too long";
+const ERR_SCOPE_NOT_DECLARED: &str = "The application has not declared the requested scope";
+const ERR_REAUTH_REQUIRED: &str = "The application requires the user to log in with its credentials";
+const ERR_PORTAL_NOT_FOUND: &str = "The application the authorization server logs its users in is not registered";
+
+// Outcome is what a valid authorization request ends up with: either a code, or the scopes the user has to consent first
+enum Outcome {
+    Code(String),
+    Consent(Vec<String>),
+}
+
+// AuthorizeParams are those of an authorization request, as defined by RFC 6749 section 4.1.1 and RFC 7636 section 4.3
+#[derive(Default)]
+pub struct AuthorizeParams<'a> {
+    pub client_id: &'a str, // the label of the application
+    pub redirect_uri: &'a str,
+    pub response_type: &'a str,
+    pub code_challenge: &'a str,
+    pub code_challenge_method: &'a str,
+    pub state: &'a str,
+    pub scope: &'a str,
+    pub nonce: &'a str,
+}
+
+pub struct TxAuthorize<'a> {
+    sessions: &'a dyn session::Factory,
+    portal: &'a str, // the label of the app the authorization server logs its users in
+    cookie: &'a str,
+    client_id: &'a str,
+    redirect_uri: &'a str,
+    response_type: &'a str,
+    code_challenge: &'a str,
+    code_challenge_method: &'a str,
+    state: &'a str,
+    scope: &'a str,
+    nonce: &'a str,
+}
+
+impl<'a> TxAuthorize<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, portal: &'a str, cookie: &'a str, params: AuthorizeParams<'a>) -> Self {
+        TxAuthorize{
+            sessions,
+            portal,
+            cookie,
+            client_id: params.client_id,
+            redirect_uri: params.redirect_uri,
+            response_type: params.response_type,
+            code_challenge: params.code_challenge,
+            code_challenge_method: params.code_challenge_method,
+            state: params.state,
+            scope: params.scope,
+            nonce: params.nonce,
+        }
+    }
+
+    fn precondition(&self) -> Result<(), Box<dyn Error>> {
+        if self.response_type != oauth::RESPONSE_TYPE_CODE {
+            return Err(error::Error::OAuth(oauth::UNSUPPORTED_RESPONSE_TYPE, ERR_RESPONSE_TYPE.into()).into());
+        }
+
+        if self.code_challenge.is_empty() {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_CHALLENGE_REQUIRED.into()).into());
+        }
+
+        if self.code_challenge_method != oauth::PKCE_METHOD_S256 {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_CHALLENGE_METHOD.into()).into());
+        }
+
+        if !oauth::match_pkce_challenge(self.code_challenge) {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_CHALLENGE_FORMAT.into()).into());
+        }
+
+        if self.nonce.len() > MAX_NONCE_LEN {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_NONCE_FORMAT.into()).into());
+        }
+
+        Ok(())
+    }
+
+    // issue_code returns a brand new authorization code for the session the cookie belongs to, as long as the user has
+    // granted the requested scopes to the app
+    fn issue_code(&self, app: &dyn app::Ctrl, sess: &session::Shared) -> Result<Outcome, Box<dyn Error>> {
+        self.precondition()?;
+        let scope = oauth::parse_scope(self.scope)?;
+        let declared = app.get_scope();
+        if scope.iter().any(|item| !declared.contains(item)) {
+            return Err(error::Error::OAuth(oauth::INVALID_SCOPE, ERR_SCOPE_NOT_DECLARED.into()).into());
+        }
+
+        if app.get_force_reauth() {
+            // the user's session cannot be reused, so it has to log in the application with its credentials
+            return Err(error::Error::OAuth(oauth::ACCESS_DENIED, ERR_REAUTH_REQUIRED.into()).into());
+        }
+
+        let (cookie, client_id) = { // block is required because of session's lock release
+            let sess = sess.lock();
+            (sess.get_cookie().clone(), sess.get_client_id())
+        };
+
+        let pending = login::pending_consent(client_id, app.get_id(), &scope)?;
+        if !pending.is_empty() {
+            // neither the app nor whoever crafted the request may consent on behalf of the user
+            login::request_consent(client_id, app.get_id(), &pending)?;
+            return Ok(Outcome::Consent(pending));
+        }
+
+        let timeout = Duration::from_secs(default::AUTH_CODE_TIMEOUT);
+        let (mut authcode, code) = authcode::AuthCode::new(app.get_id(), &cookie, self.redirect_uri, self.code_challenge, &scope, self.nonce, timeout);
+        authcode.insert()?;
+        Ok(Outcome::Code(code.to_string()))
+    }
+
+    pub fn execute(&self) -> Result<AuthorizeResponse, Box<dyn Error>> {
+        println!("Got an Authorization request from app {} ", self.client_id);
+
+        // none of these errors may be told to the redirect uri, since it cannot be trusted yet
+        let app = app::find_by_label(self.client_id)
+            .map_err(|_| error::Error::OAuth(oauth::INVALID_CLIENT, ERR_CLIENT_NOT_FOUND.into()))?;
+
+        if !redirect::find_by_app(app.get_id())?.iter().any(|uri| uri == self.redirect_uri) {
+            return Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_REDIRECT_NOT_REGISTERED.into()).into());
+        }
+
+        // the cookie must be the one the authorization server has set by itself, since any app holding a cookie of its own
+        // could authorize any other app otherwise
+        let portal = app::find_by_label(self.portal)
+            .map_err(|_| error::Error::Internal(ERR_PORTAL_NOT_FOUND.into()))?;
+
+        let sess = logout::authenticate_in(self.sessions, self.cookie, portal.get_id())?;
+        let params = match self.issue_code(app.as_ref(), &sess) {
+            Ok(Outcome::Code(code)) => vec![("code", code), ("state", self.state.to_string())],
+            Ok(Outcome::Consent(pending)) => return Ok(AuthorizeResponse{
+                consent: pending,
+                ..Default::default()
+            }),
+
+            Err(err) => {
+                let err = error::Error::classify(err);
+                println!("{}", err); // full details stay server side
+                let description = match &err {
+                    error::Error::OAuth(_, msg) => msg.to_string(),
+                    _ => String::new(),
+                };
+
+                vec![("error", oauth::error_code(&err).to_string()), ("error_description", description), ("state", self.state.to_string())]
+            },
+        };
+
+        let params: Vec<(&str, &str)> = params.iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect();
+
+        Ok(AuthorizeResponse{
+            redirect_uri: oauth::redirect_with(self.redirect_uri, &params)?,
+            ..Default::default()
+        })
+    }
+}
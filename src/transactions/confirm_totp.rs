@@ -0,0 +1,137 @@
+use std::error::Error;
+use crate::cookie;
+use std::time::SystemTime;
+use crate::totp;
+use crate::default;
+use crate::token::Token;
+use crate::models::{session, secret, Gateway};
+use crate::models::secret::SealedCtrl;
+use crate::error;
+use super::enroll_totp::find_sealed;
+use super::logout;
+
+// Proto message structs
+use crate::proto::user_proto::RecoveryResponse;
+
+const ERR_NO_ENROLLMENT: &str = "The user has no enrollment to confirm";
+const ERR_ENROLLMENT_EXPIRED: &str = "The enrollment has expired";
+const ERR_CODE_NOT_MATCH: &str = "The provided code does not match";
+const RECOVERY_SEPARATOR: char = ',';
+
+// has_second_factor tells whether the client has confirmed the enrollment of a second factor
+pub fn has_second_factor(client_id: i32) -> Result<bool, Box<dyn Error>> {
+    Ok(find_sealed(client_id, default::TOTP_NAME)?.is_some())
+}
+
+// match_second_factor tells whether the code is either the current one-time password of the client, or any of its
+// recovery codes; none of them is accepted twice, so it fails if some other request has used the same secret meanwhile
+pub fn match_second_factor(client_id: i32, code: &str) -> Result<bool, Box<dyn Error>> {
+    let mut sealed = match find_sealed(client_id, default::TOTP_NAME)? {
+        Some(sealed) => sealed,
+        None => return Ok(false),
+    };
+
+    let (secret, last_step) = totp::unseal(client_id, sealed.get_document())?;
+    if let Some(step) = totp::verify(&secret, code, last_step, SystemTime::now())? {
+        sealed.set_document(&totp::seal(client_id, &secret, step)?);
+        sealed.update()?;
+        return Ok(true);
+    }
+
+    let mut recovery = match find_sealed(client_id, default::RECOVERY_NAME)? {
+        Some(recovery) => recovery,
+        None => return Ok(false),
+    };
+
+    let digest = Token::from_string(code).digest();
+    let mut digests: Vec<Token> = recovery.get_document()
+        .split(RECOVERY_SEPARATOR)
+        .map(Token::from_string)
+        .collect();
+
+    let index = match digests.iter().position(|candidate| *candidate == digest) {
+        Some(index) => index,
+        None => return Ok(false),
+    };
+
+    digests.remove(index);
+    if digests.is_empty() {
+        recovery.delete()?;
+    } else {
+        let document: Vec<String> = digests.iter().map(Token::to_string).collect();
+        recovery.set_document(&document.join(&RECOVERY_SEPARATOR.to_string()));
+        recovery.update()?;
+    }
+
+    Ok(true)
+}
+
+pub struct TxConfirmTotp<'a> {
+    sessions: &'a dyn session::Factory,
+    cookie: &'a str,
+    code: &'a str,
+    pwd: &'a str,
+    meta: &'a session::Metadata,
+}
+
+impl<'a> TxConfirmTotp<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, cookie: &'a str, code: &'a str, pwd: &'a str, meta: &'a session::Metadata) -> Self {
+        TxConfirmTotp{
+            sessions,
+            cookie,
+            code,
+            pwd,
+            meta,
+        }
+    }
+
+    // new_recovery_codes replaces the client's recovery codes, if any, by brand new ones; only their digests are kept
+    fn new_recovery_codes(&self, client_id: i32) -> Result<Vec<String>, Box<dyn Error>> {
+        if let Some(recovery) = find_sealed(client_id, default::RECOVERY_NAME)? {
+            recovery.delete()?;
+        }
+
+        let codes: Vec<Token> = (0..default::RECOVERY_CODES)
+            .map(|_| Token::new(default::RECOVERY_CODE_LEN))
+            .collect();
+
+        let digests: Vec<String> = codes.iter().map(|code| code.digest().to_string()).collect();
+        let mut recovery = secret::Secret::new_sealed(client_id, default::RECOVERY_NAME, &digests.join(&RECOVERY_SEPARATOR.to_string()), None)?;
+        recovery.insert()?;
+
+        Ok(codes.iter().map(Token::to_string).collect())
+    }
+
+    pub fn execute(&self) -> Result<RecoveryResponse, Box<dyn Error>> {
+        println!("Got a ConfirmTotp request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let client_id = logout::reauthenticate(self.sessions, self.cookie, self.pwd, self.meta)?
+            .lock()
+            .get_client_id();
+
+        let pending = match find_sealed(client_id, default::TOTP_PENDING_NAME)? {
+            Some(pending) => pending,
+            None => return Err(error::Error::NotFound(ERR_NO_ENROLLMENT.into()).into()),
+        };
+
+        let now = SystemTime::now();
+        if pending.get_deadline().map(|deadline| deadline < now).unwrap_or(false) {
+            return Err(error::Error::DeadlineExceeded(ERR_ENROLLMENT_EXPIRED.into()).into());
+        }
+
+        // the code proves the authenticator app holds the very same secret
+        let (secret, last_step) = totp::unseal(client_id, pending.get_document())?;
+        let step = match totp::verify(&secret, self.code, last_step, now)? {
+            Some(step) => step,
+            None => return Err(error::Error::Unauthenticated(ERR_CODE_NOT_MATCH.into()).into()),
+        };
+
+        let mut confirmed = secret::Secret::new_sealed(client_id, default::TOTP_NAME, &totp::seal(client_id, &secret, step)?, None)?;
+        confirmed.insert()?;
+        pending.delete()?;
+
+        Ok(RecoveryResponse{
+            codes: self.new_recovery_codes(client_id)?,
+        })
+    }
+}
@@ -4,21 +4,26 @@ use crate::models::app::Ctrl as AppCtrl;
 use crate::models::secret::Ctrl as SecretCtrl;
 use crate::default;
 use crate::mongo;
+use crate::error;
 
 const ERR_SIGNATURE_HAS_FAILED: &str = "Signature verifier has failed";
 
 pub struct TxDelete<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
     label: &'a str,
     dust: &'a [u8],
     firm: &'a [u8],
 }
 
 impl<'a> TxDelete<'a> {
-    pub fn new(label: &'a str, dust: &'a [u8], firm: &'a [u8]) -> Self {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, label: &'a str, dust: &'a [u8], firm: &'a [u8]) -> Self {
         TxDelete{
-            label: label,
-            dust: dust,
-            firm: firm,
+            sessions,
+            namespaces,
+            label,
+            dust,
+            firm,
         }
     }
 
@@ -32,19 +37,23 @@ impl<'a> TxDelete<'a> {
         verifier.update(self.dust)?;
 
         if !verifier.verify(self.firm)? {
-            return Err(ERR_SIGNATURE_HAS_FAILED.into());
+            return Err(error::Error::Unauthenticated(ERR_SIGNATURE_HAS_FAILED.into()).into());
         }
 
-        if let Some(np) = namesp::get_instance().get_by_label(self.label) {
-            // application is using a namespace
-            for (cookie, token) in np.get_dirs_iter()  {
+        if let Some(np) = self.namespaces.get_by_label(self.label) {
+            // application is using a namespace, whose lock must be released before locking any session
+            let dirs: Vec<_> = np.lock().get_dirs_iter()
+                .map(|(cookie, token)| (cookie.clone(), token.clone()))
+                .collect();
+
+            for (cookie, token) in dirs {
                 // each opened directory must be deleted
-                if let Some(sess) = session::get_instance().get_by_cookie(cookie) {
-                    sess.delete_directory(token);
+                if let Some(sess) = self.sessions.get_by_cookie(&cookie)? {
+                    self.sessions.delete_directory(&mut sess.lock(), &token)?;
                 }
             }
 
-            namesp::get_instance().destroy_namespace(self.label)?;
+            self.namespaces.destroy_namespace(self.label)?;
         }
 
         let coll_name = mongo::get_collection_name()?;
@@ -0,0 +1,37 @@
+use std::error::Error;
+use crate::cookie;
+use crate::models::{session, secret, Gateway};
+use crate::default;
+use super::logout;
+
+pub struct TxDeleteKey<'a> {
+    sessions: &'a dyn session::Factory,
+    cookie: &'a str,
+    name: &'a str,
+    pwd: &'a str,
+    meta: &'a session::Metadata,
+}
+
+impl<'a> TxDeleteKey<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, cookie: &'a str, name: &'a str, pwd: &'a str, meta: &'a session::Metadata) -> Self {
+        TxDeleteKey{
+            sessions,
+            cookie,
+            name,
+            pwd,
+            meta,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a DeleteKey request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let client_id = logout::reauthenticate(self.sessions, self.cookie, self.pwd, self.meta)?
+            .lock()
+            .get_client_id();
+
+        let name = format!("{}{}", default::KEY_PREFIX, self.name);
+        let secret = secret::find_by_client_and_name(client_id, &name)?;
+        secret.delete()
+    }
+}
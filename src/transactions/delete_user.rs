@@ -2,41 +2,36 @@ use std::error::Error;
 use crate::models::{user, session, namesp, Gateway};
 use crate::regex::*;
 use crate::mongo;
+use super::{logout, login};
+use crate::error;
 
 const ERR_IDENT_NOT_MATCH: &str = "The provided indentity is not of the expected type";
-const ERR_PWD_NOT_MATCH: &str = "The provided password does not match";
 
 pub struct TxDelete<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
     ident: &'a str,
     pwd: &'a str,
+    meta: &'a session::Metadata,
 }
 
 impl<'a> TxDelete<'a> {
-    pub fn new(ident: &'a str, pwd: &'a str) -> Self {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, ident: &'a str, pwd: &'a str, meta: &'a session::Metadata) -> Self {
         TxDelete{
-            ident: ident,
-            pwd: pwd,
+            sessions,
+            namespaces,
+            ident,
+            pwd,
+            meta,
         }
     }
 
     fn clear_user_data(&self, user: Box<&dyn user::Ctrl>) -> Result<i32, Box<dyn Error>> {
-        if !user.match_pwd(self.pwd) {
-            return Err(ERR_PWD_NOT_MATCH.into());
-        }
-
-        if let Some(sess) = session::get_instance().get_by_email(user.get_email()) {
-            // user has session            
-            for token in sess.get_open_dirs() {
-                // foreach loged-in application
-                if let Some(app_id) = sess.delete_directory(&token) {
-                    if let Some(np) = namesp::get_instance().get_by_id(app_id) {
-                        // application is using a namespace
-                        np.delete_token(sess.get_cookie());
-                    }
-                }
-            }
+        login::check_pwd(self.ident, self.meta, || user.match_pwd(self.pwd))?;
 
-            session::get_instance().destroy_session(&sess.get_cookie())?;
+        for sess in self.sessions.get_by_email(user.get_email())? {
+            // foreach user's session, no matter the device
+            logout::close_session(self.sessions, self.namespaces, &mut sess.lock())?;
         }
 
         Ok(user.get_id())
@@ -56,7 +51,7 @@ impl<'a> TxDelete<'a> {
             user_id = self.clear_user_data(Box::new(user.as_ref()))?;
             user_gw = user;
         } else {
-            return Err(ERR_IDENT_NOT_MATCH.into());
+            return Err(error::Error::invalid("ident", ERR_IDENT_NOT_MATCH).into());
         }
 
         let coll_name = mongo::get_collection_name()?;
@@ -0,0 +1,74 @@
+use std::error::Error;
+use crate::cookie;
+use std::time::{Duration, SystemTime};
+use crate::time;
+use crate::totp;
+use crate::default;
+use crate::models::{session, secret, Gateway};
+use crate::error;
+use super::logout;
+
+// Proto message structs
+use crate::proto::user_proto::TotpResponse;
+
+const ERR_TOTP_ENROLLED: &str = "The user has enrolled a second factor already";
+
+// find_sealed returns the client's sealed secret of the given name, if any
+pub fn find_sealed(client_id: i32, name: &str) -> Result<Option<Box<impl secret::SealedCtrl + Gateway>>, Box<dyn Error>> {
+    match secret::find_sealed_by_client_and_name(client_id, name) {
+        Ok(sealed) => Ok(Some(sealed)),
+        Err(err) => match error::Error::classify(err) {
+            error::Error::NotFound(_) => Ok(None),
+            err => Err(err.into()),
+        },
+    }
+}
+
+pub struct TxEnrollTotp<'a> {
+    sessions: &'a dyn session::Factory,
+    cookie: &'a str,
+    pwd: &'a str,
+    meta: &'a session::Metadata,
+}
+
+impl<'a> TxEnrollTotp<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, cookie: &'a str, pwd: &'a str, meta: &'a session::Metadata) -> Self {
+        TxEnrollTotp{
+            sessions,
+            cookie,
+            pwd,
+            meta,
+        }
+    }
+
+    pub fn execute(&self) -> Result<TotpResponse, Box<dyn Error>> {
+        println!("Got an EnrollTotp request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let (client_id, email) = { // block is required because of session's lock release
+            let sess = logout::reauthenticate(self.sessions, self.cookie, self.pwd, self.meta)?;
+            let sess = sess.lock();
+            (sess.get_client_id(), sess.get_email().to_string())
+        };
+
+        if find_sealed(client_id, default::TOTP_NAME)?.is_some() {
+            return Err(error::Error::AlreadyExists(ERR_TOTP_ENROLLED.into()).into());
+        }
+
+        if let Some(pending) = find_sealed(client_id, default::TOTP_PENDING_NAME)? {
+            // a new enrollment replaces any other that has not been confirmed
+            pending.delete()?;
+        }
+
+        // the enrollment is swept away unless confirmed in time
+        let secret = totp::new_secret();
+        let deadline = SystemTime::now() + Duration::from_secs(default::TOTP_ENROLL_TIMEOUT);
+        let mut pending = secret::Secret::new_sealed(client_id, default::TOTP_PENDING_NAME, &totp::seal(client_id, &secret, 0)?, Some(deadline))?;
+        pending.insert()?;
+
+        Ok(TotpResponse{
+            uri: totp::uri(&email, &secret),
+            secret: totp::encode_secret(&secret),
+            deadline: time::unix_seconds(deadline)? as i64,
+        })
+    }
+}
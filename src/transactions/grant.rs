@@ -0,0 +1,300 @@
+use std::env;
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+use crate::jwt;
+use crate::token::Token;
+use crate::models::{app, authcode, refresh, secret, session, namesp, user, enums, Gateway};
+use crate::models::app::Ctrl as AppCtrl;
+use crate::models::authcode::Ctrl as AuthCodeCtrl;
+use crate::models::refresh::Ctrl as RefreshCtrl;
+use crate::models::secret::Ctrl as SecretCtrl;
+use crate::models::user::Ctrl as UserCtrl;
+use crate::default;
+use crate::oauth;
+use crate::error;
+use super::login;
+
+// Proto message structs
+use crate::proto::app_proto::TokenResponse;
+
+const ERR_GRANT_TYPE: &str = "Only the authorization code and refresh token grant types are supported";
+const ERR_MISSING_PARAMS: &str = "Both the code and the code verifier are required";
+const ERR_MISSING_REFRESH: &str = "The refresh token is required";
+const ERR_CLIENT_NOT_FOUND: &str = "The client is not a registered application";
+const ERR_ASSERTION_TYPE: &str = "Only jwt client assertions are supported";
+const ERR_CLIENT_NOT_AUTHENTICATED: &str = "The client assertion is not valid";
+const ERR_CODE_NOT_VALID: &str = "The authorization code is not valid";
+const ERR_CODE_NOT_MATCH: &str = "The authorization code was not issued for this client and redirect uri";
+const ERR_VERIFIER_NOT_MATCH: &str = "The code verifier does not match the code challenge";
+const ERR_SESSION_CLOSED: &str = "The session the grant was issued for is no longer alive";
+const ERR_REFRESH_NOT_VALID: &str = "The refresh token is not valid";
+const ERR_REFRESH_REUSED: &str = "The refresh token has already been used, so all the tokens of its family have been revoked";
+const ERR_USER_NOT_ALLOWED: &str = "The user is no longer allowed to log in";
+const ERR_CONSENT_REVOKED: &str = "The user no longer grants the scopes the refresh token was issued for";
+
+lazy_static! {
+    // refresh tokens can be rotated for as long as this since their family was started
+    static ref REFRESH_LIFETIME: Duration = env::var(default::ENV_REFRESH_TOKEN_LIFETIME).ok()
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(default::REFRESH_TOKEN_LIFETIME));
+
+    static ref ACCESS_LIFETIME: Duration = env::var(default::ENV_ACCESS_TOKEN_LIFETIME).ok()
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(default::ACCESS_TOKEN_LIFETIME));
+}
+
+fn invalid_grant(msg: &str) -> Box<dyn Error> {
+    error::Error::OAuth(oauth::INVALID_GRANT, msg.into()).into()
+}
+
+// revoke_family removes all the refresh tokens of the family, and logs out of the app every session they were issued for
+pub fn revoke_family(sessions: &dyn session::Factory, namespaces: &dyn namesp::Factory, family: &str, app_id: i32) -> Result<(), Box<dyn Error>> {
+    for cookie in refresh::delete_by_family(family)? {
+        let sess = match sessions.get_by_cookie(&cookie)? {
+            Some(sess) => sess,
+            None => continue,
+        };
+
+        // a session must always be locked before any namespace
+        let mut sess = sess.lock();
+        if let Some(token) = sess.get_token(app_id).cloned() {
+            sessions.delete_directory(&mut sess, &token)?;
+            if let Some(np) = namespaces.get_by_id(app_id) {
+                np.lock().delete_token(sess.get_cookie());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// GrantParams are those of a token request, as defined by RFC 6749 sections 4.1.3 and 6, RFC 7636 section 4.5 and
+// RFC 7523 section 2.2
+#[derive(Default)]
+pub struct GrantParams<'a> {
+    pub grant_type: &'a str,
+    pub code: &'a str,
+    pub redirect_uri: &'a str,
+    pub client_id: &'a str, // the label of the application
+    pub code_verifier: &'a str,
+    pub refresh_token: &'a str,
+    pub client_assertion_type: &'a str,
+    pub client_assertion: &'a str,
+}
+
+pub struct TxGrant<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    grant_type: &'a str,
+    code: &'a str,
+    redirect_uri: &'a str,
+    client_id: &'a str,
+    code_verifier: &'a str,
+    refresh_token: &'a str,
+    client_assertion_type: &'a str,
+    client_assertion: &'a str,
+}
+
+impl<'a> TxGrant<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, params: GrantParams<'a>) -> Self {
+        TxGrant{
+            sessions,
+            namespaces,
+            grant_type: params.grant_type,
+            code: params.code,
+            redirect_uri: params.redirect_uri,
+            client_id: params.client_id,
+            code_verifier: params.code_verifier,
+            refresh_token: params.refresh_token,
+            client_assertion_type: params.client_assertion_type,
+            client_assertion: params.client_assertion,
+        }
+    }
+
+    fn precondition(&self) -> Result<(), Box<dyn Error>> {
+        match self.grant_type {
+            oauth::GRANT_TYPE_CODE if self.code.is_empty() || self.code_verifier.is_empty() => {
+                Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_MISSING_PARAMS.into()).into())
+            },
+
+            oauth::GRANT_TYPE_REFRESH if self.refresh_token.is_empty() => {
+                Err(error::Error::OAuth(oauth::INVALID_REQUEST, ERR_MISSING_REFRESH.into()).into())
+            },
+
+            oauth::GRANT_TYPE_CODE | oauth::GRANT_TYPE_REFRESH => Ok(()),
+            _ => Err(error::Error::OAuth(oauth::UNSUPPORTED_GRANT_TYPE, ERR_GRANT_TYPE.into()).into()),
+        }
+    }
+
+    // authenticate_client makes sure the request comes from the app itself, which is the only one holding its key
+    fn authenticate_client(&self, app: &dyn app::Ctrl) -> Result<(), Box<dyn Error>> {
+        if self.client_assertion_type != oauth::CLIENT_ASSERTION_TYPE_JWT {
+            return Err(error::Error::OAuth(oauth::INVALID_CLIENT, ERR_ASSERTION_TYPE.into()).into());
+        }
+
+        // the assertion may be issued either for the token endpoint or the server as a whole, as RFC 7523 allows
+        let issuer = jwt::get_instance().get_issuer();
+        let endpoint = format!("{}{}", issuer, oauth::PATH_TOKEN);
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME)?;
+        let verifier = secret.get_verifier()?;
+        if jwt::decode_assertion(self.client_assertion, verifier, self.client_id, &[&endpoint, issuer]).is_err() {
+            return Err(error::Error::OAuth(oauth::INVALID_CLIENT, ERR_CLIENT_NOT_AUTHENTICATED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    // consume_code makes sure the code was issued for the client and the redirect uri, returning it once consumed
+    fn consume_code(&self, app_id: i32) -> Result<Box<dyn authcode::Ctrl>, Box<dyn Error>> {
+        let mut authcode = authcode::find_by_code(&Token::from_string(self.code))
+            .map_err(|_| invalid_grant(ERR_CODE_NOT_VALID))?;
+
+        if authcode.is_alive().is_err() {
+            return Err(invalid_grant(ERR_CODE_NOT_VALID));
+        }
+
+        if authcode.get_app_id() != app_id || authcode.get_redirect_uri() != self.redirect_uri {
+            return Err(invalid_grant(ERR_CODE_NOT_MATCH));
+        }
+
+        if !authcode.match_verifier(self.code_verifier) {
+            return Err(invalid_grant(ERR_VERIFIER_NOT_MATCH));
+        }
+
+        authcode.consume();
+        if let Err(err) = authcode.update() {
+            return match error::Error::classify(err) {
+                // a racing request has exchanged the code in the meanwhile
+                error::Error::PermissionDenied(_) => Err(invalid_grant(ERR_CODE_NOT_VALID)),
+                err => Err(err.into()),
+            };
+        }
+
+        Ok(authcode)
+    }
+
+    // rotate_refresh makes sure the refresh token is used for the very first time, returning it once rotated; a replayed
+    // token may have been stolen, so its whole family gets revoked
+    fn rotate_refresh(&self, app_id: i32) -> Result<Box<dyn refresh::Ctrl>, Box<dyn Error>> {
+        let mut refresh = refresh::find_by_token(&Token::from_string(self.refresh_token))
+            .map_err(|_| invalid_grant(ERR_REFRESH_NOT_VALID))?;
+
+        if refresh.get_app_id() != app_id || refresh.is_alive().is_err() {
+            return Err(invalid_grant(ERR_REFRESH_NOT_VALID));
+        }
+
+        if refresh.is_rotated() {
+            revoke_family(self.sessions, self.namespaces, refresh.get_family(), app_id)?;
+            return Err(invalid_grant(ERR_REFRESH_REUSED));
+        }
+
+        refresh.rotate();
+        if let Err(err) = refresh.update() {
+            return match error::Error::classify(err) {
+                // a racing request has used the token in the meanwhile, so it is a replay as well
+                error::Error::PermissionDenied(_) => {
+                    revoke_family(self.sessions, self.namespaces, refresh.get_family(), app_id)?;
+                    Err(invalid_grant(ERR_REFRESH_REUSED))
+                },
+
+                err => Err(err.into()),
+            };
+        }
+
+        Ok(refresh)
+    }
+
+    // bound_session returns the session the refresh token was issued for, as long as it is still alive; otherwise the
+    // family gets revoked, since no refresh token can outlive its session
+    fn bound_session(&self, refresh: &dyn refresh::Ctrl) -> Result<session::Shared, Box<dyn Error>> {
+        let sess = match self.sessions.get_by_cookie(&refresh.get_cookie())? {
+            Some(sess) if sess.lock().is_alive().is_ok() => sess,
+            _ => {
+                revoke_family(self.sessions, self.namespaces, refresh.get_family(), refresh.get_app_id())?;
+                return Err(invalid_grant(ERR_SESSION_CLOSED));
+            },
+        };
+
+        let user = user::find_by_client_id(refresh.get_client_id())?;
+        if user.get_status() == enums::Status::HIDDEN {
+            revoke_family(self.sessions, self.namespaces, refresh.get_family(), refresh.get_app_id())?;
+            return Err(invalid_grant(ERR_USER_NOT_ALLOWED));
+        }
+
+        Ok(sess)
+    }
+
+    // issue_tokens logs the session in the app, returning the access token along with a refresh token of the given family,
+    // and an id token if the openid scope has been granted; the session's cookie is never told to the app this way
+    fn issue_tokens(&self, sess: session::Shared, app_id: i32, family: Option<&str>, scope: &[String], nonce: &str, deadline: SystemTime) -> Result<TokenResponse, Box<dyn Error>> {
+        let np = login::get_namespace(self.namespaces, self.client_id)?;
+        // a session must always be locked before any namespace
+        let mut sess = sess.lock();
+        if sess.is_alive().is_err() {
+            return Err(invalid_grant(ERR_SESSION_CLOSED));
+        }
+
+        self.sessions.touch_session(&mut sess)?;
+        login::enter_app(self.sessions, &mut sess, &np)?;
+        // the access token cannot outlive the session it has been issued for
+        let lifetime = sess.get_deadline().duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .min(*ACCESS_LIFETIME);
+
+        let claims = jwt::Claims::new(sess.get_user_id(), self.client_id, scope, lifetime);
+        let access_token = jwt::get_instance().encode(&claims)?;
+
+        let mut id_token = String::new();
+        if scope.iter().any(|item| item == oauth::SCOPE_OPENID) {
+            let auth_time = sess.get_cookie().get_created_at();
+            id_token = jwt::get_instance().encode_id_token(sess.get_user_id(), self.client_id, nonce, auth_time, lifetime)?;
+        }
+
+        // neither the refresh token can outlive the session
+        let deadline = deadline.min(sess.get_deadline());
+        let (mut refresh, refresh_token) = refresh::RefreshToken::new(family, sess.get_client_id(), app_id, sess.get_cookie(), sess.get_device(), scope, deadline);
+        refresh.insert()?;
+
+        Ok(TokenResponse{
+            access_token,
+            token_type: oauth::TOKEN_TYPE_BEARER.to_string(),
+            expires_in: lifetime.as_secs() as i64,
+            refresh_token: refresh_token.to_string(),
+            id_token,
+        })
+    }
+
+    pub fn execute(&self) -> Result<TokenResponse, Box<dyn Error>> {
+        println!("Got a Token request from app {} ", self.client_id);
+        self.precondition()?;
+
+        let app = app::find_by_label(self.client_id)
+            .map_err(|_| error::Error::OAuth(oauth::INVALID_CLIENT, ERR_CLIENT_NOT_FOUND.into()))?;
+
+        self.authenticate_client(app.as_ref())?;
+        if self.grant_type == oauth::GRANT_TYPE_REFRESH {
+            let refresh = self.rotate_refresh(app.get_id())?;
+            let sess = self.bound_session(refresh.as_ref())?;
+            // the new token belongs to the same family, which is not extended by rotating it; nor is its scope, which the
+            // user must still grant
+            let scope = refresh.get_scope();
+            if !login::pending_consent(refresh.get_client_id(), app.get_id(), &scope)?.is_empty() {
+                revoke_family(self.sessions, self.namespaces, refresh.get_family(), app.get_id())?;
+                return Err(invalid_grant(ERR_CONSENT_REVOKED));
+            }
+
+            return self.issue_tokens(sess, app.get_id(), Some(refresh.get_family()), &scope, "", refresh.get_deadline());
+        }
+
+        let authcode = self.consume_code(app.get_id())?;
+        let sess = match self.sessions.get_by_cookie(&authcode.get_cookie())? {
+            Some(sess) => sess,
+            None => return Err(invalid_grant(ERR_SESSION_CLOSED)),
+        };
+
+        let scope = authcode.get_scope();
+        self.issue_tokens(sess, app.get_id(), None, &scope, authcode.get_nonce(), SystemTime::now() + *REFRESH_LIFETIME)
+    }
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+use crate::cookie;
+use crate::time;
+use crate::models::{secret, session, namesp};
+use crate::error;
+use crate::proto::Status;
+use super::login;
+
+// Proto message structs
+use crate::proto::app_proto::IntrospectResponse;
+
+const ERR_SIGNATURE_HAS_FAILED: &str = "Signature verifier has failed";
+const ERR_COOKIE_NOT_VALID: &str = "The provided cookie is not valid for this application";
+
+pub struct TxIntrospect<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    label: &'a str,
+    cookie: &'a str,
+    dust: &'a [u8],
+    firm: &'a [u8],
+}
+
+impl<'a> TxIntrospect<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, label: &'a str, cookie: &'a str, dust: &'a [u8], firm: &'a [u8]) -> Self {
+        TxIntrospect{
+            sessions,
+            namespaces,
+            label,
+            cookie,
+            dust,
+            firm,
+        }
+    }
+
+    fn verify_firm(&self, secret: &dyn secret::Ctrl) -> Result<(), Box<dyn Error>> {
+        let mut verifier = secret.get_verifier()?;
+        verifier.update(self.label.as_bytes())?;
+        verifier.update(self.cookie.as_bytes())?;
+        verifier.update(self.dust)?;
+
+        if !verifier.verify(self.firm)? {
+            return Err(error::Error::Unauthenticated(ERR_SIGNATURE_HAS_FAILED.into()).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(&self) -> Result<IntrospectResponse, Box<dyn Error>> {
+        println!("Got an Introspection request from app {} ", self.label);
+
+        let np = login::get_namespace(self.namespaces, self.label)?;
+        let (sess_token, dir_token) = cookie::split_cookie(self.cookie)?;
+        let app_id = { // block is required because of namespace's lock release
+            let np = np.lock();
+            self.verify_firm(np.get_secret().as_ref())?;
+            np.get_id()
+        };
+
+        if let Some(sess) = self.sessions.get_by_cookie(&sess_token.digest())? {
+            let mut sess = sess.lock();
+            if sess.get_status() == Status::Dead {
+                // the session has been closed, which is all the application needs to know
+                return Ok(IntrospectResponse{
+                    status: Status::Dead as i32,
+                    ..Default::default()
+                });
+            }
+
+            // the session store, and not the namespace of this very instance, tells whether the directory is still open
+            let is_valid = sess.get_directory(&dir_token.digest())
+                .map(|dir| dir.get_app_id() == app_id)
+                .unwrap_or(false);
+
+            if !is_valid {
+                return Err(error::Error::NotFound(ERR_COOKIE_NOT_VALID.into()).into());
+            }
+
+            // the cookie belongs to a living session
+            sess.is_alive()?;
+            self.sessions.touch_session(&mut sess)?;
+            return Ok(IntrospectResponse{
+                user_id: sess.get_user_id(),
+                name: sess.get_name().to_string(),
+                email: sess.get_email().to_string(),
+                status: sess.get_status() as i32,
+                deadline: time::unix_seconds(sess.get_deadline())? as i64,
+                scope: login::granted_scope(sess.get_client_id(), app_id)?,
+            });
+        }
+
+        Err(error::Error::NotFound(ERR_COOKIE_NOT_VALID.into()).into())
+    }
+}
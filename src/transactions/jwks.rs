@@ -0,0 +1,31 @@
+use std::error::Error;
+use crate::jwt;
+
+// Proto message structs
+use crate::proto::app_proto::{Jwk, JwksResponse};
+
+pub struct TxJwks {}
+
+impl TxJwks {
+    pub fn new() -> Self {
+        TxJwks{}
+    }
+
+    pub fn execute(&self) -> Result<JwksResponse, Box<dyn Error>> {
+        let keys = jwt::get_instance().jwks()?.into_iter()
+            .map(|jwk| Jwk{
+                kty: jwk.kty,
+                kid: jwk.kid,
+                alg: jwk.alg,
+                crv: jwk.crv,
+                n: jwk.n,
+                e: jwk.e,
+                x: jwk.x,
+            })
+            .collect();
+
+        Ok(JwksResponse{
+            keys,
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use std::error::Error;
+use crate::cookie;
+use crate::time;
+use crate::models::{session, consent, app};
+use crate::models::app::Ctrl as AppCtrl;
+use crate::models::consent::Ctrl as ConsentCtrl;
+use super::logout;
+
+// Proto message structs
+use crate::proto::user_proto::{ConsentsResponse, ConsentInfo};
+
+pub struct TxListConsents<'a> {
+    sessions: &'a dyn session::Factory,
+    cookie: &'a str,
+}
+
+impl<'a> TxListConsents<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, cookie: &'a str) -> Self {
+        TxListConsents{
+            sessions,
+            cookie,
+        }
+    }
+
+    pub fn execute(&self) -> Result<ConsentsResponse, Box<dyn Error>> {
+        println!("Got a ListConsents request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let client_id = { // block is required because of session's lock release
+            let current = logout::authenticate(self.sessions, self.cookie)?;
+            let client_id = current.lock().get_client_id();
+            client_id
+        };
+
+        let mut consents = Vec::new();
+        for consent in consent::find_all_by_client(client_id)? {
+            // foreach application the user has consented to
+            let app = app::find_by_id(consent.get_app_id())?;
+            consents.push(ConsentInfo{
+                app: app.get_label().to_string(),
+                name: app.get_name().to_string(),
+                url: app.get_url().to_string(),
+                scope: consent.get_scope(),
+                created_at: time::unix_seconds(consent.get_created_at())? as i64,
+                updated_at: time::unix_seconds(consent.get_updated_at())? as i64,
+            });
+        }
+
+        consents.sort_by_key(|info| info.created_at);
+        Ok(ConsentsResponse{
+            consents,
+        })
+    }
+}
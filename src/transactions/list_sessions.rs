@@ -0,0 +1,80 @@
+use std::error::Error;
+use crate::cookie;
+use crate::time;
+use crate::models::{session, namesp, app};
+use crate::models::app::Ctrl as AppCtrl;
+use super::logout;
+
+// Proto message structs
+use crate::proto::user_proto::{SessionsResponse, SessionInfo};
+
+pub struct TxListSessions<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    cookie: &'a str,
+}
+
+impl<'a> TxListSessions<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, cookie: &'a str) -> Self {
+        TxListSessions{
+            sessions,
+            namespaces,
+            cookie,
+        }
+    }
+
+    fn get_app_labels(&self, sess: &Box<dyn session::Ctrl>) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut labels = Vec::new();
+        for token in sess.get_open_dirs() {
+            // foreach loged-in application
+            let app_id = match sess.get_directory(&token) {
+                Some(dir) => dir.get_app_id(),
+                None => continue,
+            };
+
+            if let Some(np) = self.namespaces.get_by_id(app_id) {
+                // application is using a namespace
+                labels.push(np.lock().get_label().to_string());
+            } else {
+                labels.push(app::find_by_id(app_id)?.get_label().to_string());
+            }
+        }
+
+        labels.sort();
+        Ok(labels)
+    }
+
+    pub fn execute(&self) -> Result<SessionsResponse, Box<dyn Error>> {
+        println!("Got a ListSessions request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let (cookie, email) = { // block is required because of session's lock release
+            let current = logout::authenticate(self.sessions, self.cookie)?;
+            let current = current.lock();
+            (current.get_cookie().clone(), current.get_email().to_string())
+        };
+
+        let mut sessions = Vec::new();
+        for sess in self.sessions.get_by_email(&email)? {
+            // foreach user's session, no matter the device
+            let sess = sess.lock();
+            let meta = sess.get_metadata();
+            sessions.push(SessionInfo{
+                id: sess.get_cookie().fingerprint(),
+                status: sess.get_status() as i32,
+                created_at: time::unix_seconds(sess.get_cookie().get_created_at())? as i64,
+                touch_at: time::unix_seconds(sess.get_touch_at())? as i64,
+                device: sess.get_device().to_string(),
+                apps: self.get_app_labels(&sess)?,
+                current: *sess.get_cookie() == cookie,
+                peer_addr: meta.peer_addr.clone(),
+                user_agent: meta.user_agent.clone(),
+                forwarded_for: meta.forwarded_for.clone(),
+            });
+        }
+
+        sessions.sort_by_key(|info| info.created_at);
+        Ok(SessionsResponse{
+            sessions,
+        })
+    }
+}
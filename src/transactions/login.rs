@@ -1,9 +1,22 @@
+use std::env;
 use std::error::Error;
+use std::time::{Duration, SystemTime};
 use crate::regex::*;
+use crate::time;
 use crate::token::Token;
-use crate::models::{session, namesp, user, app, secret};
+use crate::cookie;
+use crate::throttle;
+use crate::models::{session, namesp, user, app, secret, ticket, consent, enums, Gateway};
+use crate::models::user::Ctrl as UserCtrl;
+use crate::models::consent::Ctrl as ConsentCtrl;
 use crate::models::app::Ctrl as AppCtrl;
+use crate::models::ticket::Ctrl as TicketCtrl;
+use crate::models::secret::Ctrl as SecretCtrl;
 use crate::default;
+use crate::mailer::{outbox, template};
+use crate::error;
+use crate::proto::{Status, TicketKind};
+use super::{logout, confirm_totp};
 
 // Proto message structs
 use crate::proto::user_proto;
@@ -11,107 +24,657 @@ use user_proto::LoginResponse;
 
 const ERR_IDENT_NOT_MATCH: &str = "The provided indentity is not of the expected type";
 const ERR_PWD_NOT_MATCH: &str = "The provided password does not match";
+const ERR_CLIENT_PENDING: &str = "The user has not verified its email yet";
+const ERR_CLIENT_HIDDEN: &str = "The user is not allowed to log in";
+const ERR_REAUTH_REQUIRED: &str = "The application requires the user to log in with its credentials";
+const ERR_CHALLENGE_FORMAT: &str = "The provided challenge does not match with a real one";
+const ERR_CHALLENGE_NOT_MATCH: &str = "The provided challenge is not valid";
+const ERR_CHALLENGE_NOT_COMPLETED: &str = "The provided code or signature, or the user's consent, does not complete the challenge";
+const ERR_SESSION_STALE: &str = "The session has been opened by a key that is no longer the current one";
+const CHALLENGE_SEPARATOR: char = '.';
+
+// PendingPolicy tells whether users that have not verified their email yet may log in
+enum PendingPolicy {
+    Allow,
+    Grace(Duration), // only for a while since signing up
+    Reject,
+}
+
+// Principal is whom a set of credentials has been verified for
+enum Principal {
+    Session(session::Shared), // the user has a session on the device already
+    User(Box<dyn user::Ctrl>),
+}
+
+impl Principal {
+    fn get_client_id(&self) -> i32 {
+        match self {
+            Principal::Session(sess) => sess.lock().get_client_id(),
+            Principal::User(user) => user.get_client_id(),
+        }
+    }
+}
+
+// Access is what a set of valid credentials grants: either a session, or a challenge to complete with a second factor
+enum Access {
+    Granted(session::Shared),
+    Challenged(LoginResponse),
+}
+
+lazy_static! {
+    static ref PENDING_POLICY: PendingPolicy = {
+        let policy = env::var(default::ENV_PENDING_POLICY)
+            .unwrap_or(default::PENDING_POLICY.to_string());
+
+        match policy.to_lowercase().as_str() {
+            "reject" => PendingPolicy::Reject,
+            "grace" => {
+                let grace = env::var(default::ENV_PENDING_GRACE).ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(default::PENDING_GRACE);
+                PendingPolicy::Grace(Duration::from_secs(grace))
+            },
+            _ => PendingPolicy::Allow,
+        }
+    };
+
+    // the oldest sessions of a user get closed once it has more than these
+    static ref MAX_SESSIONS: usize = env::var(default::ENV_SESSION_MAX_PER_USER).ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default::SESSION_MAX_PER_USER);
+}
+
+// get_namespace returns the app's namespace, building it if the app has none yet; whether a directory is open or not is
+// told by the session store, so the namespace is not filled up with the directories other instances may have opened
+pub fn get_namespace(namespaces: &dyn namesp::Factory, label: &str) -> Result<namesp::Shared, Box<dyn Error>> {
+    if let Some(np) = namespaces.get_by_label(label) {
+        return Ok(np);
+    }
+
+    let app = app::find_by_label(label)?;
+    let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME)?;
+    match namespaces.new_namespace(app, secret) {
+        Ok(np) => Ok(np),
+        // another request may have built it in the meanwhile
+        Err(err) => namespaces.get_by_label(label).ok_or(err),
+    }
+}
+
+// enter_app opens a directory of the session in the app's namespace, returning the token the app cookie is made of;
+// the session must be locked already, since a session is always locked before any namespace
+pub fn enter_app(sessions: &dyn session::Factory, sess: &mut Box<dyn session::Ctrl>, np: &namesp::Shared) -> Result<Token, Box<dyn Error>> {
+    let mut np = np.lock();
+    if let Some(token) = sess.reopen_directory(np.get_id()) {
+        // user is currently loged in the application, whose cookie remains the same
+        return Ok(token);
+    }
+
+    if let Some(token) = sess.get_token(np.get_id()).cloned() {
+        // the directory's token was derived by a key that is no longer the current one, so it cannot be told again
+        sessions.delete_directory(sess, &token)?;
+    }
+
+    // any other instance may have closed the directory this one has a token of
+    np.delete_token(sess.get_cookie());
+    let token = sessions.new_directory(sess, np.get_id())?;
+    np.set_token(sess.get_cookie().clone(), token.digest())?;
+    Ok(token)
+}
+
+// issue_challenge returns a brand new challenge of the given kind for the client, along with its deadline; the challenge
+// is made of a ticket's id and secret, so only the digest of the secret is kept
+pub fn issue_challenge(client_id: i32, kind: TicketKind) -> Result<(String, SystemTime), Box<dyn Error>> {
+    let timeout = Duration::from_secs(default::CHALLENGE_TIMEOUT);
+    let (mut ticket, secret) = ticket::Ticket::new(client_id, kind, timeout)?;
+    ticket.insert()?;
+
+    let challenge = format!("{}{}{}", ticket.get_id(), CHALLENGE_SEPARATOR, secret);
+    Ok((challenge, ticket.get_deadline()))
+}
+
+// issue_consent_challenge returns a brand new consent challenge for the client, just like issue_challenge does, but bound
+// to the app the user is asked to grant the scopes to
+fn issue_consent_challenge(client_id: i32, app_id: i32, scope: &[String]) -> Result<(String, SystemTime), Box<dyn Error>> {
+    let timeout = Duration::from_secs(default::CHALLENGE_TIMEOUT);
+    let (mut ticket, secret) = ticket::Ticket::new(client_id, TicketKind::Consent, timeout)?;
+    ticket.bind(app_id, scope);
+    ticket.insert()?;
+
+    let challenge = format!("{}{}{}", ticket.get_id(), CHALLENGE_SEPARATOR, secret);
+    Ok((challenge, ticket.get_deadline()))
+}
+
+// decoy_challenge returns a challenge just like issue_challenge does, but for no one: it is never stored, so it cannot be completed
+pub fn decoy_challenge(kind: TicketKind) -> Result<(String, SystemTime), Box<dyn Error>> {
+    let timeout = Duration::from_secs(default::CHALLENGE_TIMEOUT);
+    let (ticket, secret) = ticket::Ticket::new(0, kind, timeout)?;
+
+    let challenge = format!("{}{}{}", ticket.get_id(), CHALLENGE_SEPARATOR, secret);
+    Ok((challenge, ticket.get_deadline()))
+}
+
+// throttle_keys are the keys failed attempts are accounted to: the identity being guessed and the client guessing it
+pub fn throttle_keys(ident: &str, meta: &session::Metadata) -> Vec<String> {
+    let mut keys = vec![throttle::ident_key(ident)];
+    let addr = meta.client_addr();
+    if !addr.is_empty() {
+        keys.push(throttle::peer_key(addr));
+    }
+
+    keys
+}
+
+// check_pwd makes sure the password matches, accounting any failure to the same keys a failed login is accounted to; so
+// no other transaction asking for the password can be used to guess it
+pub fn check_pwd<F>(ident: &str, meta: &session::Metadata, matches: F) -> Result<(), Box<dyn Error>>
+    where F: FnOnce() -> bool {
+
+    let throttle = throttle::get_instance();
+    let keys = throttle_keys(ident, meta);
+    for key in keys.iter() {
+        throttle.check(key)?;
+    }
+
+    if !matches() {
+        for key in keys.iter() {
+            throttle.fail(key);
+        }
+
+        return Err(error::Error::Unauthenticated(ERR_PWD_NOT_MATCH.into()).into());
+    }
+
+    Ok(())
+}
+
+// granted_scope returns all the scopes the user has granted to the app, if any
+pub fn granted_scope(client_id: i32, app_id: i32) -> Result<Vec<String>, Box<dyn Error>> {
+    match consent::find_by_client_and_app(client_id, app_id) {
+        Ok(consent) => Ok(consent.get_scope()),
+        Err(err) => match error::Error::classify(err) {
+            error::Error::NotFound(_) => Ok(Vec::new()), // the user has never consented
+            err => Err(err.into()),
+        },
+    }
+}
+
+// pending_consent returns those of the scopes the user has not granted to the app yet
+pub fn pending_consent(client_id: i32, app_id: i32, scope: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    if scope.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let granted = granted_scope(client_id, app_id)?;
+    Ok(scope.iter().filter(|item| !granted.contains(item)).cloned().collect())
+}
+
+// request_consent mails the user a ticket granting the scopes to the app once resolved, since no one but the user may
+// consent on its behalf; any other ticket of the kind the user has not resolved yet is replaced by this one
+pub fn request_consent(client_id: i32, app_id: i32, scope: &[String]) -> Result<(), Box<dyn Error>> {
+    let user = user::find_by_client_id(client_id)?;
+    let app = app::find_by_id(app_id)?;
+    ticket::delete_by_client_and_kind(client_id, TicketKind::GrantConsent)?;
+
+    let timeout = Duration::new(default::TICKET_TIMEOUT, 0);
+    let (mut ticket, secret) = ticket::Ticket::new(client_id, TicketKind::GrantConsent, timeout)?;
+    ticket.bind(app_id, scope);
+
+    let msg = template::grant_consent(user.get_email(), user.get_name(), app.get_name(), scope, ticket.get_id(), secret.as_str());
+    ticket.insert_along(|connection| outbox::enqueue_on(connection, &msg))?;
+
+    Ok(())
+}
+
+// grant_consent records the user grants the scopes to the app, along with any granted before
+pub fn grant_consent(client_id: i32, app_id: i32, scope: &[String]) -> Result<(), Box<dyn Error>> {
+    match consent::find_by_client_and_app(client_id, app_id) {
+        Ok(mut consent) => {
+            consent.grant(scope);
+            consent.update()
+        },
+
+        Err(err) => match error::Error::classify(err) {
+            error::Error::NotFound(_) => consent::Consent::new(client_id, app_id, scope).insert(),
+            err => Err(err.into()),
+        },
+    }
+}
+
+// LoginParams are whatever the user logs in with; none of them is required by itself, so any may be left empty
+#[derive(Default)]
+pub struct LoginParams<'a> {
+    pub token: &'a str,     // the cookie of any other app, for single sign-on
+    pub ident: &'a str,     // the user name or email
+    pub pwd: &'a str,
+    pub challenge: &'a str, // the challenge of a previous login response, or a nonce
+    pub code: &'a str,      // the one-time password or recovery code completing the challenge
+    pub firm: &'a [u8],     // the signature of ident and nonce by any of the user's keys
+    pub app: &'a str,       // the label of the app the user is logging in
+    pub device: &'a str,
+}
 
 pub struct TxLogin<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    token: &'a str,
     ident: &'a str,
     pwd: &'a str,
+    challenge: &'a str,
+    code: &'a str,
+    firm: &'a [u8],
     app: &'a str,
+    device: &'a str,
+    meta: &'a session::Metadata,
 }
 
 impl<'a> TxLogin<'a> {
-    pub fn new(ident: &'a str, pwd: &'a str, app: &'a str) -> Self {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, params: LoginParams<'a>, meta: &'a session::Metadata) -> Self {
         TxLogin{
-            ident: ident,
-            pwd: pwd,
-            app: app,
+            sessions,
+            namespaces,
+            token: params.token,
+            ident: params.ident,
+            pwd: params.pwd,
+            challenge: params.challenge,
+            code: params.code,
+            firm: params.firm,
+            app: params.app,
+            device: params.device,
+            meta,
         }
     }
 
-    fn find_sess_by_identity(&self) -> Option<&mut Box<dyn session::Ctrl>> {
-        if let Ok(_) = match_name(self.ident) {
-            let sess = session::get_instance().get_by_name(self.ident)?;
-            Some(sess)
-        } else if let Ok(_) = match_email(self.ident) {
-            let sess = session::get_instance().get_by_email(self.ident)?;
-            Some(sess)
+    fn find_sess_by_identity(&self) -> Result<Option<session::Shared>, Box<dyn Error>> {
+        let all = if match_name(self.ident).is_ok() {
+            self.sessions.get_by_name(self.ident)?
+        } else if match_email(self.ident).is_ok() {
+            self.sessions.get_by_email(self.ident)?
         } else {
-            None
+            Vec::new()
+        };
+
+        // the user may have as many sessions as devices
+        Ok(all.into_iter().find(|sess| sess.lock().get_device() == self.device))
+    }
+
+    // evict_oldest closes the oldest sessions of the user until it has no more than allowed
+    fn evict_oldest(&self, email: &str) {
+        let all = match self.sessions.get_by_email(email) {
+            Ok(all) => all,
+            Err(err) => {
+                println!("Sessions of user {} could not be listed: {}", email, err);
+                return;
+            },
+        };
+
+        if all.len() <= *MAX_SESSIONS {
+            return;
+        }
+
+        let mut all: Vec<_> = all.into_iter()
+            .map(|sess| {
+                let created_at = sess.lock().get_cookie().get_created_at();
+                (created_at, sess)
+            })
+            .collect();
+
+        all.sort_by_key(|(created_at, _)| *created_at);
+        let exceeding = all.len() - *MAX_SESSIONS;
+        for (_, sess) in all.into_iter().take(exceeding) {
+            let mut sess = sess.lock();
+            if sess.get_status() == Status::Dead {
+                continue; // a racing login has already evicted it
+            }
+
+            if let Err(err) = logout::close_session(self.sessions, self.namespaces, &mut sess) {
+                // the eviction must not prevent the user from logging in
+                println!("Session {} could not be evicted: {}", sess.get_cookie().fingerprint(), err);
+            }
         }
     }
 
+    fn check_status(&self, user: &dyn user::Ctrl) -> Result<(), Box<dyn Error>> {
+        match user.get_status() {
+            enums::Status::ACTIVATED => Ok(()),
+            enums::Status::HIDDEN => Err(error::Error::PermissionDenied(ERR_CLIENT_HIDDEN.into()).into()),
+            enums::Status::PENDING => match *PENDING_POLICY {
+                PendingPolicy::Allow => Ok(()),
+                PendingPolicy::Grace(grace) if user.get_created_at() + grace > SystemTime::now() => Ok(()),
+                _ => Err(error::Error::PermissionDenied(ERR_CLIENT_PENDING.into()).into()),
+            },
+        }
+    }
+
+    // check_client_status applies check_status to the user a session belongs to, since whatever its status was when
+    // the session was opened it may have changed in the meanwhile
+    fn check_client_status(&self, sess: &session::Shared) -> Result<(), Box<dyn Error>> {
+        let client_id = sess.lock().get_client_id();
+        let user = user::find_by_client_id(client_id)?;
+        self.check_status(user.as_ref())
+    }
+
+    fn upgrade_pwd(&self, user: &mut (impl user::Ctrl + Gateway)) -> Result<(), Box<dyn Error>> {
+        if user.needs_rehash() {
+            // the stored hash is weaker than the current policy
+            user.set_pwd(self.pwd)?;
+            user.update()?;
+        }
+
+        Ok(())
+    }
+
     fn find_user_by_identity(&self) -> Result<Box<dyn user::Ctrl>, Box<dyn Error>> {
         if let Ok(_) = match_name(self.ident) {
-            let ctrl = user::find_by_name(self.ident)?;
+            let mut ctrl = user::find_by_name(self.ident)?;
+            if !ctrl.match_pwd(self.pwd) {
+                return Err(self.not_match(ERR_PWD_NOT_MATCH));
+            }
+
+            self.check_status(ctrl.as_ref())?;
+            self.upgrade_pwd(ctrl.as_mut())?;
             return Ok(ctrl);
         } else if let Ok(_) = match_email(self.ident) {
-            let ctrl = user::find_by_email(self.ident)?;
+            let mut ctrl = user::find_by_email(self.ident)?;
+            if !ctrl.match_pwd(self.pwd) {
+                return Err(self.not_match(ERR_PWD_NOT_MATCH));
+            }
+
+            self.check_status(ctrl.as_ref())?;
+            self.upgrade_pwd(ctrl.as_mut())?;
             return Ok(ctrl);
         }
         
-        Err(ERR_IDENT_NOT_MATCH.into())
+        Err(error::Error::invalid("ident", ERR_IDENT_NOT_MATCH).into())
+    }
+
+    // reopen returns the token of the session, which is never kept but derived again each time it has to be told
+    fn reopen(&self, sess: &Box<dyn session::Ctrl>) -> Result<Token, Box<dyn Error>> {
+        sess.reopen().ok_or_else(|| error::Error::Unauthenticated(ERR_SESSION_STALE.into()).into())
     }
 
-    fn session_response(&self, sess: &Box<dyn session::Ctrl>, token: &Token) -> LoginResponse {
-        LoginResponse {
-            cookie: format!("{}{}", sess.get_cookie(), token),
+    fn session_response(&self, sess: &Box<dyn session::Ctrl>, token: &Token) -> Result<LoginResponse, Box<dyn Error>> {
+        Ok(LoginResponse {
+            cookie: cookie::build_cookie(&self.reopen(sess)?, token)?,
             status: sess.get_status() as i32,
+            deadline: time::unix_seconds(sess.get_deadline())? as i64,
+            idle_deadline: time::unix_seconds(sess.get_idle_deadline())? as i64,
+            ..Default::default()
+        })
+    }
+
+    fn open_session(&self, user: Box<dyn user::Ctrl>) -> Result<session::Shared, Box<dyn Error>> {
+        match self.sessions.new_session(user, self.device, self.meta) {
+            Ok(sess) => {
+                let email = { // block is required because of session's lock release
+                    let guard = sess.lock();
+                    self.alert_new_login(&guard);
+                    guard.get_email().to_string()
+                };
+
+                self.evict_oldest(&email);
+                Ok(sess)
+            },
+
+            // a racing login may have opened it in the meanwhile
+            Err(err) => self.find_sess_by_identity()?.ok_or(err),
         }
     }
 
-    pub fn execute(&self) -> Result<LoginResponse, Box<dyn Error>> {
-        println!("Got Login request from user {} ", self.ident);
-        if let Some(sess) = self.find_sess_by_identity() {
-            // user has session
-            if !sess.match_pwd(self.pwd) {
-                return Err(ERR_PWD_NOT_MATCH.into());
-            } // password does match
-
-            if let Some(np) = namesp::get_instance().get_by_label(self.app) {
-                // application is using a namespace
-                if let Some(token) = sess.get_token(np.get_id()) {
-                    // user is currently loged in the application
-                    return Ok(self.session_response(sess, token));
-                }   
-
-                // user is not loged in the application
-                let token = sess.new_directory(np.get_id())?;
-                let resp = self.session_response(sess, &token);
-                np.set_token(sess.get_cookie().clone(), token)?;
-                return Ok(resp);
-            }         
-
-            // application has no namespace
-            let app = app::find_by_label(self.app)?;
-            let token = sess.new_directory(app.get_id())?;
-            let secret = secret::find_by_client_and_name(app.get_id(), default::RSA_NAME)?;
-            let np = namesp::get_instance().new_namespace(app, secret)?;
-            let resp = self.session_response(sess, &token);
-            np.set_token(sess.get_cookie().clone(), token)?;
-            return Ok(resp);
+    fn alert_new_login(&self, sess: &Box<dyn session::Ctrl>) {
+        let meta = sess.get_metadata();
+        let msg = template::new_login(sess.get_email(), sess.get_name(), self.app, sess.get_device(), &meta.peer_addr, &meta.user_agent);
+        if let Err(err) = outbox::enqueue(&msg) {
+            // the alert must not prevent the user from logging in
+            println!("Login alert for user {} could not be queued: {}", sess.get_name(), err);
+        }
+    }
+
+    // find_sess_by_cookie returns the session of the provided cookie, if any, so the user does not have to provide its credentials again
+    fn find_sess_by_cookie(&self) -> Result<Option<session::Shared>, Box<dyn Error>> {
+        if self.token.is_empty() {
+            return Ok(None);
+        }
+
+        let sess = match logout::authenticate(self.sessions, self.token).and_then(|sess| {
+            // the cookie of a session whose token cannot be told again is no longer valid for any other app
+            self.reopen(&sess.lock())?;
+            Ok(sess)
+        }) {
+            Ok(sess) => sess,
+            Err(err) if self.ident.is_empty() => return Err(err),
+            Err(_) => return Ok(None), // the cookie is no longer valid, but there are credentials to log in with
+        };
+
+        let np = get_namespace(self.namespaces, self.app)?;
+        if np.lock().get_force_reauth() {
+            // the application requires its users to log in with their credentials
+            if self.ident.is_empty() {
+                return Err(error::Error::Unauthenticated(ERR_REAUTH_REQUIRED.into()).into());
+            }
+
+            return Ok(None);
+        }
+
+        if !self.ident.is_empty() {
+            let guard = sess.lock();
+            if guard.get_email() != self.ident && guard.get_name() != self.ident {
+                // the user is logging in with another identity
+                return Ok(None);
+            }
+        }
+
+        self.check_client_status(&sess)?;
+        Ok(Some(sess))
+    }
+
+    fn not_match(&self, msg: &str) -> Box<dyn Error> {
+        let throttle = throttle::get_instance();
+        for key in throttle_keys(self.ident, self.meta) {
+            throttle.fail(&key);
+        }
+
+        error::Error::Unauthenticated(msg.into()).into()
+    }
+
+    fn find_sess_by_credentials(&self) -> Result<Access, Box<dyn Error>> {
+        let throttle = throttle::get_instance();
+        for key in throttle_keys(self.ident, self.meta) {
+            throttle.check(&key)?;
+        }
+
+        let (principal, first_factor) = if self.challenge.is_empty() {
+            (self.verify_pwd()?, true)
+        } else {
+            let (principal, kind) = self.verify_challenge()?;
+            (principal, kind == TicketKind::Signature)
+        };
+
+        // a password or a key are just the first factor, so whoever has enrolled a second one has to complete it as well
+        let client_id = principal.get_client_id();
+        if first_factor && confirm_totp::has_second_factor(client_id)? {
+            return self.challenge_second_factor(client_id).map(Access::Challenged);
+        }
+
+        let sess = self.grant(principal)?;
+        { // block is required because of session's lock release
+            // the identity is no longer under suspicion, while its client remains so
+            let guard = sess.lock();
+            throttle.reset(&throttle::ident_key(guard.get_email()));
+            throttle.reset(&throttle::ident_key(guard.get_name()));
+        }
+
+        Ok(Access::Granted(sess))
+    }
+
+    fn verify_pwd(&self) -> Result<Principal, Box<dyn Error>> {
+        match self.find_sess_by_identity()? {
+            Some(sess) => {
+                if !sess.lock().match_pwd(self.pwd) {
+                    return Err(self.not_match(ERR_PWD_NOT_MATCH));
+                } // password does match
+
+                self.check_client_status(&sess)?;
+                Ok(Principal::Session(sess))
+            },
+
+            // user has no session
+            None => Ok(Principal::User(self.find_user_by_identity()?)),
+        }
+    }
+
+    // challenge_second_factor returns the challenge the user has to complete with its second factor
+    fn challenge_second_factor(&self, client_id: i32) -> Result<LoginResponse, Box<dyn Error>> {
+        let (challenge, deadline) = issue_challenge(client_id, TicketKind::SecondFactor)?;
+        Ok(LoginResponse {
+            challenge,
+            deadline: time::unix_seconds(deadline)? as i64,
+            ..Default::default()
+        })
+    }
+
+    // ask_consent returns the challenge that gets completed once the user consents, unless it has granted the scopes the
+    // app asks for already
+    fn ask_consent(&self, client_id: i32, app_id: i32, scope: &[String]) -> Result<Option<LoginResponse>, Box<dyn Error>> {
+        let pending = pending_consent(client_id, app_id, scope)?;
+        if pending.is_empty() {
+            return Ok(None);
+        }
+
+        // the consent must come from the user itself, never from the app asking for it
+        request_consent(client_id, app_id, &pending)?;
+
+        // the user has proven its identity already, so it does not have to provide its credentials again
+        let (challenge, deadline) = issue_consent_challenge(client_id, app_id, &pending)?;
+        Ok(Some(LoginResponse {
+            challenge,
+            deadline: time::unix_seconds(deadline)? as i64,
+            consent: pending,
+            ..Default::default()
+        }))
+    }
+
+    // match_signature tells whether the ident and the challenge have been signed by any of the user's keys
+    fn match_signature(&self, client_id: i32) -> Result<bool, Box<dyn Error>> {
+        for key in secret::find_all_by_client_and_prefix(client_id, default::KEY_PREFIX)? {
+            let mut verifier = key.get_verifier()?;
+            verifier.update(self.ident.as_bytes())?;
+            verifier.update(self.challenge.as_bytes())?;
+            // a malformed signature is just as wrong as any other
+            if verifier.verify(self.firm).unwrap_or(false) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    // match_consent tells whether the user has granted the scopes the challenge was issued for, to the app the login is for
+    fn match_consent(&self, ticket: &dyn ticket::Ctrl) -> Result<bool, Box<dyn Error>> {
+        let app_id = get_namespace(self.namespaces, self.app)?.lock().get_id();
+        if ticket.get_app_id() != Some(app_id) {
+            return Ok(false);
+        }
+
+        Ok(pending_consent(ticket.get_client_id(), app_id, &ticket.get_scope())?.is_empty())
+    }
+
+    // verify_challenge makes sure the challenge has been issued to the identity, and the code or the signature completes it;
+    // the kind of the completed challenge is returned along the principal
+    fn verify_challenge(&self) -> Result<(Principal, TicketKind), Box<dyn Error>> {
+        let (id, secret) = match self.challenge.split_once(CHALLENGE_SEPARATOR) {
+            Some(parts) => parts,
+            None => return Err(error::Error::invalid("challenge", ERR_CHALLENGE_FORMAT).into()),
+        };
+
+        let mut ticket = ticket::find_by_id(id)?;
+        ticket.is_alive()?;
+        if !ticket.match_secret(secret) {
+            return Err(self.not_match(ERR_CHALLENGE_NOT_MATCH));
+        }
+
+        let user = user::find_by_client_id(ticket.get_client_id())?;
+        if user.get_email() != self.ident && user.get_name() != self.ident {
+            return Err(self.not_match(ERR_CHALLENGE_NOT_MATCH));
         }
 
-        // user has no session
-        let user = self.find_user_by_identity()?;
-        if !user.match_pwd(self.pwd) {
-            return Err(ERR_PWD_NOT_MATCH.into());
+        let completed = match ticket.get_kind() {
+            TicketKind::SecondFactor => confirm_totp::match_second_factor(ticket.get_client_id(), self.code)?,
+            TicketKind::Signature => self.match_signature(ticket.get_client_id())?,
+            TicketKind::Consent => self.match_consent(&*ticket)?,
+            _ => false,
+        };
+
+        if !completed {
+            return Err(self.not_match(ERR_CHALLENGE_NOT_COMPLETED));
+        }
+
+        // the challenge gets consumed once completed, so it cannot be completed twice
+        ticket.consume();
+        ticket.update()?;
+
+        self.check_status(user.as_ref())?;
+        let principal = match self.find_sess_by_identity()? {
+            Some(sess) => Principal::Session(sess),
+            None => Principal::User(user),
+        };
+
+        Ok((principal, ticket.get_kind()))
+    }
+
+    // grant returns the session the principal is logging in with, opening a new one if required
+    fn grant(&self, principal: Principal) -> Result<session::Shared, Box<dyn Error>> {
+        let sess = match principal {
+            Principal::Session(sess) => sess,
+            Principal::User(user) => return self.open_session(user),
+        };
+
+        let expired = { // block is required because of session's lock release
+            let mut guard = sess.lock();
+            if guard.is_alive().is_err() || guard.reopen().is_none() {
+                // neither an expired session nor one whose token cannot be told again can be reused, so the user has
+                // to start a new one
+                logout::close_session(self.sessions, self.namespaces, &mut guard)?;
+                Some(guard.get_client_id())
+            } else {
+                self.sessions.touch_session(&mut guard)?;
+                None
+            }
+        };
+
+        match expired {
+            Some(client_id) => {
+                let user = user::find_by_client_id(client_id)?;
+                self.check_status(user.as_ref())?;
+                self.open_session(user)
+            },
+
+            None => Ok(sess),
         }
+    }
+
+    pub fn execute(&self) -> Result<LoginResponse, Box<dyn Error>> {
+        println!("Got Login request from user {} ", self.ident);
+        let sess = match self.find_sess_by_cookie()? {
+            Some(sess) => sess, // single sign-on
+            None => match self.find_sess_by_credentials()? {
+                Access::Granted(sess) => sess,
+                Access::Challenged(resp) => return Ok(resp),
+            },
+        };
+
+        let np = get_namespace(self.namespaces, self.app)?;
+        let (app_id, scope) = { // block is required because of namespace's lock release
+            let np = np.lock();
+            (np.get_id(), np.get_scope())
+        };
 
-        let sess = session::get_instance().new_session(user)?;
-        if let Some(np) = namesp::get_instance().get_by_label(self.app) {
-            // application is using a namespace
-            let token = sess.new_directory(np.get_id())?;
-            let resp = self.session_response(sess, &token);
-            np.set_token(sess.get_cookie().clone(), token)?;
+        let client_id = sess.lock().get_client_id();
+        if let Some(resp) = self.ask_consent(client_id, app_id, &scope)? {
             return Ok(resp);
         }
 
-        // application has no namespace
-        let app = app::find_by_label(self.app)?;
-        let token = sess.new_directory(app.get_id())?;
-        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME)?;
-        let np = namesp::get_instance().new_namespace(app, secret)?;
-        let resp = self.session_response(sess, &token);
-        np.set_token(sess.get_cookie().clone(), token)?;
-        return Ok(resp);
+        // a session must always be locked before any namespace
+        let mut sess = sess.lock();
+        let token = enter_app(self.sessions, &mut sess, &np)?;
+        self.session_response(&sess, &token)
     }
 }
\ No newline at end of file
@@ -1,51 +1,104 @@
-use crate::token::Token;
 use std::error::Error;
-use crate::regex::*;
-use crate::models::{session, namesp};
-use crate::default;
+use crate::cookie;
+use crate::models::{session, namesp, refresh};
+use crate::error;
+use super::login;
 
 const ERR_SESSION_NOT_FOUND: &str = "Session not found for the provided cookie";
+const ERR_COOKIE_NOT_VALID: &str = "The provided cookie is not valid";
+
+// close_session removes all the session's directories from their namespaces before killing it
+pub fn close_session(sessions: &dyn session::Factory, namespaces: &dyn namesp::Factory, sess: &mut Box<dyn session::Ctrl>) -> Result<(), Box<dyn Error>> {
+    for token in sess.get_open_dirs() {
+        // foreach loged-in application
+        if let Some(app_id) = sessions.delete_directory(sess, &token)? {
+            if let Some(np) = namespaces.get_by_id(app_id) {
+                // application is using a namespace
+                np.lock().delete_token(sess.get_cookie());
+            }
+        }
+    }
+
+    sessions.destroy_session(sess)
+}
+
+// authenticate returns the living session the cookie belongs to, which counts as using it
+pub fn authenticate(sessions: &dyn session::Factory, cookie: &str) -> Result<session::Shared, Box<dyn Error>> {
+    authenticate_dir(sessions, cookie, None)
+}
+
+// authenticate_in returns the living session the cookie belongs to, as long as the cookie was issued for the given app
+pub fn authenticate_in(sessions: &dyn session::Factory, cookie: &str, app_id: i32) -> Result<session::Shared, Box<dyn Error>> {
+    authenticate_dir(sessions, cookie, Some(app_id))
+}
+
+// reauthenticate returns the living session the cookie belongs to, as long as the password is the user's one; so no app
+// holding the cookie can change the user's credentials on its own
+pub fn reauthenticate(sessions: &dyn session::Factory, cookie: &str, pwd: &str, meta: &session::Metadata) -> Result<session::Shared, Box<dyn Error>> {
+    let shared = authenticate(sessions, cookie)?;
+    { // block is required because of session's lock release
+        let sess = shared.lock();
+        login::check_pwd(sess.get_email(), meta, || sess.match_pwd(pwd))?;
+    }
+
+    Ok(shared)
+}
+
+fn authenticate_dir(sessions: &dyn session::Factory, cookie: &str, app_id: Option<i32>) -> Result<session::Shared, Box<dyn Error>> {
+    let (token, dir_token) = cookie::split_cookie(cookie)?;
+    let shared = match sessions.get_by_cookie(&token.digest())? {
+        Some(shared) => shared,
+        None => return Err(error::Error::Unauthenticated(ERR_SESSION_NOT_FOUND.into()).into()),
+    };
+
+    { // block is required because of session's lock release
+        let mut sess = shared.lock();
+        sess.is_alive()?;
+        match sess.get_directory(&dir_token.digest()) {
+            Some(dir) if app_id.is_none() || app_id == Some(dir.get_app_id()) => (),
+            _ => return Err(error::Error::Unauthenticated(ERR_COOKIE_NOT_VALID.into()).into()),
+        }
+
+        sessions.touch_session(&mut sess)?;
+    }
+
+    Ok(shared)
+}
 
 pub struct TxLogout<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
     cookie: &'a str,
 }
 
 impl<'a> TxLogout<'a> {
-    pub fn new(cookie: &'a str) -> Self {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, cookie: &'a str) -> Self {
         TxLogout{
-            cookie: cookie,
+            sessions,
+            namespaces,
+            cookie,
         }
     }
 
-    fn split_cookie(&self, left: bool) -> Result<Token, Box<dyn Error>> {
-        match_cookie(self.cookie)?;
-        let token: &str = {
-            if left {
-                &self.cookie[..default::TOKEN_LEN]
-            } else {
-                &self.cookie[default::TOKEN_LEN..]
-            }
-        };
-
-        Ok(Token::from_string(token))
-    }
-
     pub fn execute(&self) -> Result<(), Box<dyn Error>> {
-        println!("Got a Logout request for cookie {} ", self.cookie);
+        println!("Got a Logout request for cookie {} ", cookie::fingerprint(self.cookie));
         
-        let token = self.split_cookie(true)?;
-        if let Some(sess) = session::get_instance().get_by_cookie(&token) {
+        let (token, dir_token) = cookie::split_cookie(self.cookie)?;
+        if let Some(sess) = self.sessions.get_by_cookie(&token.digest())? {
             // user has a session
-            let dir_token = self.split_cookie(false)?;
-            if let Some(app_id) = sess.delete_directory(&dir_token) {
-                // user was loged in the application
-                if let Some(np) = namesp::get_instance().get_by_id(app_id) {
+            let mut sess = sess.lock();
+            sess.is_alive()?;
+            self.sessions.touch_session(&mut sess)?;
+            if let Some(app_id) = self.sessions.delete_directory(&mut sess, &dir_token.digest())? {
+                // user was loged in the application, which must not be able to log it back in
+                refresh::delete_by_cookie(sess.get_cookie(), Some(app_id))?;
+                if let Some(np) = self.namespaces.get_by_id(app_id) {
                     // application is using a namespace
-                    np.delete_token(sess.get_cookie());
+                    np.lock().delete_token(sess.get_cookie());
                 }
             }
         } else {
-            return Err(ERR_SESSION_NOT_FOUND.into());
+            return Err(error::Error::NotFound(ERR_SESSION_NOT_FOUND.into()).into());
         }
 
         Ok(())
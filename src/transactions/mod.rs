@@ -6,19 +6,39 @@ pub mod delete_app;
 pub mod register;
 pub mod ticket;
 pub mod resolve;
+pub mod introspect;
+pub mod sweep;
+pub mod list_sessions;
+pub mod revoke_session;
+pub mod revoke_others;
+pub mod enroll_totp;
+pub mod confirm_totp;
+pub mod nonce;
+pub mod register_key;
+pub mod delete_key;
+pub mod set_redirects;
+pub mod authorize;
+pub mod grant;
+pub mod jwks;
+pub mod userinfo;
+pub mod list_consents;
+pub mod revoke_consent;
 
 #[cfg(test)]
 mod tests {
-    use crate::token::Token;
-use crate::transactions::{signup, delete_user, register};
-    use crate::models::{user, secret, app, session, Gateway};
+    use crate::cookie;
+    use crate::transactions::{signup, delete_user, register};
+    use crate::models::{user, secret, app, session, namesp, ticket, Gateway};
     use openssl::sign::Signer;
     use openssl::encrypt::Decrypter;
     use openssl::rsa::{Rsa, Padding};
     use openssl::hash::MessageDigest;
     use openssl::pkey::PKey;
-    use crate::default::tests::{get_prefixed_data, DUMMY_DESCR, DUMMY_PWD};
+    use crate::default::tests::{get_prefixed_data, DUMMY_DESCR, DUMMY_PWD, DUMMY_DEVICE, DUMMY_META};
     use crate::default;
+    use super::login::LoginParams;
+    use super::authorize::AuthorizeParams;
+    use super::grant::GrantParams;
 
     #[test]
     fn signup() {
@@ -53,6 +73,8 @@ use crate::transactions::{signup, delete_user, register};
     fn delete_by_email() {
         crate::initialize();
         const PREFIX: &str = "delete_by_email";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
         
         let (name, email) = get_prefixed_data(PREFIX, false);
         
@@ -67,7 +89,7 @@ use crate::transactions::{signup, delete_user, register};
         };
         
         // Delete the user
-        let tx_dummy = delete_user::TxDelete::new(&email, DUMMY_PWD);
+        let tx_dummy = delete_user::TxDelete::new(&*sessions, &*namespaces, &email, DUMMY_PWD, &DUMMY_META);
         tx_dummy.execute().unwrap();
         
         // Checking the user data
@@ -79,6 +101,8 @@ use crate::transactions::{signup, delete_user, register};
     fn delete_by_name() {
         crate::initialize();
         const PREFIX: &str = "delete_by_name";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
         
         let (name, email) = get_prefixed_data(PREFIX, false);
         
@@ -93,7 +117,7 @@ use crate::transactions::{signup, delete_user, register};
         };
         
         // Delete the user
-        let tx_dummy = delete_user::TxDelete::new(&name, DUMMY_PWD);
+        let tx_dummy = delete_user::TxDelete::new(&*sessions, &*namespaces, &name, DUMMY_PWD, &DUMMY_META);
         tx_dummy.execute().unwrap();
         
         // Checking the user data
@@ -122,7 +146,7 @@ use crate::transactions::{signup, delete_user, register};
         let firm = signer.sign_to_vec().unwrap();
     
         // Register app
-        let tx_register = register::TxRegister::new(&name, &url, DUMMY_DESCR, &public, &firm);
+        let tx_register = register::TxRegister::new(&name, &url, DUMMY_DESCR, &public, false, &[], &firm);
         let resp = tx_register.execute().unwrap();
     
          // Decrypt the data
@@ -179,6 +203,8 @@ use crate::transactions::{signup, delete_user, register};
         use user::Ctrl as UserCtrl;
         crate::initialize();
         const PREFIX: &str = "login_by_email";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
     
         // Setting up the required client
         let (user_name, email) = get_prefixed_data(PREFIX, false);
@@ -202,7 +228,7 @@ use crate::transactions::{signup, delete_user, register};
         let firm = signer.sign_to_vec().unwrap();
     
         // Register app
-        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, &firm);
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
         let resp = tx_register.execute().unwrap();
 
         // Decrypt the data
@@ -221,24 +247,32 @@ use crate::transactions::{signup, delete_user, register};
         assert_eq!(app.get_label(), label);
 
         // Login the new client using its email
-        let tx_dummy = super::login::TxLogin::new(&email, DUMMY_PWD, &label);
+        let tx_dummy = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
         let resp = tx_dummy.execute().unwrap();
 
         use crate::proto::user_proto::Status;
         let status_new = Status::New as i32;
         assert_eq!(resp.status, status_new);
-        assert_eq!(resp.cookie.len(), 2 * default::TOKEN_LEN);
-
-        use crate::token::Token;
-        let cookie = &resp.cookie[..default::TOKEN_LEN];
-        let token = &resp.cookie[default::TOKEN_LEN..];
-        let cookie = Token::from_string(cookie);
-        let token = Token::from_string(token);
-        let sess = session::get_instance().get_by_cookie(&cookie).unwrap();
-        assert!(sess.get_directory(&token).is_some());
-        assert_eq!(sess.get_cookie().as_str(), cookie.as_str());
+        assert!(resp.cookie.starts_with("v1."));
+        assert!(resp.idle_deadline > 0);
+        assert!(resp.idle_deadline <= resp.deadline);
+
+        // logging in again reuses the very same session and directory
+        let again = tx_dummy.execute().unwrap();
+        assert_eq!(again.cookie, resp.cookie);
+        assert_eq!(again.status, Status::Alive as i32);
+
+        let (cookie, token) = cookie::split_cookie(&again.cookie).unwrap();
+        let sess = sessions.get_by_cookie(&cookie.digest()).unwrap().unwrap();
+        let sess = sess.lock();
+        assert!(sess.get_directory(&token.digest()).is_some());
+        assert_eq!(sess.get_cookie().as_str(), cookie.digest().as_str());
+
+        // no token is kept as plain text
+        assert_ne!(sess.get_cookie().as_str(), cookie.as_str());
         let got_token = sess.get_token(app.get_id()).unwrap();
-        assert_eq!(got_token.as_str(), token.as_str());
+        assert_eq!(got_token.as_str(), token.digest().as_str());
+        assert_ne!(got_token.as_str(), token.as_str());
         assert_eq!(sess.get_user_id(), user.get_id());
 
         // Checking there is a default secret for the app
@@ -257,6 +291,8 @@ use crate::transactions::{signup, delete_user, register};
         use app::Ctrl;
         crate::initialize();
         const PREFIX: &str = "login_by_name";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
     
         // Setting up the required client
         let (user_name, email) = get_prefixed_data(PREFIX, false);
@@ -280,7 +316,7 @@ use crate::transactions::{signup, delete_user, register};
         let firm = signer.sign_to_vec().unwrap();
     
         // Register app
-        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, &firm);
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
         let resp = tx_register.execute().unwrap();
 
         // Decrypt the data
@@ -299,7 +335,7 @@ use crate::transactions::{signup, delete_user, register};
         assert_eq!(app.get_label(), label);
 
         // Login the new client using its email
-        let tx_dummy = super::login::TxLogin::new(&user_name, DUMMY_PWD, &label);
+        let tx_dummy = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &user_name, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
         assert!(tx_dummy.execute().is_ok());
 
         // Checking there is a default secret for the app
@@ -318,6 +354,8 @@ use crate::transactions::{signup, delete_user, register};
         use app::Ctrl as AppCtrl;
         crate::initialize();
         const PREFIX: &str = "logout";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
     
         // Setting up the required client
         let (user_name, email) = get_prefixed_data(PREFIX, false);
@@ -341,7 +379,7 @@ use crate::transactions::{signup, delete_user, register};
         let firm = signer.sign_to_vec().unwrap();
     
         // Register app
-        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, &firm);
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
         let resp = tx_register.execute().unwrap();
 
         // Decrypt the data
@@ -359,14 +397,115 @@ use crate::transactions::{signup, delete_user, register};
         let app = app::find_by_label(&label).unwrap();
 
         // Login the new client using its email
-        let tx_dummy = super::login::TxLogin::new(&email, DUMMY_PWD, &label);
+        let tx_dummy = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
         let resp = tx_dummy.execute().unwrap();
-        let tx_dummy = super::logout::TxLogout::new(&resp.cookie);
+        let tx_dummy = super::logout::TxLogout::new(&*sessions, &*namespaces, &resp.cookie);
         assert!(tx_dummy.execute().is_ok());
-        let cookie = Token::from_string(&resp.cookie[..default::TOKEN_LEN]);
-        let sess = session::get_instance().get_by_cookie(&cookie).unwrap();
-        let token = Token::from_string(&resp.cookie[default::TOKEN_LEN..]);
-        assert!(sess.get_directory(&token).is_none());
+        let cookie = cookie::split_cookie(&resp.cookie).unwrap().0;
+        let sess = sessions.get_by_cookie(&cookie.digest()).unwrap().unwrap();
+        let sess = sess.lock();
+        let token = cookie::split_cookie(&resp.cookie).unwrap().1;
+        assert!(sess.get_directory(&token.digest()).is_none());
+
+        // Checking there is a default secret for the app
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+    
+        // Deleting the dummy user and its data from the database
+        secret.delete().unwrap();
+        // Deleting the app and client
+        app.delete().unwrap();
+        // Deleting the user and client
+        user.delete().unwrap();
+    }
+
+    #[test]
+    fn introspect() {
+        use app::Ctrl as AppCtrl;
+        use user::Ctrl as UserCtrl;
+        crate::initialize();
+        const PREFIX: &str = "introspect";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
+    
+        // Setting up the required client
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+    
+        let user = user::find_by_name(&user_name).unwrap();
+    
+        // Generate a keypair
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+    
+        // Register app
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        // Decrypt the data
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        // Create an output buffer
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        // Encrypt and truncate the buffer
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+ 
+        let label = String::from_utf8(decrypted).unwrap();
+        let app = app::find_by_label(&label).unwrap();
+
+        // Login the new client using its email
+        let tx_dummy = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let resp = tx_dummy.execute().unwrap();
+
+        // Introspect the cookie on behalf of the app
+        let dust = b"introspect dust";
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(label.as_bytes()).unwrap();
+        signer.update(resp.cookie.as_bytes()).unwrap();
+        signer.update(dust).unwrap();
+        let firm = signer.sign_to_vec().unwrap();
+
+        let tx_dummy = super::introspect::TxIntrospect::new(&*sessions, &*namespaces, &label, &resp.cookie, dust, &firm);
+        let info = tx_dummy.execute().unwrap();
+        assert_eq!(info.user_id, user.get_id());
+        assert_eq!(info.name, user_name);
+        assert_eq!(info.email, email);
+        assert!(info.deadline > 0);
+
+        // the session is no longer new once the app has used it
+        use crate::proto::user_proto::Status;
+        assert_eq!(resp.status, Status::New as i32);
+        assert_eq!(info.status, Status::Alive as i32);
+
+        // A wrong signature must not disclose anything
+        let tx_dummy = super::introspect::TxIntrospect::new(&*sessions, &*namespaces, &label, &resp.cookie, b"another dust", &firm);
+        assert!(tx_dummy.execute().is_err());
+
+        // Any other instance tells the cookie as valid, even if its namespace knows nothing about the directory
+        let replica = namesp::Provider::new();
+        let tx_dummy = super::introspect::TxIntrospect::new(&*sessions, &replica, &label, &resp.cookie, dust, &firm);
+        assert_eq!(tx_dummy.execute().unwrap().user_id, user.get_id());
+
+        // A closed session is told as dead
+        let cookie = cookie::split_cookie(&resp.cookie).unwrap().0;
+        let sess = sessions.get_by_cookie(&cookie.digest()).unwrap().unwrap();
+        super::logout::close_session(&*sessions, &*namespaces, &mut sess.lock()).unwrap();
+        let tx_dummy = super::introspect::TxIntrospect::new(&*sessions, &*namespaces, &label, &resp.cookie, dust, &firm);
+        let info = tx_dummy.execute().unwrap();
+        assert_eq!(info.status, Status::Dead as i32);
+        assert_eq!(info.user_id, 0);
 
         // Checking there is a default secret for the app
         let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
@@ -382,6 +521,7 @@ use crate::transactions::{signup, delete_user, register};
     #[test]
     fn ticket_restore() {
         use crate::models::Gateway;
+        use crate::models::ticket::Ctrl as TicketCtrl;
 
         crate::initialize();
         const PREFIX: &str = "ticket_restore";
@@ -396,7 +536,1455 @@ use crate::transactions::{signup, delete_user, register};
         let tx_ticket = super::ticket::TxTicket::new(0, &email);
         let resp = tx_ticket.execute().unwrap();
 
+        // Checking the ticket data
+        let ticket = ticket::find_by_id(&resp.id).unwrap();
+        assert!(ticket.is_alive().is_ok());
+        assert!(resp.deadline > 0);
+
+        // Checking the ticket has been delivered to the user, once the outbox gets flushed
+        crate::mailer::outbox::flush().unwrap();
+        let maildir = std::path::Path::new(default::MAILER_DIR).join("new");
+        let delivered = std::fs::read_dir(maildir).unwrap()
+            .filter_map(|entry| std::fs::read_to_string(entry.unwrap().path()).ok())
+            .any(|content| content.contains(&email) && content.contains(&resp.id));
+        assert!(delivered);
+
+        // Deleting the user and client
+        user.delete().unwrap();
+    }
+
+    #[test]
+    fn resolve_restore() {
+        use std::time::Duration;
+        use crate::models::Gateway;
+        use crate::models::user::Ctrl as UserCtrl;
+        use crate::models::ticket::Ctrl as TicketCtrl;
+        use crate::proto::TicketKind;
+
+        crate::initialize();
+        const PREFIX: &str = "resolve_restore";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
+        const NEW_PWD: &str = "1A2b3C4d5E6f7A8b";
+        
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        
+        // Signing up the user
+        let tx_signup = signup::TxSignup::new(&name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+        
+        let user = user::find_by_name(&name).unwrap();
+        let timeout = Duration::from_secs(default::TICKET_TIMEOUT);
+        let (mut ticket, secret) = ticket::Ticket::new(user.get_client_id(), TicketKind::RestoreCredentials, timeout).unwrap();
+        ticket.insert().unwrap();
+
+        // A wrong secret must not resolve the ticket
+        let tx_resolve = super::resolve::TxResolve::new(&*sessions, &*namespaces, ticket.get_id(), NEW_PWD, "wrong secret");
+        assert!(tx_resolve.execute().is_err());
+
+        // Neither does a malformed password, which must not burn the ticket either
+        let tx_resolve = super::resolve::TxResolve::new(&*sessions, &*namespaces, ticket.get_id(), "weak", secret.as_str());
+        assert!(tx_resolve.execute().is_err());
+
+        // Any key registered meanwhile may belong to whoever holds the old credentials
+        let key_public = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap().public_key_to_pem().unwrap();
+        let key_name = format!("{}{}", default::KEY_PREFIX, PREFIX);
+        secret::Secret::new(user.get_client_id(), &key_name, &key_public).unwrap().insert().unwrap();
+
+        let tx_resolve = super::resolve::TxResolve::new(&*sessions, &*namespaces, ticket.get_id(), NEW_PWD, secret.as_str());
+        tx_resolve.execute().unwrap();
+
+        let user = user::find_by_name(&name).unwrap();
+        assert!(user.match_pwd(NEW_PWD));
+        assert!(!user.match_pwd(DUMMY_PWD));
+        assert!(secret::find_all_by_client_and_prefix(user.get_client_id(), default::KEY_PREFIX).unwrap().is_empty());
+
+        // Tickets are single-use
+        assert!(tx_resolve.execute().is_err());
+
         // Deleting the user and client
         user.delete().unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn resolve_verify() {
+        use std::time::Duration;
+        use crate::models::{Gateway, enums};
+        use crate::models::user::Ctrl as UserCtrl;
+        use app::Ctrl as AppCtrl;
+        use crate::models::ticket::Ctrl as TicketCtrl;
+        use crate::proto::TicketKind;
+
+        crate::initialize();
+        const PREFIX: &str = "resolve_verify";
+        let sessions = session::get_instance();
+        let namespaces = namesp::get_instance();
+        
+        let (name, email) = get_prefixed_data(PREFIX, false);
+        
+        // Signing up the user
+        let tx_signup = signup::TxSignup::new(&name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+        
+        let user = user::find_by_name(&name).unwrap();
+        assert_eq!(user.get_status(), enums::Status::PENDING);
+
+        let timeout = Duration::from_secs(default::TICKET_TIMEOUT);
+        let (mut ticket, secret) = ticket::Ticket::new(user.get_client_id(), TicketKind::VerifyEmail, timeout).unwrap();
+        ticket.insert().unwrap();
+
+        let tx_resolve = super::resolve::TxResolve::new(&*sessions, &*namespaces, ticket.get_id(), "", secret.as_str());
+        tx_resolve.execute().unwrap();
+
+        let mut user = user::find_by_name(&name).unwrap();
+        assert_eq!(user.get_status(), enums::Status::ACTIVATED);
+
+        // An already verified email cannot be verified again
+        let tx_ticket = super::ticket::TxTicket::new(TicketKind::VerifyEmail as i32, &email);
+        assert!(tx_ticket.execute().is_err());
+
+        // Registering an app to log in
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        let firm = signer.sign_to_vec().unwrap();
+
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // The user gets a session while it is allowed to
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+
+        // Hidden users are not allowed to log in, not even with the session they had already
+        user.set_status(enums::Status::HIDDEN);
+        user.update().unwrap();
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{token: &login.cookie, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+
+        // Deleting the app, the user and its session
+        let sess = super::logout::authenticate(&*sessions, &login.cookie).unwrap();
+        super::logout::close_session(&*sessions, &*namespaces, &mut sess.lock()).unwrap();
+
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+        user.delete().unwrap();
+    }
+
+    #[test]
+    fn concurrent_sessions() {
+        use std::thread;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        crate::initialize();
+        const PREFIX: &str = "concurrent_sessions";
+        const WORKERS: usize = 8;
+        const ROUNDS: usize = 4;
+
+        // the registries are not shared with any other test
+        let timeout = Duration::from_secs(default::SESSION_LIFETIME);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions: Arc<dyn session::Factory> = Arc::new(session::Provider::new(timeout, timeout, store));
+        let namespaces: Arc<dyn namesp::Factory> = Arc::new(namesp::Provider::new());
+
+        // Setting up the required clients
+        let (shared_name, shared_email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&shared_name, &shared_email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // Generate a keypair
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+    
+        // Register app
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        // Decrypt the label
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // each worker logs in and out its own user and the shared one, all of them in the same app
+        let workers: Vec<_> = (0..WORKERS).map(|index| {
+            let sessions = sessions.clone();
+            let namespaces = namespaces.clone();
+            let label = label.clone();
+            let shared_email = shared_email.clone();
+
+            thread::spawn(move || {
+                let (name, email) = get_prefixed_data(&format!("{}_{}", PREFIX, index), false);
+                let tx_signup = signup::TxSignup::new(&name, &email, DUMMY_PWD);
+                tx_signup.execute().unwrap();
+
+                for _ in 0..ROUNDS {
+                    for ident in &[&name, &shared_email] {
+                        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+                        let resp = tx_login.execute().unwrap();
+                        let tx_logout = super::logout::TxLogout::new(&*sessions, &*namespaces, &resp.cookie);
+                        tx_logout.execute().unwrap();
+                    }
+                }
+
+                let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+                tx_login.execute().unwrap();
+                let tx_delete = delete_user::TxDelete::new(&*sessions, &*namespaces, &name, DUMMY_PWD, &DUMMY_META);
+                tx_delete.execute().unwrap();
+                assert!(sessions.get_by_email(&email).unwrap().is_empty());
+            })
+        }).collect();
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let tx_delete = delete_user::TxDelete::new(&*sessions, &*namespaces, &shared_email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+        assert!(sessions.get_by_email(&shared_email).unwrap().is_empty());
+
+        // no directory may remain open once all the users have gone
+        let np = namespaces.get_by_label(&label).unwrap();
+        assert_eq!(np.lock().get_dirs_iter().count(), 0);
+
+        // Deleting the app and its secret
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn multiple_devices() {
+        use std::thread;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        use crate::proto::Status;
+        crate::initialize();
+        const PREFIX: &str = "multiple_devices";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions: Arc<dyn session::Factory> = Arc::new(session::Provider::new(lifetime, idle, store));
+        let namespaces: Arc<dyn namesp::Factory> = Arc::new(namesp::Provider::new());
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // Generate a keypair
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+    
+        // Register app
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        // Decrypt the label
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // each device gets its own session, up to the per-user cap
+        let devices: Vec<String> = (0..=default::SESSION_MAX_PER_USER)
+            .map(|index| format!("{}_{}", DUMMY_DEVICE, index))
+            .collect();
+
+        let mut cookies = Vec::new();
+        for device in &devices {
+            let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device, ..Default::default()}, &DUMMY_META);
+            let resp = tx_login.execute().unwrap();
+            assert_eq!(resp.status, Status::New as i32);
+            cookies.push(cookie::split_cookie(&resp.cookie).unwrap().0);
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // the oldest session has been evicted in favour of the newest one
+        assert_eq!(sessions.get_by_email(&email).unwrap().len(), default::SESSION_MAX_PER_USER);
+        let oldest = sessions.get_by_cookie(&cookies[0].digest()).unwrap().unwrap();
+        assert_eq!(oldest.lock().get_status(), Status::Dead);
+
+        // logging in again from a known device reuses its session
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &user_name, pwd: DUMMY_PWD, app: &label, device: &devices[1], ..Default::default()}, &DUMMY_META);
+        let resp = tx_login.execute().unwrap();
+        assert_eq!(resp.status, Status::Alive as i32);
+        assert_eq!(cookie::split_cookie(&resp.cookie).unwrap().0.as_str(), cookies[1].as_str());
+
+        // deleting the user closes all of its sessions
+        let tx_delete = delete_user::TxDelete::new(&*sessions, &*namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+        assert!(sessions.get_by_email(&email).unwrap().is_empty());
+        for cookie in &cookies {
+            let sess = sessions.get_by_cookie(&cookie.digest()).unwrap().unwrap();
+            assert_eq!(sess.lock().get_status(), Status::Dead);
+        }
+
+        let np = namespaces.get_by_label(&label).unwrap();
+        assert_eq!(np.lock().get_dirs_iter().count(), 0);
+
+        // Deleting the app and its secret
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn manage_sessions() {
+        use std::sync::Arc;
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        use crate::proto::Status;
+        crate::initialize();
+        const PREFIX: &str = "manage_sessions";
+        const DEVICES: usize = 3;
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions: Arc<dyn session::Factory> = Arc::new(session::Provider::new(lifetime, idle, store));
+        let namespaces: Arc<dyn namesp::Factory> = Arc::new(namesp::Provider::new());
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // Generate a keypair
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+    
+        // Register app
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        // Decrypt the label
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        let cookies: Vec<String> = (0..DEVICES).map(|index| {
+            let device = format!("{}_{}", DUMMY_DEVICE, index);
+            let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: &device, ..Default::default()}, &DUMMY_META);
+            tx_login.execute().unwrap().cookie
+        }).collect();
+
+        // all the user's sessions are listed, with no cookie in them
+        let tx_list = super::list_sessions::TxListSessions::new(&*sessions, &*namespaces, &cookies[0]);
+        let resp = tx_list.execute().unwrap();
+        assert_eq!(resp.sessions.len(), DEVICES);
+        assert_eq!(resp.sessions.iter().filter(|info| info.current).count(), 1);
+        for info in &resp.sessions {
+            assert_eq!(info.apps, vec![label.clone()]);
+            assert!(info.device.starts_with(DUMMY_DEVICE));
+            assert!(info.created_at > 0 && info.touch_at >= info.created_at);
+            assert!(cookies.iter().all(|cookie| !cookie.contains(&info.id)));
+        }
+
+        // a session can be revoked from any other one of the same user
+        let target = resp.sessions.iter().find(|info| !info.current).unwrap();
+        let tx_revoke = super::revoke_session::TxRevokeSession::new(&*sessions, &*namespaces, &cookies[0], &target.id);
+        tx_revoke.execute().unwrap();
+        assert!(tx_revoke.execute().is_err());
+
+        let tx_list = super::list_sessions::TxListSessions::new(&*sessions, &*namespaces, &cookies[0]);
+        assert_eq!(tx_list.execute().unwrap().sessions.len(), DEVICES - 1);
+
+        // revoking all the others leaves the current session alone
+        let tx_revoke = super::revoke_others::TxRevokeOthers::new(&*sessions, &*namespaces, &cookies[0]);
+        tx_revoke.execute().unwrap();
+        let resp = tx_list.execute().unwrap();
+        assert_eq!(resp.sessions.len(), 1);
+        assert!(resp.sessions[0].current);
+
+        // revoked cookies are no longer valid
+        for cookie in &cookies[1..] {
+            let tx_list = super::list_sessions::TxListSessions::new(&*sessions, &*namespaces, cookie);
+            assert!(tx_list.execute().is_err());
+            let token = cookie::split_cookie(cookie).unwrap().0;
+            let sess = sessions.get_by_cookie(&token.digest()).unwrap().unwrap();
+            assert_eq!(sess.lock().get_status(), Status::Dead);
+        }
+
+        let tx_delete = delete_user::TxDelete::new(&*sessions, &*namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+        let np = namespaces.get_by_label(&label).unwrap();
+        assert_eq!(np.lock().get_dirs_iter().count(), 0);
+
+        // Deleting the app and its secret
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn single_sign_on() {
+        use std::sync::Arc;
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        crate::initialize();
+        const PREFIX: &str = "single_sign_on";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions: Arc<dyn session::Factory> = Arc::new(session::Provider::new(lifetime, idle, store));
+        let namespaces: Arc<dyn namesp::Factory> = Arc::new(namesp::Provider::new());
+
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // registers an app, returning its label
+        let register = |subject: &str, reauth: bool| {
+            let (app_name, url) = get_prefixed_data(subject, true);
+            let rsa = Rsa::generate(2048).unwrap();
+            let rsa = PKey::from_rsa(rsa).unwrap();
+            let public = rsa.public_key_to_pem().unwrap();
+            
+            let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+            signer.update(app_name.as_bytes()).unwrap();
+            signer.update(url.as_bytes()).unwrap();
+            signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+            signer.update(&public).unwrap();
+            if reauth {
+                signer.update(&[1]).unwrap();
+            }
+            
+            let firm = signer.sign_to_vec().unwrap();
+            let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, reauth, &[], &firm);
+            let resp = tx_register.execute().unwrap();
+
+            let mut decrypter = Decrypter::new(&rsa).unwrap();
+            decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+            let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+            let mut decrypted = vec![0; buffer_len];
+            let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+            decrypted.truncate(decrypted_len);
+            String::from_utf8(decrypted).unwrap()
+        };
+
+        let first = register(&format!("{}_first", PREFIX), false);
+        let second = register(&format!("{}_second", PREFIX), false);
+        let strict = register(&format!("{}_strict", PREFIX), true);
+
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &first, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let resp = tx_login.execute().unwrap();
+
+        // the very same session gets logged in another app with no credentials
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{token: &resp.cookie, app: &second, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let sso = tx_login.execute().unwrap();
+        assert_eq!(cookie::split_cookie(&sso.cookie).unwrap().0.as_str(), cookie::split_cookie(&resp.cookie).unwrap().0.as_str());
+        assert_ne!(sso.cookie, resp.cookie);
+
+        // each cookie authenticates the session in its own app only
+        let first_id = app::find_by_label(&first).unwrap().get_id();
+        let second_id = app::find_by_label(&second).unwrap().get_id();
+        assert!(super::logout::authenticate_in(&*sessions, &resp.cookie, first_id).is_ok());
+        assert!(super::logout::authenticate_in(&*sessions, &resp.cookie, second_id).is_err());
+        assert!(super::logout::authenticate_in(&*sessions, &sso.cookie, second_id).is_ok());
+
+        // an app may require its users to always provide their credentials
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{token: &resp.cookie, app: &strict, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{token: &resp.cookie, ident: &user_name, pwd: DUMMY_PWD, app: &strict, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let strict_resp = tx_login.execute().unwrap();
+        assert_eq!(cookie::split_cookie(&strict_resp.cookie).unwrap().0.as_str(), cookie::split_cookie(&resp.cookie).unwrap().0.as_str());
+
+        // a cookie is no longer valid once its session has been closed
+        let cookie = cookie::split_cookie(&resp.cookie).unwrap().0;
+        let sess = sessions.get_by_cookie(&cookie.digest()).unwrap().unwrap();
+        super::logout::close_session(&*sessions, &*namespaces, &mut sess.lock()).unwrap();
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{token: &resp.cookie, app: &first, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+
+        let tx_delete = delete_user::TxDelete::new(&*sessions, &*namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        // Deleting the apps and their secrets
+        for (label, reauth) in &[(first, false), (second, false), (strict, true)] {
+            let app = app::find_by_label(label).unwrap();
+            assert_eq!(app.get_force_reauth(), *reauth);
+            let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+            secret.delete().unwrap();
+            app.delete().unwrap();
+        }
+    }
+
+    #[test]
+    fn login_lockout() {
+        use std::time::Duration;
+        use tonic::Code;
+        use crate::error;
+        use crate::throttle;
+        crate::initialize();
+        const PREFIX: &str = "login_lockout";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // a wrong password makes the identity wait before trying again, even with the right one
+        let login = |pwd: &str| {
+            let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+            error::Error::classify(tx_login.execute().unwrap_err()).code()
+        };
+
+        assert_eq!(login("wrong_password"), Code::Unauthenticated);
+        assert_eq!(login(DUMMY_PWD), Code::ResourceExhausted);
+
+        // once unlocked the credentials are accepted, so only the missing app makes the login fail
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+        assert_ne!(login(DUMMY_PWD), Code::ResourceExhausted);
+        assert_ne!(login(DUMMY_PWD), Code::Unauthenticated);
+
+        // guessing the password by deleting the account gets the identity locked out as well
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, "wrong_password", &DUMMY_META);
+        assert_eq!(error::Error::classify(tx_delete.execute().unwrap_err()).code(), Code::Unauthenticated);
+        assert_eq!(login(DUMMY_PWD), Code::ResourceExhausted);
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let user = user::find_by_email(&email).unwrap();
+        user.delete().unwrap();
+    }
+
+    #[test]
+    fn second_factor() {
+        use std::time::{Duration, SystemTime};
+        use app::Ctrl as AppCtrl;
+        use user::Ctrl as UserCtrl;
+        use secret::SealedCtrl;
+        use crate::totp;
+        use crate::throttle;
+        crate::initialize();
+        const PREFIX: &str = "second_factor";
+        const OTHER_DEVICE: &str = "second_factor_device";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let resp = tx_login.execute().unwrap();
+        assert!(resp.challenge.is_empty());
+
+        // Enrolling the second factor, which no cookie is enough for
+        let tx_enroll = super::enroll_totp::TxEnrollTotp::new(&sessions, &resp.cookie, "wrong_password", &DUMMY_META);
+        assert!(tx_enroll.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let tx_enroll = super::enroll_totp::TxEnrollTotp::new(&sessions, &resp.cookie, DUMMY_PWD, &DUMMY_META);
+        let enrollment = tx_enroll.execute().unwrap();
+        assert!(enrollment.uri.starts_with("otpauth://totp/"));
+        assert!(enrollment.uri.contains(&enrollment.secret));
+
+        let client_id = user::find_by_email(&email).unwrap().get_client_id();
+        let pending = secret::find_sealed_by_client_and_name(client_id, default::TOTP_PENDING_NAME).unwrap();
+        let (shared, _) = totp::unseal(client_id, pending.get_document()).unwrap();
+        let step = totp::current_step(SystemTime::now());
+        let code = totp::code_at(&shared, step).unwrap();
+        let next_code = totp::code_at(&shared, step + 1).unwrap();
+
+        let tx_confirm = super::confirm_totp::TxConfirmTotp::new(&sessions, &resp.cookie, &code, "wrong_password", &DUMMY_META);
+        assert!(tx_confirm.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let tx_confirm = super::confirm_totp::TxConfirmTotp::new(&sessions, &resp.cookie, &code, DUMMY_PWD, &DUMMY_META);
+        let recovery = tx_confirm.execute().unwrap();
+        assert_eq!(recovery.codes.len(), default::RECOVERY_CODES);
+        assert!(tx_enroll.execute().is_err());
+
+        // the password is no longer enough to log in
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        assert!(challenged.cookie.is_empty());
+        assert!(!challenged.challenge.is_empty());
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &challenged.challenge, code: "invalid", app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        // the code the enrollment was confirmed with cannot be used again, while the next one can
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &challenged.challenge, code: &code, app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &challenged.challenge, code: &next_code, app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        let granted = tx_login.execute().unwrap();
+        assert!(!granted.cookie.is_empty());
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        // recovery codes complete a challenge just once
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, pwd: DUMMY_PWD, app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &challenged.challenge, code: &recovery.codes[0], app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(!tx_login.execute().unwrap().cookie.is_empty());
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, pwd: DUMMY_PWD, app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &challenged.challenge, code: &recovery.codes[0], app: &label, device: OTHER_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&user_name));
+
+        // Deleting the user, along with its secrets, and the app
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn login_by_signature() {
+        use std::time::{Duration, SystemTime};
+        use app::Ctrl as AppCtrl;
+        use user::Ctrl as UserCtrl;
+        use secret::SealedCtrl;
+        use crate::totp;
+        use crate::throttle;
+        crate::initialize();
+        const PREFIX: &str = "login_by_signature";
+        const KEY_NAME: &str = "laptop";
+        const KEY_DEVICE: &str = "login_by_signature_device";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let resp = tx_login.execute().unwrap();
+
+        // Registering the user's key, which requires proving to hold it
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let key_public = key.public_key_to_pem().unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(KEY_NAME.as_bytes()).unwrap();
+        signer.update(&key_public).unwrap();
+        let key_firm = signer.sign_to_vec().unwrap();
+
+        let tx_register_key = super::register_key::TxRegisterKey::new(&sessions, &resp.cookie, KEY_NAME, &key_public, &firm, DUMMY_PWD, &DUMMY_META);
+        assert!(tx_register_key.execute().is_err());
+        // no cookie is enough to register a key, but the user's password is required as well
+        let tx_register_key = super::register_key::TxRegisterKey::new(&sessions, &resp.cookie, KEY_NAME, &key_public, &key_firm, "wrong_password", &DUMMY_META);
+        assert!(tx_register_key.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let tx_register_key = super::register_key::TxRegisterKey::new(&sessions, &resp.cookie, KEY_NAME, &key_public, &key_firm, DUMMY_PWD, &DUMMY_META);
+        tx_register_key.execute().unwrap();
+
+        // signs the identity and a brand new nonce
+        let sign_nonce = |ident: &str| {
+            let nonce = super::nonce::TxNonce::new(ident, &DUMMY_META).execute().unwrap().nonce;
+            let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+            signer.update(ident.as_bytes()).unwrap();
+            signer.update(nonce.as_bytes()).unwrap();
+            (nonce, signer.sign_to_vec().unwrap())
+        };
+
+        // Login by signature, with no password
+        let (nonce, nonce_firm) = sign_nonce(&user_name);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &nonce, firm: &nonce_firm, app: &label, device: KEY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let signed = tx_login.execute().unwrap();
+        assert!(!signed.cookie.is_empty());
+
+        // a nonce is consumed once used, and signatures are bound to the identity
+        assert!(tx_login.execute().is_err());
+        let (nonce, nonce_firm) = sign_nonce(&user_name);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &nonce, firm: &nonce_firm, app: &label, device: KEY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&user_name));
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        // a brand new nonce replaces the former one
+        let (stale, stale_firm) = sign_nonce(&user_name);
+        sign_nonce(&user_name);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &stale, firm: &stale_firm, app: &label, device: KEY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&user_name));
+
+        // unknown identities get a nonce as well, so they cannot be told apart from the known ones
+        let (unknown, _) = get_prefixed_data("unknown_nonce", false);
+        let decoy = super::nonce::TxNonce::new(&unknown, &DUMMY_META).execute().unwrap();
+        assert_eq!(decoy.nonce.len(), nonce.len());
+
+        // a key stands for the password only, so once a second factor is enrolled it has to be completed as well
+        let tx_enroll = super::enroll_totp::TxEnrollTotp::new(&sessions, &resp.cookie, DUMMY_PWD, &DUMMY_META);
+        tx_enroll.execute().unwrap();
+
+        let client_id = user::find_by_email(&email).unwrap().get_client_id();
+        let pending = secret::find_sealed_by_client_and_name(client_id, default::TOTP_PENDING_NAME).unwrap();
+        let (shared, _) = totp::unseal(client_id, pending.get_document()).unwrap();
+        let code = totp::code_at(&shared, totp::current_step(SystemTime::now())).unwrap();
+        let tx_confirm = super::confirm_totp::TxConfirmTotp::new(&sessions, &resp.cookie, &code, DUMMY_PWD, &DUMMY_META);
+        tx_confirm.execute().unwrap();
+
+        let (nonce, nonce_firm) = sign_nonce(&user_name);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &nonce, firm: &nonce_firm, app: &label, device: KEY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        assert!(challenged.cookie.is_empty());
+        assert!(!challenged.challenge.is_empty());
+
+        // a deleted key is no longer accepted
+        let tx_delete_key = super::delete_key::TxDeleteKey::new(&sessions, &resp.cookie, KEY_NAME, "wrong_password", &DUMMY_META);
+        assert!(tx_delete_key.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&email));
+
+        let tx_delete_key = super::delete_key::TxDeleteKey::new(&sessions, &resp.cookie, KEY_NAME, DUMMY_PWD, &DUMMY_META);
+        tx_delete_key.execute().unwrap();
+        let (nonce, nonce_firm) = sign_nonce(&user_name);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &user_name, challenge: &nonce, firm: &nonce_firm, app: &label, device: KEY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+        throttle::get_instance().reset(&throttle::ident_key(&user_name));
+
+        // Deleting the user and the app
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn oauth_code_grant() {
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        use user::Ctrl as UserCtrl;
+        use crate::oauth;
+        crate::initialize();
+        const PREFIX: &str = "oauth_code_grant";
+        const REDIRECT_URI: &str = "https://oauth_code_grant.dummy.com/callback";
+        const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        const STATE: &str = "af0ifjsldkj";
+        const NONCE: &str = "n-0S6_WzA2Mj";
+        const SCOPE: &[&str] = &["openid", "profile", "email"];
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        for scope in SCOPE.iter() {
+            signer.update(scope.as_bytes()).unwrap();
+        }
+        
+        let firm = signer.sign_to_vec().unwrap();
+        let scope: Vec<String> = SCOPE.iter().map(|scope| scope.to_string()).collect();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &scope, &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // Registering the redirect uri on behalf of the app
+        let uris = vec![REDIRECT_URI.to_string()];
+        let dust = b"oauth_code_grant dust";
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(label.as_bytes()).unwrap();
+        signer.update(&(REDIRECT_URI.len() as u32).to_be_bytes()).unwrap();
+        signer.update(REDIRECT_URI.as_bytes()).unwrap();
+        signer.update(dust).unwrap();
+        let firm = signer.sign_to_vec().unwrap();
+
+        let tx_set_redirects = super::set_redirects::TxSetRedirects::new(&label, &uris, b"another dust", &firm);
+        assert!(tx_set_redirects.execute().is_err());
+        let tx_set_redirects = super::set_redirects::TxSetRedirects::new(&label, &uris, dust, &firm);
+        tx_set_redirects.execute().unwrap();
+
+        // the user logs in the authorization server itself, and in the app the authorization is asked from
+        let portal = register_portal(PREFIX);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &portal, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let cookie = tx_login.execute().unwrap().cookie;
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+
+        // an unregistered redirect uri must not be redirected to
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: "https://evil.com/callback", response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, ..Default::default()});
+        assert!(tx_authorize.execute().is_err());
+
+        // nor may anyone with no session authorize the app
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, "", AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, ..Default::default()});
+        assert!(tx_authorize.execute().is_err());
+
+        // neither any app holding a cookie of its own, since it could authorize any other app otherwise
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &login.cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, ..Default::default()});
+        assert!(tx_authorize.execute().is_err());
+
+        // the plain method is told to the redirect uri
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: VERIFIER, code_challenge_method: "plain", state: STATE, ..Default::default()});
+        let redirect = tx_authorize.execute().unwrap().redirect_uri;
+        assert!(redirect.contains("error=invalid_request"));
+        assert!(redirect.contains(&format!("state={}", STATE)));
+
+        // neither may any unsupported scope be requested
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, scope: "openid admin", nonce: NONCE});
+        let redirect = tx_authorize.execute().unwrap().redirect_uri;
+        assert!(redirect.contains("error=invalid_scope"));
+
+        // the user has to consent before any code is issued
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, scope: "openid profile email", nonce: NONCE});
+        let resp = tx_authorize.execute().unwrap();
+        assert!(resp.redirect_uri.is_empty());
+        assert_eq!(resp.consent, SCOPE);
+
+        // asking again does not make the user consent
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, scope: "openid profile email", nonce: NONCE});
+        assert!(tx_authorize.execute().unwrap().redirect_uri.is_empty());
+
+        let client_id = user::find_by_email(&email).unwrap().get_client_id();
+        let app_id = app::find_by_label(&label).unwrap().get_id();
+        resolve_consent(&sessions, &namespaces, client_id, app_id, &scope);
+
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, scope: "openid profile email", nonce: NONCE});
+        let redirect = tx_authorize.execute().unwrap().redirect_uri;
+        let redirect = url::Url::parse(&redirect).unwrap();
+        let code = redirect.query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, value)| value.to_string())
+            .unwrap();
+
+        // only the app itself may exchange the code, which is not consumed otherwise
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_CLIENT);
+
+        let forged = client_assertion(&PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(), &label);
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &forged, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_CLIENT);
+
+        // a wrong verifier does not consume the code
+        let assertion = client_assertion(&rsa, &label);
+        let wrong_verifier = VERIFIER.replace('d', "e");
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: &wrong_verifier, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        assert!(tx_grant.execute().is_err());
+
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let token = tx_grant.execute().unwrap();
+        assert_eq!(token.token_type, oauth::TOKEN_TYPE_BEARER);
+        assert!(token.expires_in > 0);
+
+        // the access token tells whom it has been issued for, and can be verified offline
+        let user = user::find_by_email(&email).unwrap();
+        let claims = crate::jwt::get_instance().decode(&token.access_token).unwrap();
+        assert_eq!(claims.sub, user.get_id().to_string());
+        assert_eq!(claims.aud, label);
+        assert!(token.expires_in > 0 && token.expires_in <= default::ACCESS_TOKEN_LIFETIME as i64);
+        assert_eq!(claims.scope, vec!["openid", "profile", "email"]);
+
+        // the id token tells the nonce back, while the user info tells whatever the scope grants access to
+        let id_token = crate::jwt::get_instance().decode_id_token(&token.id_token).unwrap();
+        assert_eq!(id_token["sub"], user.get_id().to_string());
+        assert_eq!(id_token["nonce"], NONCE);
+        assert!(crate::jwt::get_instance().decode(&token.id_token).is_err());
+
+        let tx_userinfo = super::userinfo::TxUserInfo::new(&token.access_token);
+        let userinfo = tx_userinfo.execute().unwrap();
+        assert_eq!(userinfo.sub, user.get_id().to_string());
+        assert_eq!(userinfo.email, email);
+        assert_eq!(userinfo.preferred_username, user_name);
+
+        let tx_userinfo = super::userinfo::TxUserInfo::new(&token.id_token);
+        let err = crate::error::Error::classify(tx_userinfo.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_TOKEN);
+
+        // the user remains logged in the app with the very same cookie
+        assert!(super::logout::authenticate(&sessions, &login.cookie).is_ok());
+
+        // the code cannot be exchanged twice
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_GRANT);
+
+        // Deleting the user and the app
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        for label in [&label, &portal] {
+            let app = app::find_by_label(label).unwrap();
+            let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+            secret.delete().unwrap();
+            app.delete().unwrap();
+        }
+    }
+
+    #[test]
+    fn oauth_refresh_rotation() {
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        use crate::oauth;
+        crate::initialize();
+        const PREFIX: &str = "oauth_refresh_rotation";
+        const REDIRECT_URI: &str = "https://oauth_refresh_rotation.dummy.com/callback";
+        const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+        const STATE: &str = "af0ifjsldkj";
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // Registering the redirect uri on behalf of the app
+        let uris = vec![REDIRECT_URI.to_string()];
+        let dust = b"oauth_refresh_rotation dust";
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(label.as_bytes()).unwrap();
+        signer.update(&(REDIRECT_URI.len() as u32).to_be_bytes()).unwrap();
+        signer.update(REDIRECT_URI.as_bytes()).unwrap();
+        signer.update(dust).unwrap();
+        let firm = signer.sign_to_vec().unwrap();
+
+        let tx_set_redirects = super::set_redirects::TxSetRedirects::new(&label, &uris, dust, &firm);
+        tx_set_redirects.execute().unwrap();
+
+        // the user logs in the authorization server itself, and in the app the authorization is asked from
+        let portal = register_portal(PREFIX);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &portal, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let cookie = tx_login.execute().unwrap().cookie;
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, ..Default::default()});
+        let redirect = tx_authorize.execute().unwrap().redirect_uri;
+        let redirect = url::Url::parse(&redirect).unwrap();
+        let code = redirect.query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, value)| value.to_string())
+            .unwrap();
+
+        let assertion = client_assertion(&rsa, &label);
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let first = tx_grant.execute().unwrap();
+        assert!(!first.refresh_token.is_empty());
+
+        // each refresh token can be used just once, and gets rotated by another of the same family
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: &label, refresh_token: &first.refresh_token, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let second = tx_grant.execute().unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert!(super::logout::authenticate(&sessions, &login.cookie).is_ok());
+
+        // a refresh token of another app is not valid
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: "another_label", refresh_token: &second.refresh_token, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        assert!(tx_grant.execute().is_err());
+
+        // replaying a rotated token revokes the whole family, and logs the session out of the app
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: &label, refresh_token: &first.refresh_token, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_GRANT);
+        assert!(super::logout::authenticate(&sessions, &login.cookie).is_err());
+        assert!(super::logout::authenticate(&sessions, &cookie).is_ok());
+
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: &label, refresh_token: &second.refresh_token, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        assert!(tx_grant.execute().is_err());
+
+        // a refresh token cannot outlive the session it was issued for
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+
+        let tx_authorize = super::authorize::TxAuthorize::new(&sessions, &portal, &cookie, AuthorizeParams{client_id: &label, redirect_uri: REDIRECT_URI, response_type: "code", code_challenge: CHALLENGE, code_challenge_method: "S256", state: STATE, ..Default::default()});
+        let redirect = tx_authorize.execute().unwrap().redirect_uri;
+        let redirect = url::Url::parse(&redirect).unwrap();
+        let code = redirect.query_pairs()
+            .find(|(key, _)| key == "code")
+            .map(|(_, value)| value.to_string())
+            .unwrap();
+
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_CODE, code: &code, redirect_uri: REDIRECT_URI, client_id: &label, code_verifier: VERIFIER, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let third = tx_grant.execute().unwrap();
+
+        let sess = super::logout::authenticate(&sessions, &login.cookie).unwrap();
+        super::logout::close_session(&sessions, &namespaces, &mut sess.lock()).unwrap();
+
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: &label, refresh_token: &third.refresh_token, client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_GRANT);
+
+        // Deleting the user and the app
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        for label in [&label, &portal] {
+            let app = app::find_by_label(label).unwrap();
+            let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+            secret.delete().unwrap();
+            app.delete().unwrap();
+        }
+    }
+
+    // register_portal registers the app the authorization server logs its users in, returning its label
+    fn register_portal(prefix: &str) -> String {
+        let (app_name, url) = get_prefixed_data(&format!("{}_portal", prefix), true);
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+
+        let firm = signer.sign_to_vec().unwrap();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        String::from_utf8(decrypted).unwrap()
+    }
+
+    // client_assertion returns the jwt the app authenticates itself with at the token endpoint
+    fn client_assertion(rsa: &PKey<openssl::pkey::Private>, label: &str) -> String {
+        use std::time::Duration;
+        let endpoint = format!("{}{}", crate::jwt::get_instance().get_issuer(), crate::oauth::PATH_TOKEN);
+        crate::jwt::encode_assertion(rsa, label, &endpoint, Duration::from_secs(60))
+    }
+
+    // resolve_consent grants the scopes to the app just like the user does by resolving the ticket it gets mailed
+    fn resolve_consent(sessions: &dyn session::Factory, namespaces: &dyn namesp::Factory, client_id: i32, app_id: i32, scope: &[String]) {
+        use std::time::Duration;
+        use crate::proto::TicketKind;
+        use crate::models::ticket::Ctrl as TicketCtrl;
+
+        let timeout = Duration::from_secs(default::TICKET_TIMEOUT);
+        let (mut ticket, secret) = ticket::Ticket::new(client_id, TicketKind::GrantConsent, timeout).unwrap();
+        ticket.bind(app_id, scope);
+        ticket.insert().unwrap();
+
+        let tx_resolve = super::resolve::TxResolve::new(sessions, namespaces, ticket.get_id(), "", secret.as_str());
+        tx_resolve.execute().unwrap();
+    }
+
+    #[test]
+    fn consent_lifecycle() {
+        use std::time::Duration;
+        use app::Ctrl as AppCtrl;
+        use user::Ctrl as UserCtrl;
+        use crate::models::ticket::Ctrl as TicketCtrl;
+        use session::Factory;
+        use crate::oauth;
+        crate::initialize();
+        const PREFIX: &str = "consent_lifecycle";
+        const SCOPE: &[&str] = &["openid", "email"];
+
+        // the registries are not shared with any other test
+        let lifetime = Duration::from_secs(default::SESSION_LIFETIME);
+        let idle = Duration::from_secs(default::SESSION_IDLE_TIMEOUT);
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions = session::Provider::new(lifetime, idle, store);
+        let namespaces = namesp::Provider::new();
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        let firm = signer.sign_to_vec().unwrap();
+
+        // the scopes are signed as well, so no one can make the app ask for more
+        let scope: Vec<String> = SCOPE.iter().map(|scope| scope.to_string()).collect();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &scope, &firm);
+        assert!(tx_register.execute().is_err());
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        for scope in SCOPE.iter() {
+            signer.update(scope.as_bytes()).unwrap();
+        }
+
+        let firm = signer.sign_to_vec().unwrap();
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &scope, &firm);
+        let resp = tx_register.execute().unwrap();
+
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+
+        // the user is asked for its consent before getting any cookie
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        assert!(challenged.cookie.is_empty());
+        assert_eq!(challenged.consent, SCOPE);
+
+        // the challenge is not completed until the user consents
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &challenged.challenge, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        assert!(tx_login.execute().is_err());
+
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+
+        // no one but whoever got the mailed secret may resolve the consent ticket
+        let user = user::find_by_email(&email).unwrap();
+        let app_id = app::find_by_label(&label).unwrap().get_id();
+        let timeout = Duration::from_secs(default::TICKET_TIMEOUT);
+        let (mut ticket, _) = ticket::Ticket::new(user.get_client_id(), crate::proto::TicketKind::GrantConsent, timeout).unwrap();
+        ticket.bind(app_id, &scope);
+        ticket.insert().unwrap();
+
+        let tx_resolve = super::resolve::TxResolve::new(&sessions, &namespaces, ticket.get_id(), "", "wrong secret");
+        assert!(tx_resolve.execute().is_err());
+
+        resolve_consent(&sessions, &namespaces, user.get_client_id(), app_id, &scope);
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, challenge: &challenged.challenge, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+        assert!(super::logout::authenticate(&sessions, &login.cookie).is_ok());
+
+        // once consented, the user is not asked again
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let login = tx_login.execute().unwrap();
+        assert!(!login.cookie.is_empty());
+
+        let tx_list = super::list_consents::TxListConsents::new(&sessions, &login.cookie);
+        let consents = tx_list.execute().unwrap().consents;
+        assert_eq!(consents.len(), 1);
+        assert_eq!(consents[0].app, label);
+        assert_eq!(consents[0].scope, SCOPE);
+        assert!(consents[0].created_at <= consents[0].updated_at);
+
+        // revoking the consent logs the user out of the app
+        let tx_revoke = super::revoke_consent::TxRevokeConsent::new(&sessions, &namespaces, &login.cookie, &label);
+        tx_revoke.execute().unwrap();
+        assert!(super::logout::authenticate(&sessions, &login.cookie).is_err());
+
+        // the user is asked again once the consent has been revoked
+        let tx_login = super::login::TxLogin::new(&sessions, &namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        let challenged = tx_login.execute().unwrap();
+        assert!(challenged.cookie.is_empty());
+        assert_eq!(challenged.consent, SCOPE);
+
+        // a refresh token that outlived the consent is not rotated anymore
+        let sess = sessions.get_by_email(&email).unwrap().remove(0);
+        let (mut refresh, refresh_token) = {
+            let sess = sess.lock();
+            crate::models::refresh::RefreshToken::new(None, sess.get_client_id(), app_id, sess.get_cookie(), sess.get_device(), &scope, std::time::SystemTime::now() + timeout)
+        };
+
+        refresh.insert().unwrap();
+        let assertion = client_assertion(&rsa, &label);
+        let tx_grant = super::grant::TxGrant::new(&sessions, &namespaces, GrantParams{grant_type: oauth::GRANT_TYPE_REFRESH, client_id: &label, refresh_token: refresh_token.as_str(), client_assertion_type: oauth::CLIENT_ASSERTION_TYPE_JWT, client_assertion: &assertion, ..Default::default()});
+        let err = crate::error::Error::classify(tx_grant.execute().unwrap_err());
+        assert_eq!(oauth::error_code(&err), oauth::INVALID_GRANT);
+        assert!(crate::models::refresh::find_by_token(&refresh_token).is_err());
+
+        // Deleting the user and the app
+        let tx_delete = delete_user::TxDelete::new(&sessions, &namespaces, &email, DUMMY_PWD, &DUMMY_META);
+        tx_delete.execute().unwrap();
+
+        let app = app::find_by_label(&label).unwrap();
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).unwrap();
+        secret.delete().unwrap();
+        app.delete().unwrap();
+    }
+
+    #[test]
+    fn sweep() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::{Duration, SystemTime};
+        use app::Ctrl as AppCtrl;
+        use crate::models::user::Ctrl as UserCtrl;
+        use crate::models::ticket::Ctrl as TicketCtrl;
+        use crate::schema::secrets;
+        use crate::diesel::prelude::*;
+        crate::initialize();
+        const PREFIX: &str = "sweep";
+
+        // any session gets expired as soon as it is opened
+        let store = Box::new(session::memory::MemoryStore::new());
+        let sessions: Arc<dyn session::Factory> = Arc::new(session::Provider::new(Duration::from_secs(0), Duration::from_secs(0), store));
+        let namespaces: Arc<dyn namesp::Factory> = Arc::new(namesp::Provider::new());
+
+        // Setting up the required clients
+        let (user_name, email) = get_prefixed_data(PREFIX, false);
+        let (app_name, url) = get_prefixed_data(PREFIX, true);
+        let tx_signup = signup::TxSignup::new(&user_name, &email, DUMMY_PWD);
+        tx_signup.execute().unwrap();
+
+        // Generate a keypair
+        let rsa = Rsa::generate(2048).unwrap();
+        let rsa = PKey::from_rsa(rsa).unwrap();
+        let public = rsa.public_key_to_pem().unwrap();
+        
+        let mut signer = Signer::new(MessageDigest::sha256(), &rsa).unwrap();
+        signer.update(app_name.as_bytes()).unwrap();
+        signer.update(url.as_bytes()).unwrap();
+        signer.update(DUMMY_DESCR.as_bytes()).unwrap();
+        signer.update(&public).unwrap();
+        
+        let firm = signer.sign_to_vec().unwrap();
+    
+        // Register app
+        let tx_register = register::TxRegister::new(&app_name, &url, DUMMY_DESCR, &public, false, &[], &firm);
+        let resp = tx_register.execute().unwrap();
+
+        // Decrypt the label
+        let mut decrypter = Decrypter::new(&rsa).unwrap();
+        decrypter.set_rsa_padding(Padding::PKCS1).unwrap();
+        let buffer_len = decrypter.decrypt_len(&resp.label).unwrap();
+        let mut decrypted = vec![0; buffer_len];
+        let decrypted_len = decrypter.decrypt(&resp.label, &mut decrypted).unwrap();
+        decrypted.truncate(decrypted_len);
+        let label = String::from_utf8(decrypted).unwrap();
+        let app = app::find_by_label(&label).unwrap();
+
+        let tx_login = super::login::TxLogin::new(&*sessions, &*namespaces, LoginParams{ident: &email, pwd: DUMMY_PWD, app: &label, device: DUMMY_DEVICE, ..Default::default()}, &DUMMY_META);
+        tx_login.execute().unwrap();
+
+        // a ticket past its deadline can never be resolved
+        let client_id = user::find_by_name(&user_name).unwrap().get_client_id();
+        let (mut expired, _) = ticket::Ticket::new(client_id, crate::proto::TicketKind::VerifyEmail, Duration::from_secs(0)).unwrap();
+        expired.insert().unwrap();
+        thread::sleep(Duration::from_millis(10));
+
+        let tx_sweep = super::sweep::TxSweep::new(&*sessions, &*namespaces);
+        let reaped = tx_sweep.execute().unwrap();
+        assert_eq!(reaped.sessions, 1);
+        assert_eq!(reaped.dirs, 1);
+        assert!(reaped.tickets >= 1);
+        assert!(sessions.get_by_email(&email).unwrap().is_empty());
+        assert!(ticket::find_by_id(expired.get_id()).is_err());
+
+        let np = namespaces.get_by_label(&label).unwrap();
+        assert_eq!(np.lock().get_dirs_iter().count(), 0);
+
+        // the secret's deadline cannot be set in the past, so it has to be waited for
+        { // block is required because of connection release
+            let connection = crate::postgres::open_stream().get().unwrap();
+            let deadline = SystemTime::now() + Duration::from_secs(1);
+            diesel::update(secrets::table.filter(secrets::client_id.eq(app.get_client_id())))
+                .set(secrets::deadline.eq(Some(deadline)))
+                .execute(&connection).unwrap();
+        }
+
+        tx_login.execute().unwrap();
+        thread::sleep(Duration::from_millis(1500));
+
+        let reaped = tx_sweep.execute().unwrap();
+        assert!(reaped.secrets >= 1);
+        assert!(namespaces.get_by_label(&label).is_none());
+        assert!(secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME).is_err());
+
+        // Deleting the app and the user
+        app.delete().unwrap();
+        user::find_by_name(&user_name).unwrap().delete().unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use crate::regex::*;
+use crate::time;
+use crate::proto::TicketKind;
+use crate::throttle;
+use crate::models::{user, ticket, session};
+use crate::error;
+use super::login;
+
+// Proto message structs
+use crate::proto::user_proto::NonceResponse;
+
+const ERR_IDENT_NOT_MATCH: &str = "The provided indentity is not of the expected type";
+
+pub struct TxNonce<'a> {
+    ident: &'a str,
+    meta: &'a session::Metadata,
+}
+
+impl<'a> TxNonce<'a> {
+    pub fn new(ident: &'a str, meta: &'a session::Metadata) -> Self {
+        TxNonce{
+            ident,
+            meta,
+        }
+    }
+
+    fn find_user_by_identity(&self) -> Result<Box<dyn user::Ctrl>, Box<dyn Error>> {
+        if match_name(self.ident).is_ok() {
+            let ctrl = user::find_by_name(self.ident)?;
+            return Ok(ctrl);
+        } else if match_email(self.ident).is_ok() {
+            let ctrl = user::find_by_email(self.ident)?;
+            return Ok(ctrl);
+        }
+        
+        Err(error::Error::invalid("ident", ERR_IDENT_NOT_MATCH).into())
+    }
+
+    pub fn execute(&self) -> Result<NonceResponse, Box<dyn Error>> {
+        println!("Got a Nonce request for user {} ", self.ident);
+
+        // whoever cannot log in right now has no reason to get a nonce either
+        let throttle = throttle::get_instance();
+        let nonces = throttle::get_nonce_instance();
+        let keys = login::throttle_keys(self.ident, self.meta);
+        for key in keys.iter() {
+            throttle.check(key)?;
+            nonces.check(key)?;
+        }
+
+        // any nonce costs a stored ticket, decoys aside, so all of them count no matter the identity exists or not
+        for key in keys.iter() {
+            nonces.fail(key);
+        }
+
+        // unknown identities get a nonce as well, so no one can tell whether they exist or not
+        let user = match self.find_user_by_identity() {
+            Ok(user) => user,
+            Err(err) => match error::Error::classify(err) {
+                error::Error::NotFound(_) => {
+                    let (nonce, deadline) = login::decoy_challenge(TicketKind::Signature)?;
+                    return Ok(NonceResponse{
+                        nonce,
+                        deadline: time::unix_seconds(deadline)? as i64,
+                    });
+                },
+
+                err => return Err(err.into()),
+            },
+        };
+
+        // the nonce is just a challenge to be completed by signature; a new one replaces any other, so no user can pile them up
+        ticket::delete_by_client_and_kind(user.get_client_id(), TicketKind::Signature)?;
+        let (nonce, deadline) = login::issue_challenge(user.get_client_id(), TicketKind::Signature)?;
+        Ok(NonceResponse{
+            nonce,
+            deadline: time::unix_seconds(deadline)? as i64,
+        })
+    }
+}
@@ -5,31 +5,41 @@ use crate::models::secret::Ctrl as SecretCtrl;
 use crate::models::Gateway;
 use crate::proto::app_proto::RegisterResponse;
 use crate::default;
+use crate::oauth;
+use crate::error;
 
 const ERR_SIGNATURE_HAS_FAILED: &str = "Signature verifier has failed";
+const ERR_SCOPE_NOT_SUPPORTED: &str = "Any of the scopes is not supported";
 
 pub struct TxRegister<'a> {
     name: &'a str,
     url: &'a str,
     descr: &'a str,
     public: &'a [u8],
+    reauth: bool,
+    scope: &'a [String],
     firm: &'a [u8],
 }
 
 impl<'a> TxRegister<'a> {
-    pub fn new(name: &'a str, url: &'a str, descr: &'a str, public:&'a [u8], firm: &'a [u8]) -> Self {
+    pub fn new(name: &'a str, url: &'a str, descr: &'a str, public:&'a [u8], reauth: bool, scope: &'a [String], firm: &'a [u8]) -> Self {
         TxRegister{
-            name: name,
-            url: url,
-            descr: descr,
-            public: public,
-            firm: firm,
+            name,
+            url,
+            descr,
+            public,
+            reauth,
+            scope,
+            firm,
         }
     }
 
     pub fn execute(&self) -> Result<RegisterResponse, Box<dyn Error>> {
         println!("Got a Register request for application {} ", self.name);
 
+        let scope = oauth::parse_scope(&self.scope.join(" "))
+            .map_err(|_| error::Error::invalid("scope", ERR_SCOPE_NOT_SUPPORTED))?;
+
         let mut app = app::App::new(self.name, self.url, self.descr)?;
         let aux_secret = secret::Secret::new(0, default::RSA_NAME, self.public)?;
         let mut verifier = aux_secret.get_verifier()?;
@@ -37,11 +47,22 @@ impl<'a> TxRegister<'a> {
         verifier.update(self.url.as_bytes())?;
         verifier.update(self.descr.as_bytes())?;
         verifier.update(self.public)?;
+        if self.reauth {
+            // only signed when set, so the signature of former clients still matches
+            verifier.update(&[1])?;
+        }
+
+        for scope in self.scope {
+            // none is signed when there are no scopes, so the signature of former clients still matches
+            verifier.update(scope.as_bytes())?;
+        }
 
         if !verifier.verify(self.firm)? {
-            return Err(ERR_SIGNATURE_HAS_FAILED.into());
+            return Err(error::Error::Unauthenticated(ERR_SIGNATURE_HAS_FAILED.into()).into());
         }
-        
+
+        app.set_force_reauth(self.reauth);
+        app.set_scope(&scope);
         app.insert()?;
         
         let mut secret = secret::Secret::new(app.get_client_id(), default::RSA_NAME, self.public)?;
@@ -0,0 +1,59 @@
+use std::error::Error;
+use crate::cookie;
+use crate::models::{session, secret, Gateway};
+use crate::models::secret::Ctrl as SecretCtrl;
+use crate::regex::*;
+use crate::default;
+use crate::error;
+use super::logout;
+
+const ERR_SIGNATURE_HAS_FAILED: &str = "Signature verifier has failed";
+
+pub struct TxRegisterKey<'a> {
+    sessions: &'a dyn session::Factory,
+    cookie: &'a str,
+    name: &'a str,
+    public: &'a [u8],
+    firm: &'a [u8],
+    pwd: &'a str,
+    meta: &'a session::Metadata,
+}
+
+impl<'a> TxRegisterKey<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, cookie: &'a str, name: &'a str, public: &'a [u8], firm: &'a [u8], pwd: &'a str, meta: &'a session::Metadata) -> Self {
+        TxRegisterKey{
+            sessions,
+            cookie,
+            name,
+            public,
+            firm,
+            pwd,
+            meta,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a RegisterKey request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        match_name(self.name)?;
+        let client_id = logout::reauthenticate(self.sessions, self.cookie, self.pwd, self.meta)?
+            .lock()
+            .get_client_id();
+
+        // users' keys are kept apart from any other secret of theirs
+        let name = format!("{}{}", default::KEY_PREFIX, self.name);
+        let mut secret = secret::Secret::new(client_id, &name, self.public)?;
+
+        { // block is required because of verifier's borrow release
+            // the user must prove to hold the private key
+            let mut verifier = secret.get_verifier()?;
+            verifier.update(self.name.as_bytes())?;
+            verifier.update(self.public)?;
+            if !verifier.verify(self.firm)? {
+                return Err(error::Error::Unauthenticated(ERR_SIGNATURE_HAS_FAILED.into()).into());
+            }
+        }
+
+        secret.insert()
+    }
+}
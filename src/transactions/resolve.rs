@@ -1,21 +1,102 @@
 use std::error::Error;
+use crate::proto::TicketKind;
+use crate::models::{user, ticket, session, namesp, secret, enums, Gateway};
+use crate::models::user::Ctrl as UserCtrl;
+use crate::models::ticket::Ctrl as TicketCtrl;
+use super::{logout, login};
+use crate::error;
+use crate::throttle;
+use crate::regex::match_pwd;
+use crate::default;
+
+const ERR_SECRET_NOT_MATCH: &str = "The provided secret does not match";
+const ERR_KIND_NOT_RESOLVABLE: &str = "The ticket can only be resolved by logging in";
+const ERR_APP_NOT_BOUND: &str = "The ticket is not bound to any application";
 
 pub struct TxResolve<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
     id: &'a str,
-    data: &'a str
+    data: &'a str,
+    secret: &'a str,
 }
 
 impl<'a> TxResolve<'a> {
-    pub fn new(id: &'a str, data: &'a str) -> Self {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, id: &'a str, data: &'a str, secret: &'a str) -> Self {
         TxResolve{
-            id: id,
-            data: data
+            sessions,
+            namespaces,
+            id,
+            data,
+            secret,
+        }
+    }
+
+    fn restore_credentials(&self, client_id: i32) -> Result<(), Box<dyn Error>> {
+        let mut user = user::find_by_client_id(client_id)?;
+        user.set_pwd(self.data)?;
+        user.update()?;
+
+        // whoever has been locked out for guessing the old password may log in with the new one
+        let throttle = throttle::get_instance();
+        throttle.reset(&throttle::ident_key(user.get_email()));
+        throttle.reset(&throttle::ident_key(user.get_name()));
+
+        // any key may have been registered by whoever stole the old credentials, as well as any living session opened
+        secret::delete_by_client_and_prefix(client_id, default::KEY_PREFIX)?;
+        for sess in self.sessions.get_by_email(user.get_email())? {
+            logout::close_session(self.sessions, self.namespaces, &mut sess.lock())?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_email(&self, client_id: i32) -> Result<(), Box<dyn Error>> {
+        let mut user = user::find_by_client_id(client_id)?;
+        if user.get_status() == enums::Status::PENDING {
+            // hidden clients must remain as such
+            user.set_status(enums::Status::ACTIVATED);
+            user.update()?;
         }
+
+        Ok(())
+    }
+
+    fn grant_consent(&self, ticket: &dyn ticket::Ctrl) -> Result<(), Box<dyn Error>> {
+        let app_id = ticket.get_app_id()
+            .ok_or_else(|| error::Error::Internal(ERR_APP_NOT_BOUND.into()))?;
+
+        login::grant_consent(ticket.get_client_id(), app_id, &ticket.get_scope())
     }
 
     pub fn execute(&self) -> Result<(), Box<dyn Error>> {
         println!("Got a Resolve request for ticket {} ", self.id);
 
-        Ok(())
+        let mut ticket = ticket::find_by_id(self.id)?;
+        ticket.is_alive()?;
+
+        if !ticket.match_secret(self.secret) {
+            return Err(error::Error::Unauthenticated(ERR_SECRET_NOT_MATCH.into()).into());
+        }
+
+        if ticket.get_kind() == TicketKind::SecondFactor || ticket.get_kind() == TicketKind::Signature || ticket.get_kind() == TicketKind::Consent {
+            return Err(error::Error::PermissionDenied(ERR_KIND_NOT_RESOLVABLE.into()).into());
+        }
+
+        if ticket.get_kind() == TicketKind::RestoreCredentials {
+            // a malformed password must not burn the ticket, so the user can try again with a valid one
+            match_pwd(self.data)?;
+        }
+
+        // the ticket gets consumed before resolving it, so it cannot be used twice
+        ticket.consume();
+        ticket.update()?;
+
+        match ticket.get_kind() {
+            TicketKind::RestoreCredentials => self.restore_credentials(ticket.get_client_id()),
+            TicketKind::VerifyEmail => self.verify_email(ticket.get_client_id()),
+            TicketKind::GrantConsent => self.grant_consent(&*ticket),
+            TicketKind::SecondFactor | TicketKind::Signature | TicketKind::Consent => unreachable!(),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use crate::models::{session, namesp, consent, app, refresh, Gateway};
+use crate::models::app::Ctrl as AppCtrl;
+use crate::error;
+use super::logout;
+
+const ERR_CONSENT_NOT_FOUND: &str = "The user has not consented to the provided application";
+
+pub struct TxRevokeConsent<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    cookie: &'a str,
+    label: &'a str,
+}
+
+impl<'a> TxRevokeConsent<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, cookie: &'a str, label: &'a str) -> Self {
+        TxRevokeConsent{
+            sessions,
+            namespaces,
+            cookie,
+            label,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a RevokeConsent request for app {} ", self.label);
+
+        let (client_id, email) = { // block is required because of session's lock release
+            let current = logout::authenticate(self.sessions, self.cookie)?;
+            let current = current.lock();
+            (current.get_client_id(), current.get_email().to_string())
+        };
+
+        let app = app::find_by_label(self.label)
+            .map_err(|_| error::Error::NotFound(ERR_CONSENT_NOT_FOUND.into()))?;
+
+        let consent = consent::find_by_client_and_app(client_id, app.get_id())
+            .map_err(|_| error::Error::NotFound(ERR_CONSENT_NOT_FOUND.into()))?;
+
+        consent.delete()?;
+        // the app must not keep on acting on behalf of the user with whatever it was granted before
+        refresh::delete_by_client(client_id, app.get_id())?;
+        for sess in self.sessions.get_by_email(&email)? {
+            // a session must always be locked before any namespace
+            let mut sess = sess.lock();
+            if let Some(token) = sess.get_token(app.get_id()).cloned() {
+                self.sessions.delete_directory(&mut sess, &token)?;
+                if let Some(np) = self.namespaces.get_by_id(app.get_id()) {
+                    np.lock().delete_token(sess.get_cookie());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use crate::cookie;
+use crate::models::{session, namesp, refresh};
+use crate::proto::Status;
+use super::logout;
+
+pub struct TxRevokeOthers<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    cookie: &'a str,
+}
+
+impl<'a> TxRevokeOthers<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, cookie: &'a str) -> Self {
+        TxRevokeOthers{
+            sessions,
+            namespaces,
+            cookie,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a RevokeOtherSessions request for cookie {} ", cookie::fingerprint(self.cookie));
+
+        let (cookie, email) = { // block is required because of session's lock release
+            let current = logout::authenticate(self.sessions, self.cookie)?;
+            let current = current.lock();
+            (current.get_cookie().clone(), current.get_email().to_string())
+        };
+
+        for sess in self.sessions.get_by_email(&email)? {
+            // foreach user's session but the current one
+            let mut sess = sess.lock();
+            if *sess.get_cookie() == cookie || sess.get_status() == Status::Dead {
+                continue;
+            }
+
+            // no application may open the session back with a refresh token
+            refresh::delete_by_cookie(sess.get_cookie(), None)?;
+            logout::close_session(self.sessions, self.namespaces, &mut sess)?;
+        }
+
+        Ok(())
+    }
+}
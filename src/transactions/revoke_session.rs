@@ -0,0 +1,49 @@
+use std::error::Error;
+use crate::models::{session, namesp, refresh};
+use crate::proto::Status;
+use crate::error;
+use super::logout;
+
+const ERR_SESSION_NOT_FOUND: &str = "The user has no session with the provided id";
+
+pub struct TxRevokeSession<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+    cookie: &'a str,
+    id: &'a str,
+}
+
+impl<'a> TxRevokeSession<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory, cookie: &'a str, id: &'a str) -> Self {
+        TxRevokeSession{
+            sessions,
+            namespaces,
+            cookie,
+            id,
+        }
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a RevokeSession request for session {} ", self.id);
+
+        let email = { // block is required because of session's lock release
+            let current = logout::authenticate(self.sessions, self.cookie)?;
+            let email = current.lock().get_email().to_string();
+            email
+        };
+
+        for sess in self.sessions.get_by_email(&email)? {
+            // only the sessions of the very same user can be revoked
+            let mut sess = sess.lock();
+            if sess.get_cookie().fingerprint() != self.id || sess.get_status() == Status::Dead {
+                continue;
+            }
+
+            // no application may open the session back with a refresh token
+            refresh::delete_by_cookie(sess.get_cookie(), None)?;
+            return logout::close_session(self.sessions, self.namespaces, &mut sess);
+        }
+
+        Err(error::Error::NotFound(ERR_SESSION_NOT_FOUND.into()).into())
+    }
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use crate::models::{app, secret, redirect};
+use crate::models::app::Ctrl as AppCtrl;
+use crate::models::secret::Ctrl as SecretCtrl;
+use crate::default;
+use crate::oauth;
+use crate::error;
+
+const ERR_SIGNATURE_HAS_FAILED: &str = "Signature verifier has failed";
+
+pub struct TxSetRedirects<'a> {
+    label: &'a str,
+    uris: &'a [String],
+    dust: &'a [u8],
+    firm: &'a [u8],
+}
+
+impl<'a> TxSetRedirects<'a> {
+    pub fn new(label: &'a str, uris: &'a [String], dust: &'a [u8], firm: &'a [u8]) -> Self {
+        TxSetRedirects{
+            label,
+            uris,
+            dust,
+            firm,
+        }
+    }
+
+    fn precondition(&self) -> Result<(), Box<dyn Error>> {
+        for uri in self.uris {
+            oauth::match_redirect_uri(uri)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(&self) -> Result<(), Box<dyn Error>> {
+        println!("Got a Redirects request from app {} ", self.label);
+        self.precondition()?;
+
+        let app = app::find_by_label(self.label)?;
+        let secret = secret::find_by_client_and_name(app.get_client_id(), default::RSA_NAME)?;
+        let mut verifier = secret.get_verifier()?;
+        verifier.update(self.label.as_bytes())?;
+        for uri in self.uris {
+            // each uri is preceded by its length, so no other list of uris can be signed alike
+            verifier.update(&(uri.len() as u32).to_be_bytes())?;
+            verifier.update(uri.as_bytes())?;
+        }
+
+        verifier.update(self.dust)?;
+        if !verifier.verify(self.firm)? {
+            return Err(error::Error::Unauthenticated(ERR_SIGNATURE_HAS_FAILED.into()).into());
+        }
+
+        let mut uris = self.uris.to_vec();
+        uris.sort();
+        uris.dedup();
+        redirect::replace_all(app.get_id(), &uris)
+    }
+}
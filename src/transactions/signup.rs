@@ -1,6 +1,8 @@
 use std::error::Error;
 use crate::models::user;
 use crate::models::Gateway;
+use crate::proto::TicketKind;
+use super::ticket;
 
 pub struct TxSignup<'a> {
     name: &'a str,
@@ -23,7 +25,14 @@ impl<'a> TxSignup<'a> {
         println!("Got Signup request from user {} ", self.email);
         user::User::new(self.name, self.email, self.pwd)?.insert()?;
         println!("User {} successfully registered with email {}", self.name, self.email);
-        
+
+        // the user must prove the email belongs to it
+        let tx_ticket = ticket::TxTicket::new(TicketKind::VerifyEmail as i32, self.email);
+        if let Err(err) = tx_ticket.execute() {
+            // the verification ticket can be requested again at any time
+            println!("Verification ticket for user {} could not be issued: {}", self.name, err);
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,79 @@
+use std::error::Error;
+use crate::models::{session, secret, namesp, authcode, refresh, ticket};
+use crate::throttle;
+use super::logout;
+
+// Reaped counts everything a single sweep has evicted
+#[derive(Default)]
+pub struct Reaped {
+    pub sessions: usize,
+    pub dirs: usize,
+    pub secrets: usize,
+    pub tombstones: usize,
+    pub attempts: usize,
+    pub codes: usize,
+    pub refresh: usize,
+    pub tickets: usize,
+}
+
+impl Reaped {
+    pub fn is_empty(&self) -> bool {
+        self.sessions == 0 && self.dirs == 0 && self.secrets == 0 && self.tombstones == 0 && self.attempts == 0 && self.codes == 0 && self.refresh == 0 && self.tickets == 0
+    }
+}
+
+pub struct TxSweep<'a> {
+    sessions: &'a dyn session::Factory,
+    namespaces: &'a dyn namesp::Factory,
+}
+
+impl<'a> TxSweep<'a> {
+    pub fn new(sessions: &'a dyn session::Factory, namespaces: &'a dyn namesp::Factory) -> Self {
+        TxSweep{
+            sessions,
+            namespaces,
+        }
+    }
+
+    pub fn execute(&self) -> Result<Reaped, Box<dyn Error>> {
+        let mut reaped = Reaped::default();
+        for sess in self.sessions.get_expired()? {
+            let mut sess = sess.lock();
+            let dirs = sess.get_open_dirs().len();
+            match logout::close_session(self.sessions, self.namespaces, &mut sess) {
+                Ok(_) => {
+                    reaped.sessions += 1;
+                    reaped.dirs += dirs;
+                },
+
+                // the session may have been closed by someone else in the meanwhile
+                Err(err) => println!("Sweeper could not close session {}: {}", sess.get_cookie().fingerprint(), err),
+            }
+        }
+
+        reaped.tombstones = self.sessions.purge_dead()?;
+        reaped.attempts = throttle::get_instance().purge() + throttle::get_nonce_instance().purge();
+        reaped.codes = authcode::delete_expired()?;
+        reaped.refresh = refresh::delete_expired()?;
+        reaped.tickets = ticket::delete_spent()?;
+        for client_id in secret::delete_expired()? {
+            reaped.secrets += 1;
+            let np = match self.namespaces.destroy_by_client(client_id) {
+                Ok(np) => np,
+                Err(_) => continue, // the application has no namespace in this replica
+            };
+
+            // the namespace's lock must be released before locking any session
+            let app_id = np.lock().get_id();
+            for (cookie, token) in self.sessions.get_dirs_by_app(app_id)? {
+                if let Some(sess) = self.sessions.get_by_cookie(&cookie)? {
+                    if self.sessions.delete_directory(&mut sess.lock(), &token)?.is_some() {
+                        reaped.dirs += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(reaped)
+    }
+}
@@ -1,9 +1,22 @@
 use std::error::Error;
+use std::time::Duration;
+use crate::regex::*;
+use crate::time;
+use crate::default;
 use crate::proto::TicketKind;
+use crate::models::{user, ticket, enums};
+use crate::models::ticket::Ctrl as TicketCtrl;
+use crate::mailer::{outbox, template};
+use crate::error;
 
 use crate::proto::client_proto;
 use client_proto::TicketResponse;
 
+const ERR_IDENT_NOT_MATCH: &str = "The provided indentity is not of the expected type";
+const ERR_UNKNOWN_KIND: &str = "Unknown ticket kind";
+const ERR_ALREADY_VERIFIED: &str = "The email has already been verified";
+const ERR_KIND_NOT_ALLOWED: &str = "The ticket kind cannot be requested";
+
 pub struct TxTicket<'a> {
     kind: i32,
     ident: &'a str
@@ -17,12 +30,53 @@ impl<'a> TxTicket<'a> {
         }
     }
 
+    fn find_user_by_identity(&self) -> Result<Box<dyn user::Ctrl>, Box<dyn Error>> {
+        if match_name(self.ident).is_ok() {
+            let ctrl = user::find_by_name(self.ident)?;
+            return Ok(ctrl);
+        } else if match_email(self.ident).is_ok() {
+            let ctrl = user::find_by_email(self.ident)?;
+            return Ok(ctrl);
+        }
+        
+        Err(error::Error::invalid("ident", ERR_IDENT_NOT_MATCH).into())
+    }
+
     pub fn execute(&self) -> Result<TicketResponse, Box<dyn Error>> {
-        println!("Got a Ticket request for application {} ", self.ident);
+        println!("Got a Ticket request for client {} ", self.ident);
+
+        let kind = match TicketKind::from_i32(self.kind) {
+            Some(kind) => kind,
+            None => return Err(error::Error::invalid("kind", ERR_UNKNOWN_KIND).into()),
+        };
+
+        if kind == TicketKind::SecondFactor || kind == TicketKind::Signature || kind == TicketKind::Consent || kind == TicketKind::GrantConsent {
+            // challenges are issued along the login only, and consents along the login or the authorization
+            return Err(error::Error::invalid("kind", ERR_KIND_NOT_ALLOWED).into());
+        }
+
+        let user = self.find_user_by_identity()?;
+        if kind == TicketKind::VerifyEmail && user.get_status() != enums::Status::PENDING {
+            return Err(error::Error::AlreadyExists(ERR_ALREADY_VERIFIED.into()).into());
+        }
+
+        let timeout = Duration::new(default::TICKET_TIMEOUT, 0);
+        let (mut ticket, secret) = ticket::Ticket::new(user.get_client_id(), kind, timeout)?;
+
+        // the secret must reach the user through its email, never through this response
+        let msg = match kind {
+            TicketKind::RestoreCredentials => template::restore_credentials(user.get_email(), user.get_name(), ticket.get_id(), secret.as_str()),
+            TicketKind::VerifyEmail => template::verify_email(user.get_email(), user.get_name(), ticket.get_id(), secret.as_str()),
+            TicketKind::SecondFactor | TicketKind::Signature | TicketKind::Consent | TicketKind::GrantConsent => unreachable!(),
+        };
+
+        // a ticket whose mail is not queued could never be resolved, nor a queued mail whose ticket does not exist; the
+        // mail is sent by the outbox worker, so the request does not wait for the mail server
+        ticket.insert_along(|connection| outbox::enqueue_on(connection, &msg))?;
 
         Ok(TicketResponse{
-            id: "".to_string(),
-            deadline: 0
+            id: ticket.get_id().to_string(),
+            deadline: time::unix_seconds(ticket.get_deadline())? as i64,
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use crate::models::{user, enums};
+use crate::models::user::Ctrl as UserCtrl;
+use crate::jwt;
+use crate::oauth;
+use crate::error;
+
+// Proto message structs
+use crate::proto::app_proto::UserInfoResponse;
+
+const ERR_TOKEN_NOT_VALID: &str = "The access token is not valid";
+const ERR_SCOPE_REQUIRED: &str = "The access token has not been granted the openid scope";
+
+fn invalid_token() -> Box<dyn Error> {
+    error::Error::OAuth(oauth::INVALID_TOKEN, ERR_TOKEN_NOT_VALID.into()).into()
+}
+
+pub struct TxUserInfo<'a> {
+    access_token: &'a str,
+}
+
+impl<'a> TxUserInfo<'a> {
+    pub fn new(access_token: &'a str) -> Self {
+        TxUserInfo{
+            access_token,
+        }
+    }
+
+    pub fn execute(&self) -> Result<UserInfoResponse, Box<dyn Error>> {
+        let claims = jwt::get_instance().decode(self.access_token)
+            .map_err(|_| invalid_token())?;
+
+        println!("Got a UserInfo request from app {} ", claims.aud);
+        if !claims.scope.iter().any(|item| item == oauth::SCOPE_OPENID) {
+            return Err(error::Error::OAuth(oauth::INSUFFICIENT_SCOPE, ERR_SCOPE_REQUIRED.into()).into());
+        }
+
+        let user_id = claims.sub.parse().map_err(|_| invalid_token())?;
+        let user = user::find_by_id(user_id).map_err(|_| invalid_token())?;
+        if user.get_status() == enums::Status::HIDDEN {
+            return Err(invalid_token());
+        }
+
+        let mut resp = UserInfoResponse{
+            sub: claims.sub.clone(),
+            ..Default::default()
+        };
+
+        if claims.scope.iter().any(|item| item == oauth::SCOPE_PROFILE) {
+            resp.name = user.get_name().to_string();
+            resp.preferred_username = user.get_name().to_string();
+        }
+
+        if claims.scope.iter().any(|item| item == oauth::SCOPE_EMAIL) {
+            resp.email = user.get_email().to_string();
+            resp.email_verified = user.get_status() == enums::Status::ACTIVATED;
+        }
+
+        Ok(resp)
+    }
+}